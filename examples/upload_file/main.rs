@@ -18,10 +18,14 @@ fn main() -> anyhow::Result<()> {
             .file_name(file_name.to_string_lossy())
             .upload_progress_callback(Box::new(|progress: &UploadProgressInfo| {
                 println!(
-                    "Upload progress: upload id: {}, part number: {}, uploaded: {}",
+                    "Upload progress: phase: {:?}, upload id: {:?}, uploaded: {}/{:?}, speed: {} B/s, eta: {:?}, retries: {}",
+                    progress.phase(),
                     progress.upload_id(),
-                    progress.part_number(),
-                    progress.uploaded()
+                    progress.uploaded(),
+                    progress.total_size(),
+                    progress.bytes_per_second(),
+                    progress.eta(),
+                    progress.retries(),
                 );
                 Ok(())
             }))
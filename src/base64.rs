@@ -7,13 +7,13 @@ pub(super) fn urlsafe_encode(data: &[u8]) -> String {
 }
 
 #[inline]
-pub(super) fn urlsafe_encode_buf(data: &[u8], mut encoded: &mut String) {
-    base64::encode_config_buf(data, base64::URL_SAFE, &mut encoded)
+pub(super) fn urlsafe_encode_buf(data: &[u8], encoded: &mut String) {
+    base64::encode_config_buf(data, base64::URL_SAFE, encoded)
 }
 
 #[inline]
-pub(super) fn urlsafe_encode_slice(data: &[u8], mut encoded: &mut [u8]) -> usize {
-    base64::encode_config_slice(data, base64::URL_SAFE, &mut encoded)
+pub(super) fn urlsafe_encode_slice(data: &[u8], encoded: &mut [u8]) -> usize {
+    base64::encode_config_slice(data, base64::URL_SAFE, encoded)
 }
 
 #[inline]
@@ -22,17 +22,11 @@ pub(super) fn urlsafe_decode(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
 }
 
 #[inline]
-pub(super) fn urlsafe_decode_buf(
-    data: &[u8],
-    mut decoded: &mut Vec<u8>,
-) -> Result<(), DecodeError> {
-    base64::decode_config_buf(data, base64::URL_SAFE, &mut decoded)
+pub(super) fn urlsafe_decode_buf(data: &[u8], decoded: &mut Vec<u8>) -> Result<(), DecodeError> {
+    base64::decode_config_buf(data, base64::URL_SAFE, decoded)
 }
 
 #[inline]
-pub(super) fn urlsafe_decode_slice(
-    data: &[u8],
-    mut decoded: &mut [u8],
-) -> Result<usize, DecodeError> {
-    base64::decode_config_slice(data, base64::URL_SAFE, &mut decoded)
+pub(super) fn urlsafe_decode_slice(data: &[u8], decoded: &mut [u8]) -> Result<usize, DecodeError> {
+    base64::decode_config_slice(data, base64::URL_SAFE, decoded)
 }
@@ -0,0 +1,708 @@
+use super::{
+    cancellation::CancellationToken,
+    error::{HttpCallError, HttpCallResult},
+    uploader::{UploadResult, Uploader},
+};
+use log::warn;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{
+            AtomicU64, AtomicUsize,
+            Ordering::{Relaxed, SeqCst},
+        },
+        mpsc::{channel, sync_channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{spawn, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// 批量上传任务
+#[derive(Debug, Clone)]
+pub struct BatchUploadJob {
+    source: BatchUploadSource,
+    object_name: Option<String>,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+    custom_vars: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone)]
+enum BatchUploadSource {
+    Path(PathBuf),
+    Data(Vec<u8>),
+}
+
+impl BatchUploadJob {
+    /// 创建上传文件的任务
+    #[inline]
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        Self::new(BatchUploadSource::Path(path.into()))
+    }
+
+    /// 创建上传内存数据的任务
+    #[inline]
+    pub fn bytes(data: impl Into<Vec<u8>>) -> Self {
+        Self::new(BatchUploadSource::Data(data.into()))
+    }
+
+    #[inline]
+    fn new(source: BatchUploadSource) -> Self {
+        Self {
+            source,
+            object_name: None,
+            file_name: None,
+            mime_type: None,
+            metadata: None,
+            custom_vars: None,
+        }
+    }
+
+    /// 设置对象名称
+    #[inline]
+    pub fn object_name(mut self, object_name: impl Into<String>) -> Self {
+        self.object_name = Some(object_name.into());
+        self
+    }
+
+    /// 设置原始文件名
+    #[inline]
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// 设置文件 MIME 类型
+    #[inline]
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// 设置自定义元数据
+    #[inline]
+    pub fn metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// 设置自定义变量
+    #[inline]
+    pub fn custom_vars(mut self, custom_vars: HashMap<String, String>) -> Self {
+        self.custom_vars = Some(custom_vars);
+        self
+    }
+
+    /// 获取对象名称
+    #[inline]
+    pub(super) fn key(&self) -> Option<&str> {
+        self.object_name.as_deref()
+    }
+
+    /// 获取上传文件的路径，上传内存数据的任务返回 None
+    #[inline]
+    pub(super) fn file_path(&self) -> Option<&Path> {
+        match &self.source {
+            BatchUploadSource::Path(path) => Some(path),
+            BatchUploadSource::Data(_) => None,
+        }
+    }
+}
+
+/// 批量上传进度回调函数
+pub type BatchUploadProgressCallback =
+    Box<dyn Fn(&BatchUploadProgressInfo) + Send + Sync + 'static>;
+
+/// 批量上传进度信息
+#[derive(Debug, Clone)]
+pub struct BatchUploadProgressInfo {
+    succeeded: usize,
+    failed: usize,
+    skipped: usize,
+    uploaded_bytes: u64,
+}
+
+impl BatchUploadProgressInfo {
+    /// 获取上传成功的任务数量
+    #[inline]
+    pub fn succeeded(&self) -> usize {
+        self.succeeded
+    }
+
+    /// 获取上传失败的任务数量
+    #[inline]
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// 获取被跳过的任务数量
+    #[inline]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// 获取已经结束的任务数量
+    #[inline]
+    pub fn finished(&self) -> usize {
+        self.succeeded + self.failed + self.skipped
+    }
+
+    /// 获取所有任务已经上传的数据量，包括正在上传的任务
+    #[inline]
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes
+    }
+}
+
+/// 批量上传钩子，在每个任务上传前后被工作线程调用
+pub(super) trait BatchUploadHook: Send + Sync {
+    /// 任务开始上传前调用，返回 false 表示跳过该任务，返回错误表示该任务失败
+    fn before_upload(&self, index: usize, job: &BatchUploadJob) -> HttpCallResult<bool>;
+
+    /// 任务上传结束后调用，被跳过的任务不会调用
+    fn after_upload(&self, index: usize, outcome: &BatchUploadOutcome);
+
+    /// 所有任务结束后调用
+    fn finish(&self);
+}
+
+/// 批量上传器
+///
+/// 使用固定数量的工作线程共享同一个上传器执行批量上传任务
+pub struct BatchUploader {
+    uploader: Uploader,
+    concurrency: usize,
+    progress_callback: Option<BatchUploadProgressCallback>,
+    cancellation_token: Option<CancellationToken>,
+    hook: Option<Arc<dyn BatchUploadHook>>,
+}
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+impl BatchUploader {
+    /// 创建批量上传器
+    #[inline]
+    pub fn new(uploader: Uploader) -> Self {
+        Self {
+            uploader,
+            concurrency: DEFAULT_CONCURRENCY,
+            progress_callback: None,
+            cancellation_token: None,
+            hook: None,
+        }
+    }
+
+    /// 设置工作线程数量，默认为 4，至少为 1
+    #[inline]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// 设置批量上传进度回调函数
+    ///
+    /// 回调函数将在任意任务汇报上传进度或结束时被调用，可能被多个工作线程同时调用
+    #[inline]
+    pub fn progress_callback(mut self, progress_callback: BatchUploadProgressCallback) -> Self {
+        self.progress_callback = Some(progress_callback);
+        self
+    }
+
+    /// 设置取消令牌
+    ///
+    /// 令牌被取消后，正在上传的任务将以 [`HttpCallError::Cancelled`] 错误失败，尚未开始的任务将被跳过。
+    /// 在所有任务结束前丢弃 [`BatchUploadResults`] 也将取消该令牌
+    #[inline]
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    #[inline]
+    pub(super) fn hook(mut self, hook: Arc<dyn BatchUploadHook>) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    /// 开始批量上传，返回每个任务的上传结果的迭代器
+    ///
+    /// 上传结果按照任务结束的顺序返回，与任务的提交顺序无关
+    pub fn start<I>(self, jobs: I) -> BatchUploadResults
+    where
+        I: IntoIterator<Item = BatchUploadJob>,
+        I::IntoIter: Send + 'static,
+    {
+        let started_at = Instant::now();
+        let context = Arc::new(BatchContext {
+            uploader: self.uploader,
+            progress_callback: self.progress_callback,
+            cancellation_token: self.cancellation_token.unwrap_or_default(),
+            hook: self.hook,
+            succeeded: Default::default(),
+            failed: Default::default(),
+            skipped: Default::default(),
+            uploaded_bytes: Default::default(),
+            transferring: Default::default(),
+        });
+        let (jobs_tx, jobs_rx) = sync_channel(self.concurrency);
+        let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+        let (results_tx, results_rx) = channel();
+        let mut threads = Vec::with_capacity(self.concurrency + 1);
+        {
+            let jobs = jobs.into_iter();
+            threads.push(spawn(move || {
+                for job in jobs.enumerate() {
+                    if jobs_tx.send(job).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        for _ in 0..self.concurrency {
+            let context = context.to_owned();
+            let jobs_rx = jobs_rx.to_owned();
+            let results_tx = results_tx.to_owned();
+            threads.push(spawn(move || loop {
+                let job = jobs_rx.lock().unwrap().recv();
+                let delivered = match job {
+                    Ok((index, job)) => context.run(index, job, &results_tx),
+                    Err(_) => false,
+                };
+                // 结果迭代器已被丢弃时退出，所有工作线程退出后任务分发线程也将随之退出
+                if !delivered {
+                    break;
+                }
+            }));
+        }
+        BatchUploadResults {
+            context,
+            results_rx,
+            threads,
+            started_at,
+            finished_at: None,
+        }
+    }
+}
+
+impl fmt::Debug for BatchUploader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchUploader")
+            .field("uploader", &self.uploader)
+            .field("concurrency", &self.concurrency)
+            .field("cancellation_token", &self.cancellation_token)
+            .finish()
+    }
+}
+
+struct BatchContext {
+    uploader: Uploader,
+    progress_callback: Option<BatchUploadProgressCallback>,
+    cancellation_token: CancellationToken,
+    hook: Option<Arc<dyn BatchUploadHook>>,
+    succeeded: AtomicUsize,
+    failed: AtomicUsize,
+    skipped: AtomicUsize,
+    uploaded_bytes: AtomicU64,
+    transferring: Mutex<HashMap<usize, u64>>,
+}
+
+impl BatchContext {
+    fn run(
+        self: &Arc<Self>,
+        index: usize,
+        job: BatchUploadJob,
+        results_tx: &Sender<BatchUploadJobResult>,
+    ) -> bool {
+        let object_name = job.object_name.to_owned();
+        let outcome = match self.before_upload(index, &job) {
+            Ok(true) => {
+                let result = self.upload(index, job);
+                // 以上传进度中最终汇报的数据量为准，文件在上传前无法读取元信息或在上传时发生变化也能正确统计
+                let uploaded = self
+                    .transferring
+                    .lock()
+                    .unwrap()
+                    .remove(&index)
+                    .unwrap_or_default();
+                match result {
+                    Ok(result) => {
+                        self.uploaded_bytes.fetch_add(uploaded, SeqCst);
+                        self.succeeded.fetch_add(1, SeqCst);
+                        BatchUploadOutcome::Succeeded(result)
+                    }
+                    Err(err) => self.failed(index, err),
+                }
+            }
+            Ok(false) => {
+                self.skipped.fetch_add(1, SeqCst);
+                BatchUploadOutcome::Skipped
+            }
+            Err(err) => self.failed(index, err),
+        };
+        if let (Some(hook), false) = (&self.hook, matches!(outcome, BatchUploadOutcome::Skipped)) {
+            hook.after_upload(index, &outcome);
+        }
+        self.report();
+        results_tx
+            .send(BatchUploadJobResult {
+                index,
+                object_name,
+                outcome,
+            })
+            .is_ok()
+    }
+
+    fn before_upload(&self, index: usize, job: &BatchUploadJob) -> HttpCallResult<bool> {
+        if self.cancellation_token.is_cancelled() {
+            return Ok(false);
+        }
+        match &self.hook {
+            Some(hook) => hook.before_upload(index, job),
+            None => Ok(true),
+        }
+    }
+
+    #[inline]
+    fn failed(&self, index: usize, err: HttpCallError) -> BatchUploadOutcome {
+        warn!("batch upload job {} failed: {}", index, err);
+        self.failed.fetch_add(1, SeqCst);
+        BatchUploadOutcome::Failed(err)
+    }
+
+    fn upload(self: &Arc<Self>, index: usize, job: BatchUploadJob) -> HttpCallResult<UploadResult> {
+        let mut builder = match job.source {
+            BatchUploadSource::Path(path) => self.uploader.upload_path(path)?,
+            BatchUploadSource::Data(data) => self.uploader.upload_bytes(data),
+        };
+        if let Some(object_name) = job.object_name {
+            builder = builder.object_name(object_name);
+        }
+        if let Some(file_name) = job.file_name {
+            builder = builder.file_name(file_name);
+        }
+        if let Some(mime_type) = job.mime_type {
+            builder = builder.mime_type(mime_type);
+        }
+        if let Some(metadata) = job.metadata {
+            builder = builder.metadata(metadata);
+        }
+        if let Some(custom_vars) = job.custom_vars {
+            builder = builder.custom_vars(custom_vars);
+        }
+        let context = self.to_owned();
+        builder
+            .cancellation_token(self.cancellation_token.to_owned())
+            .upload_progress_callback(Box::new(move |info| {
+                context
+                    .transferring
+                    .lock()
+                    .unwrap()
+                    .insert(index, info.uploaded());
+                context.report();
+                Ok(())
+            }))
+            .start()
+    }
+
+    fn report(&self) {
+        if let Some(progress_callback) = &self.progress_callback {
+            let transferring: u64 = self.transferring.lock().unwrap().values().sum();
+            progress_callback(&BatchUploadProgressInfo {
+                succeeded: self.succeeded.load(Relaxed),
+                failed: self.failed.load(Relaxed),
+                skipped: self.skipped.load(Relaxed),
+                uploaded_bytes: self
+                    .uploaded_bytes
+                    .load(Relaxed)
+                    .saturating_add(transferring),
+            });
+        }
+    }
+}
+
+/// 批量上传任务的结果
+#[derive(Debug)]
+pub struct BatchUploadJobResult {
+    index: usize,
+    object_name: Option<String>,
+    outcome: BatchUploadOutcome,
+}
+
+impl BatchUploadJobResult {
+    /// 获取任务的提交序号，从 0 开始
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// 获取任务的对象名称
+    #[inline]
+    pub fn object_name(&self) -> Option<&str> {
+        self.object_name.as_deref()
+    }
+
+    /// 获取任务的执行结果
+    #[inline]
+    pub fn outcome(&self) -> &BatchUploadOutcome {
+        &self.outcome
+    }
+
+    /// 获取任务的执行结果
+    #[inline]
+    pub fn into_outcome(self) -> BatchUploadOutcome {
+        self.outcome
+    }
+}
+
+/// 批量上传任务的执行结果
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BatchUploadOutcome {
+    /// 上传成功
+    Succeeded(UploadResult),
+    /// 上传失败
+    Failed(HttpCallError),
+    /// 因批量上传被取消，或同步上传时文件未发生变化而跳过
+    Skipped,
+}
+
+/// 批量上传结果迭代器
+///
+/// 迭代器按任务结束的顺序返回每个任务的结果，所有任务结束后迭代结束。
+/// 在所有任务结束前丢弃迭代器将取消批量上传，正在上传的任务将被中止，尚未开始的任务将被跳过
+#[derive(Debug)]
+pub struct BatchUploadResults {
+    context: Arc<BatchContext>,
+    results_rx: Receiver<BatchUploadJobResult>,
+    threads: Vec<JoinHandle<()>>,
+    started_at: Instant,
+    finished_at: Option<Instant>,
+}
+
+impl BatchUploadResults {
+    /// 获取当前的批量上传统计信息
+    ///
+    /// 在所有任务结束前调用将返回截至目前的统计信息
+    pub fn summary(&self) -> BatchUploadSummary {
+        BatchUploadSummary {
+            succeeded: self.context.succeeded.load(SeqCst),
+            failed: self.context.failed.load(SeqCst),
+            skipped: self.context.skipped.load(SeqCst),
+            uploaded_bytes: self.context.uploaded_bytes.load(SeqCst),
+            elapsed: self
+                .finished_at
+                .unwrap_or_else(Instant::now)
+                .duration_since(self.started_at),
+        }
+    }
+
+    /// 等待所有任务结束，返回批量上传统计信息
+    pub fn wait(mut self) -> BatchUploadSummary {
+        for _ in &mut self {}
+        self.summary()
+    }
+}
+
+impl Iterator for BatchUploadResults {
+    type Item = BatchUploadJobResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished_at.is_some() {
+            return None;
+        }
+        let result = self.results_rx.recv().ok();
+        if result.is_none() {
+            self.finished_at = Some(Instant::now());
+            for thread in self.threads.drain(..) {
+                thread.join().ok();
+            }
+            if let Some(hook) = &self.context.hook {
+                hook.finish();
+            }
+        }
+        result
+    }
+}
+
+impl Drop for BatchUploadResults {
+    fn drop(&mut self) {
+        if self.finished_at.is_none() {
+            self.context.cancellation_token.cancel();
+        }
+    }
+}
+
+impl fmt::Debug for BatchContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchContext")
+            .field("succeeded", &self.succeeded)
+            .field("failed", &self.failed)
+            .field("skipped", &self.skipped)
+            .field("uploaded_bytes", &self.uploaded_bytes)
+            .finish()
+    }
+}
+
+/// 批量上传统计信息
+#[derive(Debug, Clone)]
+pub struct BatchUploadSummary {
+    succeeded: usize,
+    failed: usize,
+    skipped: usize,
+    uploaded_bytes: u64,
+    elapsed: Duration,
+}
+
+impl BatchUploadSummary {
+    /// 获取上传成功的任务数量
+    #[inline]
+    pub fn succeeded(&self) -> usize {
+        self.succeeded
+    }
+
+    /// 获取上传失败的任务数量
+    #[inline]
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// 获取被跳过的任务数量
+    #[inline]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// 获取上传成功的任务的数据总量
+    #[inline]
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes
+    }
+
+    /// 获取批量上传耗费的时间
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::HttpCallResult,
+        http::{HttpCaller, HttpRequest, HttpResponse},
+        test_utils::{fake_uploader, FakeHttpCaller, FAKE_UP_URL},
+        uploader::UploaderBuilder,
+    };
+    use std::sync::mpsc::Receiver;
+
+    /// 每次调用前都需要获取一个许可的 HTTP 调用器，许可发送端被丢弃后不再阻塞
+    #[derive(Debug)]
+    struct GatedHttpCaller {
+        inner: FakeHttpCaller,
+        permits: Mutex<Receiver<()>>,
+    }
+
+    impl HttpCaller for GatedHttpCaller {
+        fn call(&self, request: HttpRequest) -> HttpCallResult<HttpResponse> {
+            self.permits.lock().unwrap().recv().ok();
+            self.inner.call(request)
+        }
+    }
+
+    #[test]
+    fn test_batch_upload() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let http_caller = Arc::new(FakeHttpCaller::default());
+        let max_uploaded_bytes = Arc::new(AtomicU64::new(0));
+        let jobs = (0..20)
+            .map(|i| BatchUploadJob::bytes(vec![b'a'; 10]).object_name(format!("object-{}", i)))
+            .chain(std::iter::once(
+                BatchUploadJob::path("/not/existed/file").object_name("not-existed"),
+            ))
+            .collect::<Vec<_>>();
+        let mut results = BatchUploader::new(fake_uploader(http_caller.to_owned()))
+            .concurrency(3)
+            .progress_callback({
+                let max_uploaded_bytes = max_uploaded_bytes.to_owned();
+                Box::new(move |info| {
+                    max_uploaded_bytes.fetch_max(info.uploaded_bytes(), SeqCst);
+                })
+            })
+            .start(jobs);
+        let mut indexes = Vec::new();
+        for result in &mut results {
+            indexes.push(result.index());
+            match result.outcome() {
+                BatchUploadOutcome::Succeeded(_) => assert_ne!(result.index(), 20),
+                BatchUploadOutcome::Failed(_) => {
+                    assert_eq!(result.object_name(), Some("not-existed"))
+                }
+                BatchUploadOutcome::Skipped => unreachable!(),
+            }
+        }
+        indexes.sort_unstable();
+        assert_eq!(indexes, (0..21).collect::<Vec<_>>());
+
+        let summary = results.summary();
+        assert_eq!(summary.succeeded(), 20);
+        assert_eq!(summary.failed(), 1);
+        assert_eq!(summary.skipped(), 0);
+        assert_eq!(summary.uploaded_bytes(), 200);
+        assert_eq!(max_uploaded_bytes.load(SeqCst), 200);
+        assert_eq!(http_caller.called(), 20);
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_upload_cancelled() -> anyhow::Result<()> {
+        let http_caller = Arc::new(FakeHttpCaller::default());
+        let cancellation_token = CancellationToken::new();
+        cancellation_token.cancel();
+        let summary = BatchUploader::new(fake_uploader(http_caller.to_owned()))
+            .cancellation_token(cancellation_token)
+            .start((0..5).map(|_| BatchUploadJob::bytes(vec![b'a'; 10])))
+            .wait();
+        assert_eq!(summary.succeeded(), 0);
+        assert_eq!(summary.skipped(), 5);
+        assert_eq!(http_caller.called(), 0);
+        Ok(())
+    }
+    #[test]
+    fn test_drop_batch_upload_results() -> anyhow::Result<()> {
+        let (permits_tx, permits_rx) = channel();
+        let http_caller = Arc::new(GatedHttpCaller {
+            inner: Default::default(),
+            permits: Mutex::new(permits_rx),
+        });
+        let uploader = UploaderBuilder::new("test-ak", "test-sk", "test-bucket")
+            .up_urls(vec![FAKE_UP_URL.to_owned()])
+            .http_caller(http_caller.to_owned())
+            .build();
+        // 任务分发线程在所有工作线程退出后才会退出，届时任务迭代器连同其中的发送端一起被丢弃
+        let (feeder_exited_tx, feeder_exited_rx) = channel::<()>();
+        let jobs = (0..).map(move |_| {
+            let _feeder_exited_tx = &feeder_exited_tx;
+            BatchUploadJob::bytes(vec![b'a'; 10])
+        });
+        let cancellation_token = CancellationToken::new();
+        permits_tx.send(())?;
+        let mut results = BatchUploader::new(uploader)
+            .concurrency(1)
+            .cancellation_token(cancellation_token.to_owned())
+            .start(jobs);
+        assert!(results.next().is_some());
+        drop(results);
+        assert!(cancellation_token.is_cancelled());
+        drop(permits_tx);
+        assert!(feeder_exited_rx.recv().is_err());
+        // 丢弃结果迭代器时至多还有一个任务正在上传，此后不会再开始新的任务
+        assert!(http_caller.inner.called() <= 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,707 @@
+//! 七牛对象上传命令行工具
+//!
+//! 默认从 `QINIU` 环境变量指定的配置文件中读取配置，也可以通过 `--config` 指定配置文件
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use qiniu_upload::{
+    BatchUploadOutcome, BatchUploadProgressInfo, CancellationToken, Config, HttpCallError,
+    UploadProgressInfo, UploadRequestBuilder, Uploader, UploaderBuilder,
+};
+use serde_json::{json, Value as JSONValue};
+use std::{
+    fmt,
+    io::{stdin, Error as IOError},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{Arc, Mutex},
+};
+use structopt::{clap::ErrorKind as ClapErrorKind, StructOpt};
+
+/// 上传成功
+const EXIT_SUCCESS: i32 = 0;
+/// 上传失败
+const EXIT_UPLOAD_FAILED: i32 = 1;
+/// 命令行参数或配置错误
+const EXIT_USAGE_ERROR: i32 = 2;
+/// 目录上传时部分文件上传失败
+const EXIT_PARTIALLY_FAILED: i32 = 3;
+/// 上传被用户中断
+const EXIT_CANCELLED: i32 = 130;
+
+/// 服务器允许的最小分片大小
+const MIN_PART_SIZE: u64 = 1 << 20;
+/// 服务器允许的最大分片大小
+const MAX_PART_SIZE: u64 = 1 << 30;
+
+/// 七牛对象上传工具
+#[derive(Debug, StructOpt)]
+#[structopt(name = "qupload")]
+struct Opt {
+    /// 七牛配置文件路径，不指定则使用 QINIU 环境变量
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// 使用配置文件中的命名配置
+    #[structopt(long)]
+    profile: Option<String>,
+
+    /// 以 JSON 格式输出结果，每行一个 JSON 对象，同时不再显示进度条
+    #[structopt(long)]
+    json: bool,
+
+    /// 上传结束后输出本次上传过程中 UP 域名的惩罚状态
+    ///
+    /// 域名状态仅保存在进程内存中，因此只能在上传结束后输出
+    #[structopt(long)]
+    host_stats: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// 上传单个文件
+    File {
+        /// 文件路径
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+
+        #[structopt(flatten)]
+        options: ObjectOptions,
+    },
+
+    /// 从标准输入流式上传
+    Stdin {
+        #[structopt(flatten)]
+        options: ObjectOptions,
+    },
+
+    /// 上传目录，指定清单文件时仅上传发生变化的文件
+    Dir {
+        /// 目录路径
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+
+        /// 对象名称前缀
+        #[structopt(long)]
+        prefix: Option<String>,
+
+        /// 仅上传匹配的文件，可以多次指定
+        #[structopt(long)]
+        include: Vec<String>,
+
+        /// 不上传匹配的文件，可以多次指定
+        #[structopt(long)]
+        exclude: Vec<String>,
+
+        /// 上传隐藏文件
+        #[structopt(long)]
+        hidden: bool,
+
+        /// 本地同步清单文件路径
+        #[structopt(long, parse(from_os_str))]
+        manifest: Option<PathBuf>,
+
+        /// 同步时查询存储空间中的对象，内容一致则跳过
+        #[structopt(long)]
+        check_remote: bool,
+
+        /// 并发上传的文件数量，必须大于 0
+        #[structopt(long)]
+        concurrency: Option<usize>,
+    },
+
+    /// 通过 Upload ID 继续之前未完成的分片上传
+    Resume {
+        /// 文件路径，必须与之前上传的文件一致
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+
+        /// 之前上传时返回的 Upload ID
+        #[structopt(long)]
+        upload_id: String,
+
+        #[structopt(flatten)]
+        options: ObjectOptions,
+    },
+
+    /// 上传凭证相关工具
+    Token(TokenCommand),
+}
+
+#[derive(Debug, StructOpt)]
+enum TokenCommand {
+    /// 解析上传凭证，输出其中的 Access Key 与上传策略
+    Decode {
+        /// 上传凭证
+        token: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct ObjectOptions {
+    /// 对象名称，上传文件时默认为文件名
+    #[structopt(long)]
+    key: Option<String>,
+
+    /// 上传到指定的存储空间，不指定则使用配置中的存储空间
+    #[structopt(long)]
+    bucket: Option<String>,
+
+    /// 对象的 MIME 类型
+    #[structopt(long)]
+    mime_type: Option<String>,
+
+    /// 分片大小，单位为字节，必须在 1 MB 到 1 GB 之间
+    #[structopt(long)]
+    part_size: Option<u64>,
+
+    /// 强制使用分片上传
+    #[structopt(long)]
+    resumable: bool,
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Upload {
+        error: HttpCallError,
+        upload_id: Option<String>,
+    },
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage(_) => EXIT_USAGE_ERROR,
+            Self::Upload { error, .. } if error.is_cancelled() => EXIT_CANCELLED,
+            Self::Upload { .. } => EXIT_UPLOAD_FAILED,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Usage(message) => message.fmt(f),
+            Self::Upload {
+                error,
+                upload_id: Some(upload_id),
+            } => write!(
+                f,
+                "{}, upload can be resumed with `--upload-id {}`",
+                error, upload_id
+            ),
+            Self::Upload { error, .. } => error.fmt(f),
+        }
+    }
+}
+
+impl From<IOError> for CliError {
+    #[inline]
+    fn from(err: IOError) -> Self {
+        Self::Upload {
+            error: err.into(),
+            upload_id: None,
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let opt = match Opt::from_args_safe() {
+        Ok(opt) => opt,
+        Err(err)
+            if matches!(
+                err.kind,
+                ClapErrorKind::HelpDisplayed | ClapErrorKind::VersionDisplayed
+            ) =>
+        {
+            err.exit()
+        }
+        Err(err) => {
+            eprintln!("{}", err.message);
+            exit(EXIT_USAGE_ERROR);
+        }
+    };
+    let json = opt.json;
+    match run(opt) {
+        Ok(code) => exit(code),
+        Err(err) => {
+            if json {
+                print_json(json!({
+                    "status": "failed",
+                    "error": err.to_string(),
+                    "upload_id": match &err {
+                        CliError::Upload { upload_id, .. } => upload_id.to_owned(),
+                        CliError::Usage(_) => None,
+                    },
+                }));
+            } else {
+                eprintln!("Error: {}", err);
+            }
+            exit(err.exit_code());
+        }
+    }
+}
+
+fn run(opt: Opt) -> Result<i32, CliError> {
+    if let Command::Token(TokenCommand::Decode { token }) = &opt.command {
+        return decode_token(token, opt.json);
+    }
+
+    let uploader = build_uploader(&opt)?;
+    let cancellation_token = CancellationToken::new();
+    {
+        let cancellation_token = cancellation_token.to_owned();
+        ctrlc::set_handler(move || cancellation_token.cancel())
+            .map_err(|err| CliError::Usage(format!("Failed to set Ctrl-C handler: {}", err)))?;
+    }
+
+    let (json, host_stats) = (opt.json, opt.host_stats);
+    let result = match opt.command {
+        Command::File { path, options } => {
+            let object_name = match &options.key {
+                Some(key) => key.to_owned(),
+                None => file_name_of(&path)?,
+            };
+            let builder = uploader.upload_path(&path)?.file_name(file_name_of(&path)?);
+            upload(builder, object_name, options, &cancellation_token, json)
+        }
+        Command::Stdin { options } => {
+            let object_name = options
+                .key
+                .to_owned()
+                .ok_or_else(|| CliError::Usage("--key is required for stdin upload".into()))?;
+            let builder = uploader.upload_reader(stdin());
+            upload(builder, object_name, options, &cancellation_token, json)
+        }
+        Command::Resume {
+            path,
+            upload_id,
+            options,
+        } => {
+            let object_name = options
+                .key
+                .to_owned()
+                .ok_or_else(|| CliError::Usage("--key is required for resuming upload".into()))?;
+            let builder = uploader
+                .upload_path(&path)?
+                .file_name(file_name_of(&path)?)
+                .resume(upload_id);
+            upload(builder, object_name, options, &cancellation_token, json)
+        }
+        Command::Dir {
+            path,
+            prefix,
+            include,
+            exclude,
+            hidden,
+            manifest,
+            check_remote,
+            concurrency,
+        } => {
+            let mut builder = uploader
+                .upload_dir(path)
+                .include_hidden_files(hidden)
+                .check_remote(check_remote)
+                .cancellation_token(cancellation_token.to_owned());
+            if let Some(prefix) = prefix {
+                builder = builder.key_prefix(prefix);
+            }
+            for pattern in include {
+                builder = builder.include(pattern);
+            }
+            for pattern in exclude {
+                builder = builder.exclude(pattern);
+            }
+            if let Some(manifest) = manifest {
+                builder = builder.manifest(manifest);
+            }
+            if let Some(concurrency) = concurrency {
+                validate_concurrency(concurrency)?;
+                builder = builder.concurrency(concurrency);
+            }
+            upload_dir(builder, &cancellation_token, json)
+        }
+        Command::Token(_) => unreachable!(),
+    };
+    if host_stats {
+        print_host_stats(&uploader, json);
+    }
+    result
+}
+
+fn build_uploader(opt: &Opt) -> Result<Uploader, CliError> {
+    let uploader = match (&opt.config, &opt.profile) {
+        (Some(path), profile) => {
+            let mut config = Config::load_from_path(path)
+                .map_err(|err| CliError::Usage(format!("Failed to load config: {}", err)))?;
+            if let Some(profile) = profile {
+                config = config
+                    .profile(profile)
+                    .ok_or_else(|| CliError::Usage(format!("Profile {} is not found", profile)))?;
+            }
+            UploaderBuilder::from_config(&config)
+                .and_then(UploaderBuilder::try_build)
+                .map_err(|err| CliError::Usage(format!("Invalid config: {}", err)))?
+        }
+        (None, Some(profile)) => Uploader::from_env_profile(profile).ok_or_else(|| {
+            CliError::Usage(format!(
+                "Profile {} is not found, please check QINIU env",
+                profile
+            ))
+        })?,
+        (None, None) => Uploader::from_env().ok_or_else(|| {
+            CliError::Usage("No QINIU env is setup, please specify --config".into())
+        })?,
+    };
+    Ok(uploader)
+}
+
+fn upload(
+    mut builder: UploadRequestBuilder<'_>,
+    object_name: String,
+    options: ObjectOptions,
+    cancellation_token: &CancellationToken,
+    json: bool,
+) -> Result<i32, CliError> {
+    validate_object_options(&options)?;
+    if let Some(bucket) = options.bucket {
+        builder = builder.bucket(bucket);
+    }
+    if let Some(mime_type) = options.mime_type {
+        builder = builder.mime_type(mime_type);
+    }
+    if let Some(part_size) = options.part_size {
+        builder = builder.part_size(part_size);
+    }
+    if options.resumable {
+        builder = builder.force_resumable();
+    }
+
+    let progress_bar = new_progress_bar(json);
+    progress_bar.set_style(bytes_progress_style());
+    let upload_id = Arc::new(Mutex::new(None));
+    let result = {
+        let progress_bar = progress_bar.to_owned();
+        let upload_id = upload_id.to_owned();
+        builder
+            .object_name(&object_name)
+            .cancellation_token(cancellation_token.to_owned())
+            .upload_progress_callback(Box::new(move |info: &UploadProgressInfo| {
+                if let Some(total_size) = info.total_size() {
+                    progress_bar.set_length(total_size);
+                }
+                progress_bar.set_position(info.uploaded());
+                progress_bar.set_message(format!("{:?}", info.phase()));
+                if let Some(id) = info.upload_id() {
+                    let mut upload_id = upload_id.lock().unwrap();
+                    if upload_id.is_none() {
+                        *upload_id = Some(id.to_owned());
+                    }
+                }
+                Ok(())
+            }))
+            .start()
+    };
+    progress_bar.finish_and_clear();
+
+    let result = result.map_err(|error| CliError::Upload {
+        error,
+        upload_id: upload_id.lock().unwrap().take(),
+    })?;
+    if json {
+        print_json(json!({
+            "status": "succeeded",
+            "object_name": object_name,
+            "response": result.response_body(),
+        }));
+    } else {
+        println!("Uploaded: {}", object_name);
+        println!("{}", result.response_body());
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn validate_object_options(options: &ObjectOptions) -> Result<(), CliError> {
+    match options.part_size {
+        Some(part_size) if !(MIN_PART_SIZE..=MAX_PART_SIZE).contains(&part_size) => {
+            Err(CliError::Usage(format!(
+                "--part-size must be between {} and {} bytes",
+                MIN_PART_SIZE, MAX_PART_SIZE
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_concurrency(concurrency: usize) -> Result<(), CliError> {
+    if concurrency == 0 {
+        Err(CliError::Usage(
+            "--concurrency must be greater than 0".into(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn upload_dir(
+    builder: qiniu_upload::DirUploadBuilder,
+    cancellation_token: &CancellationToken,
+    json: bool,
+) -> Result<i32, CliError> {
+    let progress_bar = new_progress_bar(json);
+    progress_bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner} [{elapsed_precise}] {bytes} ({bytes_per_sec}) {msg}",
+        )
+        .unwrap(),
+    );
+    let results = {
+        let progress_bar = progress_bar.to_owned();
+        builder
+            .progress_callback(Box::new(move |info: &BatchUploadProgressInfo| {
+                progress_bar.set_position(info.uploaded_bytes());
+                progress_bar.set_message(format!(
+                    "{} succeeded, {} failed, {} skipped",
+                    info.succeeded(),
+                    info.failed(),
+                    info.skipped()
+                ));
+            }))
+            .start()?
+    };
+
+    let mut results = results;
+    for result in &mut results {
+        let object_name = result.object_name().unwrap_or_default().to_owned();
+        match result.outcome() {
+            BatchUploadOutcome::Succeeded(upload_result) if json => progress_bar.suspend(|| {
+                print_json(json!({
+                    "status": "succeeded",
+                    "object_name": object_name,
+                    "response": upload_result.response_body(),
+                }))
+            }),
+            BatchUploadOutcome::Failed(err) if json => progress_bar.suspend(|| {
+                print_json(json!({
+                    "status": "failed",
+                    "object_name": object_name,
+                    "error": err.to_string(),
+                }))
+            }),
+            BatchUploadOutcome::Failed(err) => {
+                progress_bar.suspend(|| eprintln!("Failed: {}: {}", object_name, err))
+            }
+            _ => {}
+        }
+    }
+    let summary = results.wait();
+    progress_bar.finish_and_clear();
+
+    if json {
+        print_json(json!({
+            "status": "finished",
+            "succeeded": summary.succeeded(),
+            "failed": summary.failed(),
+            "skipped": summary.skipped(),
+            "uploaded_bytes": summary.uploaded_bytes(),
+            "elapsed_ms": summary.elapsed().as_millis() as u64,
+        }));
+    } else {
+        println!(
+            "{} succeeded, {} failed, {} skipped, {} bytes uploaded in {:?}",
+            summary.succeeded(),
+            summary.failed(),
+            summary.skipped(),
+            summary.uploaded_bytes(),
+            summary.elapsed()
+        );
+    }
+
+    let code = if cancellation_token.is_cancelled() {
+        EXIT_CANCELLED
+    } else if summary.failed() == 0 {
+        EXIT_SUCCESS
+    } else if summary.succeeded() + summary.skipped() > 0 {
+        EXIT_PARTIALLY_FAILED
+    } else {
+        EXIT_UPLOAD_FAILED
+    };
+    Ok(code)
+}
+
+fn print_host_stats(uploader: &Uploader, json: bool) {
+    let host_stats = uploader.up_host_stats();
+    if json {
+        for host_stat in host_stats {
+            print_json(json!({
+                "host": host_stat.host(),
+                "punished": host_stat.is_punished(),
+                "continuous_punished_times": host_stat.continuous_punished_times(),
+                "timeout_power": host_stat.timeout_power(),
+            }));
+        }
+    } else {
+        for host_stat in host_stats {
+            println!(
+                "{}\tpunished: {}\tcontinuous punished times: {}\ttimeout power: {}",
+                host_stat.host(),
+                host_stat.is_punished(),
+                host_stat.continuous_punished_times(),
+                host_stat.timeout_power(),
+            );
+        }
+    }
+}
+
+fn decode_token(token: &str, json: bool) -> Result<i32, CliError> {
+    let invalid_token = || CliError::Usage("Invalid upload token".into());
+    let mut parts = token.splitn(3, ':');
+    let access_key = parts.next().ok_or_else(invalid_token)?;
+    let encoded_policy = parts.nth(1).ok_or_else(invalid_token)?;
+    let policy = base64::decode_config(encoded_policy, base64::URL_SAFE)
+        .ok()
+        .and_then(|policy| serde_json::from_slice::<JSONValue>(&policy).ok())
+        .ok_or_else(invalid_token)?;
+    if json {
+        print_json(json!({
+            "access_key": access_key,
+            "policy": policy,
+        }));
+    } else {
+        println!("Access Key: {}", access_key);
+        println!("{:#}", policy);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn file_name_of(path: &Path) -> Result<String, CliError> {
+    path.file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .ok_or_else(|| CliError::Usage(format!("No file name is found in {}", path.display())))
+}
+
+fn new_progress_bar(json: bool) -> ProgressBar {
+    if json {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+    }
+}
+
+fn bytes_progress_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{spinner} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {msg}",
+    )
+    .unwrap()
+}
+
+#[inline]
+fn print_json(value: JSONValue) {
+    println!("{}", value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Opt, structopt::clap::Error> {
+        Opt::from_iter_safe(std::iter::once("qupload").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_parse_args() -> anyhow::Result<()> {
+        let opt = parse(&[
+            "--json",
+            "file",
+            "a.txt",
+            "--key",
+            "a",
+            "--part-size",
+            "4194304",
+            "--resumable",
+        ])?;
+        assert!(opt.json);
+        match opt.command {
+            Command::File { path, options } => {
+                assert_eq!(path, PathBuf::from("a.txt"));
+                assert_eq!(options.key.as_deref(), Some("a"));
+                assert_eq!(options.part_size, Some(4 << 20));
+                assert!(options.resumable);
+                validate_object_options(&options).unwrap();
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        let opt = parse(&["dir", ".", "--include", "*.log", "--include", "*.txt"])?;
+        match opt.command {
+            Command::Dir {
+                include,
+                concurrency,
+                ..
+            } => {
+                assert_eq!(include, vec!["*.log", "*.txt"]);
+                assert_eq!(concurrency, None);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        assert!(parse(&["resume", "a.txt"]).is_err());
+        assert!(parse(&["file", "a.txt", "--part-size", "-1"]).is_err());
+        assert!(parse(&["unknown"]).is_err());
+        assert!(parse(&["hosts"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_args_exit_code() -> anyhow::Result<()> {
+        for part_size in ["100", "1073741825"] {
+            let opt = parse(&["file", "a.txt", "--part-size", part_size])?;
+            match opt.command {
+                Command::File { options, .. } => assert_eq!(
+                    validate_object_options(&options).unwrap_err().exit_code(),
+                    EXIT_USAGE_ERROR
+                ),
+                command => panic!("unexpected command {:?}", command),
+            }
+        }
+        assert_eq!(
+            validate_concurrency(0).unwrap_err().exit_code(),
+            EXIT_USAGE_ERROR
+        );
+        validate_concurrency(1).unwrap();
+
+        let opt = parse(&["token", "decode", "invalid-token"])?;
+        assert_eq!(run(opt).unwrap_err().exit_code(), EXIT_USAGE_ERROR);
+        let opt = parse(&[
+            "--config",
+            "/not/existed/config.toml",
+            "--host-stats",
+            "stdin",
+            "--key",
+            "a",
+        ])?;
+        assert!(opt.host_stats);
+        assert_eq!(run(opt).unwrap_err().exit_code(), EXIT_USAGE_ERROR);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_error_exit_code() {
+        let err = CliError::Upload {
+            error: HttpCallError::Cancelled,
+            upload_id: None,
+        };
+        assert_eq!(err.exit_code(), EXIT_CANCELLED);
+        let err = CliError::Upload {
+            error: HttpCallError::DeadlineExceeded,
+            upload_id: Some("fake-upload-id".into()),
+        };
+        assert_eq!(err.exit_code(), EXIT_UPLOAD_FAILED);
+        assert!(err.to_string().contains("--upload-id fake-upload-id"));
+    }
+}
@@ -0,0 +1,370 @@
+use crc32fast::Hasher as Crc32;
+use digest::{generic_array::GenericArray, Digest};
+use log::{debug, warn};
+use md5::Md5;
+use positioned_io::ReadAt;
+use std::{
+    env::temp_dir,
+    fs::{remove_file, File, OpenOptions},
+    io::{ErrorKind as IOErrorKind, Read, Result as IOResult, Write},
+    mem::take,
+    path::PathBuf,
+    process,
+    sync::{Arc, Mutex},
+};
+
+pub(super) type Md5Digest = GenericArray<u8, <Md5 as Digest>::OutputSize>;
+
+/// 分片缓冲池
+///
+/// 为无法随机读取的数据源缓存分片数据，缓冲区在使用结束后归还缓冲池以便复用。
+/// 所有缓冲区的总容量不超过内存预算，超出预算的分片将被写入临时文件。
+/// 缓存分片的同时计算分片的 MD5，可能作为表单上传数据的分片还将计算 CRC32，上传及重试时无需再次读取分片
+#[derive(Debug)]
+pub(super) struct BufferPool {
+    memory_budget: u64,
+    spill_dir: Option<PathBuf>,
+    state: Mutex<BufferPoolState>,
+}
+
+#[derive(Debug, Default)]
+struct BufferPoolState {
+    allocated: u64,
+    idle_buffers: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    #[inline]
+    pub(super) fn new(memory_budget: u64, spill_dir: Option<PathBuf>) -> Self {
+        Self {
+            memory_budget,
+            spill_dir,
+            state: Default::default(),
+        }
+    }
+
+    /// 从输入流中读取至多 `size` 字节的数据，输入流已经结束则返回 None
+    ///
+    /// 内存预算充足时数据缓存在内存中，否则写入临时文件。仅当 `with_crc32` 为真时才计算 CRC32
+    pub(super) fn read_part(
+        self: &Arc<Self>,
+        reader: &mut dyn Read,
+        size: u64,
+        with_crc32: bool,
+    ) -> IOResult<Option<BufferedPart>> {
+        let mut hasher = PartHasher::new(with_crc32);
+        let data = match self.acquire(size) {
+            Some(mut buffer) => {
+                buffer.fill_from(reader, size)?;
+                hasher.update(&buffer.buffer);
+                BufferedData::Memory(buffer)
+            }
+            None => BufferedData::Spilled(SpilledFile::create_from(
+                self.spill_dir.to_owned().unwrap_or_else(temp_dir),
+                &mut reader.take(size),
+                &mut hasher,
+            )?),
+        };
+        if data.len() == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(BufferedPart(Arc::new(BufferedPartInner {
+                data,
+                md5: hasher.md5.finalize(),
+                crc32: hasher.crc32.map(Crc32::finalize),
+            }))))
+        }
+    }
+
+    fn acquire(self: &Arc<Self>, size: u64) -> Option<PooledBuffer> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(pos) = state
+            .idle_buffers
+            .iter()
+            .position(|buffer| buffer.capacity() as u64 >= size)
+        {
+            let buffer = state.idle_buffers.swap_remove(pos);
+            return Some(PooledBuffer::new(buffer, self.to_owned()));
+        }
+        // 释放容量不足的空闲缓冲区，为新的缓冲区腾出预算
+        while state.allocated.saturating_add(size) > self.memory_budget {
+            let buffer = state.idle_buffers.pop()?;
+            state.allocated -= buffer.capacity() as u64;
+        }
+        let buffer = Vec::with_capacity(size as usize);
+        state.allocated += buffer.capacity() as u64;
+        Some(PooledBuffer::new(buffer, self.to_owned()))
+    }
+
+    fn release(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        self.state.lock().unwrap().idle_buffers.push(buffer);
+    }
+}
+
+/// 缓存的分片数据，克隆后共享同一份数据
+#[derive(Debug, Clone)]
+pub(super) struct BufferedPart(Arc<BufferedPartInner>);
+
+#[derive(Debug)]
+struct BufferedPartInner {
+    data: BufferedData,
+    md5: Md5Digest,
+    crc32: Option<u32>,
+}
+
+#[derive(Debug)]
+enum BufferedData {
+    Memory(PooledBuffer),
+    Spilled(SpilledFile),
+}
+
+impl BufferedPart {
+    #[inline]
+    pub(super) fn len(&self) -> u64 {
+        self.0.data.len()
+    }
+
+    #[inline]
+    pub(super) fn md5(&self) -> Md5Digest {
+        self.0.md5
+    }
+
+    /// 读取分片时计算的 CRC32，未要求计算时返回 None
+    #[inline]
+    pub(super) fn crc32(&self) -> Option<u32> {
+        self.0.crc32
+    }
+
+    #[inline]
+    pub(super) fn is_spilled(&self) -> bool {
+        matches!(self.0.data, BufferedData::Spilled(_))
+    }
+}
+
+impl BufferedData {
+    #[inline]
+    fn len(&self) -> u64 {
+        match self {
+            Self::Memory(buffer) => buffer.buffer.len() as u64,
+            Self::Spilled(file) => file.len,
+        }
+    }
+}
+
+impl ReadAt for BufferedPart {
+    #[inline]
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> IOResult<usize> {
+        match &self.0.data {
+            BufferedData::Memory(buffer) => buffer.buffer.read_at(pos, buf),
+            BufferedData::Spilled(file) => {
+                // 临时文件的长度即为分片长度，无需额外截断
+                file.file.read_at(pos, buf)
+            }
+        }
+    }
+}
+
+struct PartHasher {
+    md5: Md5,
+    crc32: Option<Crc32>,
+}
+
+impl PartHasher {
+    #[inline]
+    fn new(with_crc32: bool) -> Self {
+        Self {
+            md5: Md5::new(),
+            crc32: if with_crc32 { Some(Crc32::new()) } else { None },
+        }
+    }
+
+    #[inline]
+    fn update(&mut self, data: &[u8]) {
+        self.md5.update(data);
+        if let Some(crc32) = &mut self.crc32 {
+            crc32.update(data);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl PooledBuffer {
+    #[inline]
+    fn new(buffer: Vec<u8>, pool: Arc<BufferPool>) -> Self {
+        Self { buffer, pool }
+    }
+
+    /// 读取输入流直到读满 `size` 字节或输入流结束，不会扩容缓冲区
+    ///
+    /// 复用的缓冲区容量可能大于 `size`，至多只读取 `size` 与缓冲区容量中较小者的数据
+    fn fill_from(&mut self, reader: &mut dyn Read, size: u64) -> IOResult<()> {
+        let limit = size.min(self.buffer.capacity() as u64) as usize;
+        self.buffer.resize(limit, 0);
+        let mut filled = 0;
+        while filled < limit {
+            match reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(have_read) => filled += have_read,
+                Err(err) if err.kind() == IOErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buffer.truncate(filled);
+                    return Err(err);
+                }
+            }
+        }
+        self.buffer.truncate(filled);
+        Ok(())
+    }
+}
+
+impl Drop for PooledBuffer {
+    #[inline]
+    fn drop(&mut self) {
+        self.pool.release(take(&mut self.buffer));
+    }
+}
+
+#[derive(Debug)]
+struct SpilledFile {
+    file: File,
+    path: PathBuf,
+    len: u64,
+}
+
+impl SpilledFile {
+    fn create_from(dir: PathBuf, reader: &mut dyn Read, hasher: &mut PartHasher) -> IOResult<Self> {
+        let path = dir.join(format!(
+            ".qiniu-upload-part-{}-{:016x}",
+            process::id(),
+            rand::random::<u64>()
+        ));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let mut spilled = Self {
+            file: file.try_clone()?,
+            path,
+            len: 0,
+        };
+        let mut buf = vec![0u8; 1 << 16];
+        loop {
+            let have_read = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(have_read) => have_read,
+                Err(err) if err.kind() == IOErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            file.write_all(&buf[..have_read])?;
+            hasher.update(&buf[..have_read]);
+            spilled.len += have_read as u64;
+        }
+        debug!(
+            "spilled {} bytes to {}",
+            spilled.len,
+            spilled.path.display()
+        );
+        Ok(spilled)
+    }
+}
+
+impl Drop for SpilledFile {
+    #[inline]
+    fn drop(&mut self) {
+        if let Err(err) = remove_file(&self.path) {
+            warn!(
+                "failed to remove spilled part {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use positioned_io::Cursor;
+    use std::io::{repeat, Cursor as IOCursor};
+    use tempfile::tempdir;
+
+    fn read_all(part: &BufferedPart) -> IOResult<Vec<u8>> {
+        let mut buf = Vec::new();
+        Cursor::new(part.to_owned()).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_buffer_pool() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let pool = Arc::new(BufferPool::new(10, Some(dir.path().to_owned())));
+        let mut reader = IOCursor::new((0u8..25).collect::<Vec<_>>());
+
+        let first = pool.read_part(&mut reader, 8, false)?.unwrap();
+        assert!(!first.is_spilled());
+        assert_eq!(read_all(&first)?, (0u8..8).collect::<Vec<_>>());
+        assert_eq!(first.md5(), Md5::digest(&(0u8..8).collect::<Vec<_>>()));
+        assert!(first.crc32().is_none());
+
+        let second = pool.read_part(&mut reader, 8, true)?.unwrap();
+        assert!(second.is_spilled());
+        assert_eq!(second.len(), 8);
+        assert_eq!(read_all(&second)?, (8u8..16).collect::<Vec<_>>());
+        assert_eq!(read_all(&second)?, (8u8..16).collect::<Vec<_>>());
+        assert_eq!(second.md5(), Md5::digest(&(8u8..16).collect::<Vec<_>>()));
+        assert_eq!(
+            second.crc32(),
+            Some(crc32fast::hash(&(8u8..16).collect::<Vec<_>>()))
+        );
+        assert_eq!(dir.path().read_dir()?.count(), 1);
+        drop(second);
+        assert_eq!(dir.path().read_dir()?.count(), 0);
+
+        drop(first);
+        let third = pool.read_part(&mut reader, 8, false)?.unwrap();
+        assert!(!third.is_spilled());
+        assert_eq!(read_all(&third)?, (16u8..24).collect::<Vec<_>>());
+        assert_eq!(pool.state.lock().unwrap().allocated, 8);
+
+        let last = pool.read_part(&mut reader, 8, false)?.unwrap();
+        assert!(last.is_spilled());
+        assert_eq!(read_all(&last)?, vec![24u8]);
+        assert!(pool.read_part(&mut reader, 8, false)?.is_none());
+        drop(third);
+
+        // 空闲缓冲区容量不足时将被释放
+        let large = pool
+            .read_part(&mut repeat(b'a').take(20), 10, false)?
+            .unwrap();
+        assert!(!large.is_spilled());
+        assert_eq!(large.len(), 10);
+        assert_eq!(pool.state.lock().unwrap().allocated, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reuse_larger_buffer() -> anyhow::Result<()> {
+        let pool = Arc::new(BufferPool::new(10, None));
+        let mut reader = IOCursor::new((0u8..25).collect::<Vec<_>>());
+
+        drop(pool.read_part(&mut reader, 10, false)?.unwrap());
+        // 复用容量为 10 的空闲缓冲区，但只读取 4 字节
+        let part = pool.read_part(&mut reader, 4, false)?.unwrap();
+        assert!(!part.is_spilled());
+        assert_eq!(read_all(&part)?, (10u8..14).collect::<Vec<_>>());
+        assert_eq!(part.md5(), Md5::digest(&(10u8..14).collect::<Vec<_>>()));
+        assert_eq!(pool.state.lock().unwrap().allocated, 10);
+        drop(part);
+
+        let part = pool.read_part(&mut reader, 10, false)?.unwrap();
+        assert_eq!(read_all(&part)?, (14u8..24).collect::<Vec<_>>());
+        Ok(())
+    }
+}
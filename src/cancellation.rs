@@ -0,0 +1,242 @@
+use super::error::{HttpCallError, HttpCallResult};
+use std::{
+    fmt,
+    io::{Error as IOError, Read, Result as IOResult},
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+/// 上传取消令牌
+///
+/// 克隆得到的令牌共享同一个取消状态，取消后所有使用该令牌的上传都将在下一次读取数据或发送请求前以
+/// [`HttpCallError::Cancelled`] 错误中止
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    /// 创建上传取消令牌
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 取消上传
+    #[inline]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, SeqCst);
+    }
+
+    /// 判断是否已经取消
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(SeqCst)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// 创建子令牌，当前令牌取消时子令牌随之取消，子令牌取消时不影响当前令牌
+    #[inline]
+    pub(super) fn child(&self) -> Self {
+        Self {
+            cancelled: Default::default(),
+            parent: Some(Box::new(self.to_owned())),
+        }
+    }
+
+    #[inline]
+    pub(super) fn check(&self) -> HttpCallResult<()> {
+        if self.is_cancelled() {
+            Err(HttpCallError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// 上传暂停句柄
+///
+/// 暂停后，使用该句柄的上传将在下一次发送请求前阻塞，直到恢复或被取消。
+/// 对于分片上传，已经上传的分片和 Upload ID 都将被保留，恢复后从下一个分片继续上传
+#[derive(Clone, Default)]
+pub struct PauseHandle {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+/// 暂停期间检查取消令牌的时间间隔
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+impl PauseHandle {
+    /// 创建上传暂停句柄
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// 暂停上传
+    #[inline]
+    pub fn pause(&self) {
+        *self.inner.0.lock().unwrap() = true;
+    }
+
+    /// 恢复上传
+    #[inline]
+    pub fn resume(&self) {
+        *self.inner.0.lock().unwrap() = false;
+        self.inner.1.notify_all();
+    }
+
+    /// 判断是否已经暂停
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        *self.inner.0.lock().unwrap()
+    }
+
+    /// 如果已经暂停，则阻塞当前线程直到恢复，期间如果上传被取消则返回错误
+    pub(super) fn wait_until_resumed(
+        &self,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> HttpCallResult<()> {
+        let mut paused = self.inner.0.lock().unwrap();
+        while *paused {
+            if let Some(cancellation_token) = cancellation_token {
+                cancellation_token.check()?;
+            }
+            paused = self
+                .inner
+                .1
+                .wait_timeout(paused, PAUSE_CHECK_INTERVAL)
+                .unwrap()
+                .0;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PauseHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PauseHandle")
+            .field("paused", &self.is_paused())
+            .finish()
+    }
+}
+
+/// 可取消的输入流，每次读取前都将检查取消令牌
+#[derive(Debug)]
+pub(super) struct CancellableReader<R> {
+    inner: R,
+    cancellation_token: Option<CancellationToken>,
+}
+
+impl<R: Read> CancellableReader<R> {
+    #[inline]
+    pub(super) fn new(inner: R, cancellation_token: Option<CancellationToken>) -> Self {
+        Self {
+            inner,
+            cancellation_token,
+        }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        if let Some(cancellation_token) = &self.cancellation_token {
+            cancellation_token.check().map_err(IOError::other)?;
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{copy, repeat, sink},
+        thread::{sleep, spawn},
+        time::Instant,
+    };
+
+    #[test]
+    fn test_child_cancellation_token() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let child = parent.child();
+        parent.cancel();
+        assert!(child.to_owned().is_cancelled());
+    }
+
+    #[test]
+    fn test_cancellable_reader() -> anyhow::Result<()> {
+        let cancellation_token = CancellationToken::new();
+        let mut reader =
+            CancellableReader::new(repeat(b'a').take(10), Some(cancellation_token.to_owned()));
+        let mut buf = [0u8; 5];
+        assert_eq!(reader.read(&mut buf)?, 5);
+        cancellation_token.cancel();
+        assert!(reader.read(&mut buf).is_err());
+        assert!(matches!(
+            cancellation_token.check(),
+            Err(HttpCallError::Cancelled)
+        ));
+
+        let mut reader = CancellableReader::new(repeat(b'a').take(10), None);
+        assert_eq!(copy(&mut reader, &mut sink())?, 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pause_handle() -> anyhow::Result<()> {
+        let pause_handle = PauseHandle::new();
+        pause_handle.wait_until_resumed(None)?;
+
+        pause_handle.pause();
+        let begin_at = Instant::now();
+        let thread = {
+            let pause_handle = pause_handle.to_owned();
+            spawn(move || {
+                sleep(Duration::from_millis(300));
+                pause_handle.resume();
+            })
+        };
+        pause_handle.wait_until_resumed(None)?;
+        assert!(begin_at.elapsed() >= Duration::from_millis(300));
+        thread.join().unwrap();
+
+        pause_handle.pause();
+        let cancellation_token = CancellationToken::new();
+        let thread = {
+            let cancellation_token = cancellation_token.to_owned();
+            spawn(move || {
+                sleep(Duration::from_millis(300));
+                cancellation_token.cancel();
+            })
+        };
+        assert!(matches!(
+            pause_handle.wait_until_resumed(Some(&cancellation_token)),
+            Err(HttpCallError::Cancelled)
+        ));
+        assert!(pause_handle.is_paused());
+        thread.join().unwrap();
+        Ok(())
+    }
+}
@@ -1,29 +1,65 @@
-use crate::UploaderBuilder;
+use crate::{
+    error::{ConfigError, ConfigResult},
+    uploader::{MAX_FORM_UPLOAD_THRESHOLD, MAX_PART_SIZE, MIN_PART_SIZE},
+    RateLimiter, UploaderBuilder,
+};
 use log::{error, info, warn};
 use notify::{watcher, DebouncedEvent, RecursiveMode, Result as NotifyResult, Watcher};
 use once_cell::sync::{Lazy, OnceCell};
-use reqwest::blocking::Client as HTTPClient;
+use reqwest::{
+    blocking::{Client as HTTPClient, ClientBuilder as HTTPClientBuilder},
+    tls::{Certificate, Identity, Version as TlsVersion},
+    NoProxy, Proxy,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     convert::TryInto,
-    env, fmt, fs,
-    io::Result as IOResult,
+    env,
+    ffi::{OsStr, OsString},
+    fmt, fs,
+    io::{Result as IOResult, Write},
+    net::IpAddr,
     path::{Path, PathBuf},
+    process,
+    str::FromStr,
     sync::{mpsc::channel, RwLock},
     thread::{Builder as ThreadBuilder, JoinHandle},
     time::Duration,
 };
 use tap::prelude::*;
+use url::Url;
 
 /// 七牛配置信息
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+///
+/// 除了顶层的配置项外，还可以在 `profiles` 中定义多个命名配置，例如：
+///
+/// ```toml
+/// access_key = "ak-1"
+/// secret_key = "sk-1"
+/// bucket = "default-bucket"
+/// up_urls = ["http://up.qiniup.com"]
+///
+/// [profiles.images]
+/// bucket = "images"
+///
+/// [profiles.archive]
+/// access_key = "ak-2"
+/// secret_key = "sk-2"
+/// bucket = "archive"
+/// part_size = 16
+/// ```
+///
+/// 顶层配置项作为所有命名配置的共享默认值，命名配置中设置的配置项将覆盖共享默认值。
+/// 如果仅使用命名配置，顶层的 `access_key`，`secret_key` 和 `bucket` 可以省略。
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
 pub struct Config {
-    #[serde(alias = "ak")]
+    #[serde(alias = "ak", default)]
     access_key: String,
-    #[serde(alias = "sk")]
+    #[serde(alias = "sk", default)]
     secret_key: String,
 
+    #[serde(default)]
     bucket: String,
 
     #[serde(alias = "up_hosts")]
@@ -34,24 +70,36 @@ pub struct Config {
 
     #[serde(alias = "part")]
     part_size: Option<u64>,
+    form_upload_threshold: Option<u64>,
 
+    use_https: Option<bool>,
     retry: Option<usize>,
+    up_tries: Option<usize>,
+    uc_tries: Option<usize>,
+    update_interval_s: Option<u64>,
     punish_time_s: Option<u64>,
     base_timeout_ms: Option<u64>,
-    base_timeout_multiple_percents: Option<HashMap<String, u32>>,
     dial_timeout_ms: Option<u64>,
+    max_punished_times: Option<usize>,
+    max_punished_hosts_percent: Option<u8>,
+    proxy: Option<String>,
+    no_proxy: Option<String>,
+    ca_cert_paths: Option<Vec<String>>,
+    client_cert_path: Option<String>,
+    client_key_path: Option<String>,
+    min_tls_version: Option<String>,
+    resolve_ttl_s: Option<u64>,
+    max_upload_bytes_per_s: Option<u64>,
+
+    // 以下配置项在 TOML 中表现为表，必须放在其他配置项之后
+    base_timeout_multiple_percents: Option<HashMap<String, u32>>,
+    resolve_overrides: Option<HashMap<String, Vec<String>>>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    profiles: HashMap<String, Config>,
 }
 
-static QINIU_CONFIG: Lazy<RwLock<Option<Config>>> = Lazy::new(|| {
-    RwLock::new(load_config()).tap(|_| {
-        on_config_updated(|| {
-            if let Some(config) = load_config() {
-                *QINIU_CONFIG.write().unwrap() = Some(config);
-            }
-            info!("QINIU_CONFIG reloaded: {:?}", QINIU_CONFIG);
-        })
-    })
-});
+static QINIU_CONFIG: Lazy<RwLock<Option<Config>>> = Lazy::new(|| RwLock::new(load_config()));
 pub(super) static HTTP_CLIENT: Lazy<RwLock<HTTPClient>> = Lazy::new(|| {
     RwLock::new(build_http_client()).tap(|_| {
         on_config_updated(|| {
@@ -63,13 +111,19 @@ pub(super) static HTTP_CLIENT: Lazy<RwLock<HTTPClient>> = Lazy::new(|| {
 
 /// 判断当前是否已经启用七牛环境
 ///
-/// 如果当前没有设置 QINIU 环境变量，或加载该环境变量出现错误，则返回 false
+/// 如果当前没有设置 QINIU 环境变量或七牛认证信息相关的环境变量，或加载配置出现错误，则返回 false
 #[inline]
 pub fn is_qiniu_enabled() -> bool {
     QINIU_CONFIG.read().unwrap().is_some()
 }
 
 fn build_http_client() -> HTTPClient {
+    http_client_builder()
+        .build()
+        .expect("Failed to build Reqwest Client")
+}
+
+pub(super) fn http_client_builder() -> HTTPClientBuilder {
     let mut base_timeout_ms = 30000u64;
     let mut dial_timeout_ms = DEFAULT_DIAL_TIMEOUT_MS;
 
@@ -92,8 +146,6 @@ fn build_http_client() -> HTTPClient {
         .timeout(Duration::from_millis(base_timeout_ms))
         .pool_max_idle_per_host(5)
         .connection_verbose(true)
-        .build()
-        .expect("Failed to build Reqwest Client")
 }
 
 #[cfg(test)]
@@ -102,33 +154,56 @@ const DEFAULT_DIAL_TIMEOUT_MS: u64 = 1000;
 #[cfg(not(test))]
 const DEFAULT_DIAL_TIMEOUT_MS: u64 = 50;
 
+const MIN_PART_SIZE_MB: u64 = MIN_PART_SIZE >> 20;
+const MAX_PART_SIZE_MB: u64 = MAX_PART_SIZE >> 20;
+const MAX_FORM_UPLOAD_THRESHOLD_MB: u64 = MAX_FORM_UPLOAD_THRESHOLD >> 20;
+
 const QINIU_ENV: &str = "QINIU";
+const QINIU_ACCESS_KEY_ENV: &str = "QINIU_ACCESS_KEY";
+const QINIU_SECRET_KEY_ENV: &str = "QINIU_SECRET_KEY";
+const QINIU_BUCKET_ENV: &str = "QINIU_BUCKET";
+const QINIU_UP_URLS_ENV: &str = "QINIU_UP_URLS";
+const QINIU_UC_URLS_ENV: &str = "QINIU_UC_URLS";
+const QINIU_PART_SIZE_MB_ENV: &str = "QINIU_PART_SIZE_MB";
+const QINIU_FORM_UPLOAD_THRESHOLD_MB_ENV: &str = "QINIU_FORM_UPLOAD_THRESHOLD_MB";
+const QINIU_USE_HTTPS_ENV: &str = "QINIU_USE_HTTPS";
+const QINIU_RETRY_ENV: &str = "QINIU_RETRY";
+const QINIU_UP_TRIES_ENV: &str = "QINIU_UP_TRIES";
+const QINIU_UC_TRIES_ENV: &str = "QINIU_UC_TRIES";
+const QINIU_UPDATE_INTERVAL_S_ENV: &str = "QINIU_UPDATE_INTERVAL_S";
+const QINIU_PUNISH_TIME_S_ENV: &str = "QINIU_PUNISH_TIME_S";
+const QINIU_BASE_TIMEOUT_MS_ENV: &str = "QINIU_BASE_TIMEOUT_MS";
+const QINIU_DIAL_TIMEOUT_MS_ENV: &str = "QINIU_DIAL_TIMEOUT_MS";
+const QINIU_MAX_PUNISHED_TIMES_ENV: &str = "QINIU_MAX_PUNISHED_TIMES";
+const QINIU_MAX_PUNISHED_HOSTS_PERCENT_ENV: &str = "QINIU_MAX_PUNISHED_HOSTS_PERCENT";
+const QINIU_UP_TIMEOUT_MULTIPLE_PERCENT_ENV: &str = "QINIU_UP_TIMEOUT_MULTIPLE_PERCENT";
+const QINIU_UC_TIMEOUT_MULTIPLE_PERCENT_ENV: &str = "QINIU_UC_TIMEOUT_MULTIPLE_PERCENT";
+const QINIU_PROXY_ENV: &str = "QINIU_PROXY";
+const QINIU_NO_PROXY_ENV: &str = "QINIU_NO_PROXY";
+const QINIU_CA_CERT_PATHS_ENV: &str = "QINIU_CA_CERT_PATHS";
+const QINIU_CLIENT_CERT_PATH_ENV: &str = "QINIU_CLIENT_CERT_PATH";
+const QINIU_CLIENT_KEY_PATH_ENV: &str = "QINIU_CLIENT_KEY_PATH";
+const QINIU_MIN_TLS_VERSION_ENV: &str = "QINIU_MIN_TLS_VERSION";
+const QINIU_RESOLVE_TTL_S_ENV: &str = "QINIU_RESOLVE_TTL_S";
+const QINIU_RESOLVE_OVERRIDES_ENV: &str = "QINIU_RESOLVE_OVERRIDES";
+const QINIU_MAX_UPLOAD_BYTES_PER_S_ENV: &str = "QINIU_MAX_UPLOAD_BYTES_PER_S";
 
 fn load_config() -> Option<Config> {
-    if let Ok(qiniu_config_path) = env::var(QINIU_ENV) {
-        if let Ok(qiniu_config) = fs::read(&qiniu_config_path) {
-            let qiniu_config: Option<Config> = if qiniu_config_path.ends_with(".toml") {
-                toml::from_slice(&qiniu_config).ok()
-            } else {
-                serde_json::from_slice(&qiniu_config).ok()
-            };
-            if let Some(qiniu_config) = qiniu_config {
-                setup_config_watcher(&qiniu_config_path).ok();
-                return Some(qiniu_config);
-            } else {
-                error!(
-                    "Qiniu config file cannot be deserialized: {}",
-                    qiniu_config_path
-                );
-                return None;
+    match Config::from_env() {
+        Ok(Some(config)) => {
+            if let Some(qiniu_config_path) = env::var_os(QINIU_ENV) {
+                setup_config_watcher(qiniu_config_path).ok();
             }
-        } else {
-            error!("Qiniu config file cannot be open: {}", qiniu_config_path);
+            return Some(config);
+        }
+        Ok(None) => {
+            warn!("QINIU Env IS NOT ENABLED");
+            return None;
+        }
+        Err(err) => {
+            error!("Qiniu config cannot be loaded: {}", err);
             return None;
         }
-    } else {
-        warn!("QINIU Env IS NOT ENABLED");
-        return None;
     }
 
     fn setup_config_watcher(config_path: impl Into<PathBuf>) -> IOResult<()> {
@@ -195,13 +270,129 @@ fn load_config() -> Option<Config> {
 
         fn event_received(event: DebouncedEvent) {
             info!("Received event {:?} from Qiniu config file watcher", event);
-            for handle in CONFIG_UPDATE_HANDLERS.read().unwrap().iter() {
-                handle();
+            reload_config();
+        }
+    }
+}
+
+fn reload_config() {
+    let change = match Config::from_env() {
+        Ok(Some(new_config)) => {
+            let old_config = QINIU_CONFIG.read().unwrap().to_owned();
+            if old_config.as_ref() == Some(&new_config) {
+                info!("QINIU_CONFIG is not changed");
+                return;
+            }
+            // 校验函数可能需要访问当前配置，因此不在持有锁的情况下调用
+            let rejection = CONFIG_UPDATE_VALIDATORS
+                .read()
+                .unwrap()
+                .iter()
+                .find_map(|validator| validator(old_config.as_ref(), &new_config).err());
+            if let Some(reason) = rejection {
+                warn!(
+                    "QINIU_CONFIG reload is rejected, keep the current QINIU_CONFIG: {}",
+                    reason
+                );
+                ConfigChange::Rejected {
+                    new: Box::new(new_config),
+                    reason,
+                }
+            } else {
+                *QINIU_CONFIG.write().unwrap() = Some(new_config.to_owned());
+                info!("QINIU_CONFIG reloaded: {:?}", new_config);
+                for handle in CONFIG_UPDATE_HANDLERS.read().unwrap().iter() {
+                    handle();
+                }
+                ConfigChange::Updated {
+                    old: old_config.map(Box::new),
+                    new: Box::new(new_config),
+                }
             }
         }
+        Ok(None) => {
+            warn!("QINIU Env IS NOT ENABLED, keep the current QINIU_CONFIG");
+            return;
+        }
+        Err(err) => {
+            error!(
+                "Qiniu config cannot be reloaded, keep the current QINIU_CONFIG: {}",
+                err
+            );
+            ConfigChange::Failed(err)
+        }
+    };
+    for subscriber in CONFIG_CHANGE_SUBSCRIBERS.read().unwrap().iter() {
+        subscriber(&change);
     }
 }
 
+/// 七牛配置变更事件
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigChange {
+    /// 配置内容发生了变化，新的配置已经生效
+    Updated {
+        /// 变化前的配置
+        old: Option<Box<Config>>,
+        /// 变化后的配置
+        new: Box<Config>,
+    },
+    /// 配置文件发生了变化，但新的配置无法加载，将继续使用上一次成功加载的配置
+    Failed(ConfigError),
+    /// 新的配置被 [`validate_config_updates`] 注册的校验函数拒绝，将继续使用上一次成功加载的配置
+    Rejected {
+        /// 被拒绝的配置
+        new: Box<Config>,
+        /// 校验函数返回的拒绝原因
+        reason: String,
+    },
+}
+
+type ConfigChangeSubscriber = Box<dyn Fn(&ConfigChange) + Send + Sync + 'static>;
+static CONFIG_CHANGE_SUBSCRIBERS: Lazy<RwLock<Vec<ConfigChangeSubscriber>>> =
+    Lazy::new(Default::default);
+
+type ConfigUpdateValidator =
+    Box<dyn Fn(Option<&Config>, &Config) -> Result<(), String> + Send + Sync + 'static>;
+static CONFIG_UPDATE_VALIDATORS: Lazy<RwLock<Vec<ConfigUpdateValidator>>> =
+    Lazy::new(Default::default);
+
+/// 订阅七牛配置变更事件
+///
+/// 当 `QINIU` 环境变量指定的配置文件发生变化时，配置将被重新加载。
+/// 仅当重新加载的配置与当前配置的内容不同时，才会通知订阅者 [`ConfigChange::Updated`] 事件；
+/// 如果重新加载失败，则通知订阅者 [`ConfigChange::Failed`] 事件，此时当前配置保持不变。
+///
+/// 订阅者收到 [`ConfigChange::Updated`] 事件时新的配置已经生效，无法再拒绝，
+/// 如果需要拒绝能够解析但不符合预期的配置，请使用 [`validate_config_updates`]
+#[inline]
+pub fn subscribe_config_updates(subscriber: impl Fn(&ConfigChange) + Send + Sync + 'static) {
+    CONFIG_CHANGE_SUBSCRIBERS
+        .write()
+        .unwrap()
+        .push(Box::new(subscriber));
+}
+
+/// 注册七牛配置更新校验函数
+///
+/// 重新加载的配置在生效前将依次交给所有校验函数检查，参数为当前配置与新的配置。
+/// 任意一个校验函数返回错误，新的配置都将被拒绝，当前配置保持不变，订阅者将收到 [`ConfigChange::Rejected`] 事件
+#[inline]
+pub fn validate_config_updates(
+    validator: impl Fn(Option<&Config>, &Config) -> Result<(), String> + Send + Sync + 'static,
+) {
+    CONFIG_UPDATE_VALIDATORS
+        .write()
+        .unwrap()
+        .push(Box::new(validator));
+}
+
+#[inline]
+fn is_toml_path(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("toml"))
+}
+
 type ConfigUpdateHandler = fn();
 type ConfigUpdateHandlers = Vec<ConfigUpdateHandler>;
 static CONFIG_UPDATE_HANDLERS: Lazy<RwLock<ConfigUpdateHandlers>> = Lazy::new(Default::default);
@@ -211,6 +402,293 @@ pub(super) fn on_config_updated(handle: fn()) {
 }
 
 impl Config {
+    /// 从环境变量加载七牛配置信息
+    ///
+    /// 如果设置了 `QINIU` 环境变量，则首先从该变量指定的配置文件中读取配置（以 `.toml` 结尾的文件按 TOML 格式解析，否则按 JSON 格式解析），
+    /// 然后用下列环境变量逐项覆盖配置文件中的值。
+    /// 如果没有设置 `QINIU` 环境变量，则完全从下列环境变量中构建配置，此时 `QINIU_ACCESS_KEY`，`QINIU_SECRET_KEY` 和 `QINIU_BUCKET` 必须同时设置。
+    ///
+    /// 因此配置的优先级为：环境变量 > 配置文件 > 默认值。
+    /// 环境变量覆盖的是配置文件中的顶层配置项，即命名配置的共享默认值，命名配置中单独设置的配置项不受环境变量影响。
+    ///
+    /// | 环境变量 | 对应配置项 |
+    /// | --- | --- |
+    /// | `QINIU_ACCESS_KEY` | `access_key` |
+    /// | `QINIU_SECRET_KEY` | `secret_key` |
+    /// | `QINIU_BUCKET` | `bucket` |
+    /// | `QINIU_UP_URLS` | `up_urls`，多个 URL 之间用逗号分隔 |
+    /// | `QINIU_UC_URLS` | `uc_urls`，多个 URL 之间用逗号分隔 |
+    /// | `QINIU_PART_SIZE_MB` | `part_size`，单位为 MB |
+    /// | `QINIU_FORM_UPLOAD_THRESHOLD_MB` | `form_upload_threshold`，单位为 MB |
+    /// | `QINIU_USE_HTTPS` | `use_https`，取值为 `true` 或 `false` |
+    /// | `QINIU_RETRY` | `retry` |
+    /// | `QINIU_UP_TRIES` | `up_tries` |
+    /// | `QINIU_UC_TRIES` | `uc_tries` |
+    /// | `QINIU_UPDATE_INTERVAL_S` | `update_interval_s` |
+    /// | `QINIU_PUNISH_TIME_S` | `punish_time_s` |
+    /// | `QINIU_BASE_TIMEOUT_MS` | `base_timeout_ms` |
+    /// | `QINIU_DIAL_TIMEOUT_MS` | `dial_timeout_ms` |
+    /// | `QINIU_MAX_PUNISHED_TIMES` | `max_punished_times` |
+    /// | `QINIU_MAX_PUNISHED_HOSTS_PERCENT` | `max_punished_hosts_percent` |
+    /// | `QINIU_UP_TIMEOUT_MULTIPLE_PERCENT` | `base_timeout_multiple_percents` 中的 `up` |
+    /// | `QINIU_UC_TIMEOUT_MULTIPLE_PERCENT` | `base_timeout_multiple_percents` 中的 `uc` |
+    /// | `QINIU_PROXY` | `proxy` |
+    /// | `QINIU_NO_PROXY` | `no_proxy` |
+    /// | `QINIU_CA_CERT_PATHS` | `ca_cert_paths`，多个路径之间用逗号分隔 |
+    /// | `QINIU_CLIENT_CERT_PATH` | `client_cert_path` |
+    /// | `QINIU_CLIENT_KEY_PATH` | `client_key_path` |
+    /// | `QINIU_MIN_TLS_VERSION` | `min_tls_version` |
+    /// | `QINIU_RESOLVE_TTL_S` | `resolve_ttl_s` |
+    /// | `QINIU_RESOLVE_OVERRIDES` | `resolve_overrides`，格式为 `域名=IP`，多项之间用逗号分隔，同一域名可以出现多次 |
+    /// | `QINIU_MAX_UPLOAD_BYTES_PER_S` | `max_upload_bytes_per_s` |
+    ///
+    /// 如果既没有设置 `QINIU` 环境变量，也没有设置上述任何认证信息相关的环境变量，则返回 `Ok(None)`。
+    /// 配置文件无法读取或解析，环境变量的值不合法，或最终的配置无法通过 [`Config::validate`] 校验时，将返回错误。
+    #[inline]
+    pub fn from_env() -> ConfigResult<Option<Self>> {
+        Self::from_env_with(&|name| env::var_os(name))
+    }
+
+    fn from_env_with(get_env: &dyn Fn(&str) -> Option<OsString>) -> ConfigResult<Option<Self>> {
+        let get_env = |name: &'static str| -> ConfigResult<Option<String>> {
+            match get_env(name) {
+                Some(value) => match value.into_string() {
+                    Ok(value) if value.trim().is_empty() => Ok(None),
+                    Ok(value) => Ok(Some(value.trim().to_owned())),
+                    Err(value) => Err(ConfigError::InvalidEnvVar {
+                        name,
+                        message: format!("{:?} is not a valid unicode string", value),
+                    }),
+                },
+                None => Ok(None),
+            }
+        };
+        let parse_list_env = |name: &'static str| -> ConfigResult<Option<Vec<String>>> {
+            Ok(get_env(name)?.map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim())
+                    .filter(|item| !item.is_empty())
+                    .map(|item| item.to_owned())
+                    .collect()
+            }))
+        };
+
+        let access_key = get_env(QINIU_ACCESS_KEY_ENV)?;
+        let secret_key = get_env(QINIU_SECRET_KEY_ENV)?;
+        let bucket = get_env(QINIU_BUCKET_ENV)?;
+        let mut config = if let Some(qiniu_config_path) = get_env(QINIU_ENV)? {
+            let mut config = Self::load_from_path(&qiniu_config_path)?;
+            if let Some(access_key) = access_key {
+                config.access_key = access_key;
+            }
+            if let Some(secret_key) = secret_key {
+                config.secret_key = secret_key;
+            }
+            if let Some(bucket) = bucket {
+                config.bucket = bucket;
+            }
+            config
+        } else {
+            match (access_key, secret_key, bucket) {
+                (None, None, None) => return Ok(None),
+                (Some(access_key), Some(secret_key), Some(bucket)) => {
+                    ConfigBuilder::new(access_key, secret_key, bucket).build()
+                }
+                (None, _, _) => return Err(ConfigError::MissingEnvVar(QINIU_ACCESS_KEY_ENV)),
+                (_, None, _) => return Err(ConfigError::MissingEnvVar(QINIU_SECRET_KEY_ENV)),
+                (_, _, None) => return Err(ConfigError::MissingEnvVar(QINIU_BUCKET_ENV)),
+            }
+        };
+
+        if let Some(up_urls) = parse_list_env(QINIU_UP_URLS_ENV)? {
+            config.up_urls = Some(up_urls);
+        }
+        if let Some(uc_urls) = parse_list_env(QINIU_UC_URLS_ENV)? {
+            config.uc_urls = Some(uc_urls);
+        }
+        if let Some(part_size) = parse_env(&get_env, QINIU_PART_SIZE_MB_ENV)? {
+            config.part_size = Some(part_size);
+        }
+        if let Some(form_upload_threshold) =
+            parse_env(&get_env, QINIU_FORM_UPLOAD_THRESHOLD_MB_ENV)?
+        {
+            config.form_upload_threshold = Some(form_upload_threshold);
+        }
+        if let Some(use_https) = parse_env(&get_env, QINIU_USE_HTTPS_ENV)? {
+            config.use_https = Some(use_https);
+        }
+        if let Some(retry) = parse_env(&get_env, QINIU_RETRY_ENV)? {
+            config.retry = Some(retry);
+        }
+        if let Some(up_tries) = parse_env(&get_env, QINIU_UP_TRIES_ENV)? {
+            config.up_tries = Some(up_tries);
+        }
+        if let Some(uc_tries) = parse_env(&get_env, QINIU_UC_TRIES_ENV)? {
+            config.uc_tries = Some(uc_tries);
+        }
+        if let Some(update_interval_s) = parse_env(&get_env, QINIU_UPDATE_INTERVAL_S_ENV)? {
+            config.update_interval_s = Some(update_interval_s);
+        }
+        if let Some(punish_time_s) = parse_env(&get_env, QINIU_PUNISH_TIME_S_ENV)? {
+            config.punish_time_s = Some(punish_time_s);
+        }
+        if let Some(base_timeout_ms) = parse_env(&get_env, QINIU_BASE_TIMEOUT_MS_ENV)? {
+            config.base_timeout_ms = Some(base_timeout_ms);
+        }
+        if let Some(dial_timeout_ms) = parse_env(&get_env, QINIU_DIAL_TIMEOUT_MS_ENV)? {
+            config.dial_timeout_ms = Some(dial_timeout_ms);
+        }
+        if let Some(max_punished_times) = parse_env(&get_env, QINIU_MAX_PUNISHED_TIMES_ENV)? {
+            config.max_punished_times = Some(max_punished_times);
+        }
+        if let Some(max_punished_hosts_percent) =
+            parse_env(&get_env, QINIU_MAX_PUNISHED_HOSTS_PERCENT_ENV)?
+        {
+            config.max_punished_hosts_percent = Some(max_punished_hosts_percent);
+        }
+        if let Some(proxy) = get_env(QINIU_PROXY_ENV)? {
+            config.proxy = Some(proxy);
+        }
+        if let Some(no_proxy) = get_env(QINIU_NO_PROXY_ENV)? {
+            config.no_proxy = Some(no_proxy);
+        }
+        if let Some(ca_cert_paths) = parse_list_env(QINIU_CA_CERT_PATHS_ENV)? {
+            config.ca_cert_paths = Some(ca_cert_paths);
+        }
+        if let Some(client_cert_path) = get_env(QINIU_CLIENT_CERT_PATH_ENV)? {
+            config.client_cert_path = Some(client_cert_path);
+        }
+        if let Some(client_key_path) = get_env(QINIU_CLIENT_KEY_PATH_ENV)? {
+            config.client_key_path = Some(client_key_path);
+        }
+        if let Some(min_tls_version) = get_env(QINIU_MIN_TLS_VERSION_ENV)? {
+            config.min_tls_version = Some(min_tls_version);
+        }
+        if let Some(resolve_ttl_s) = parse_env(&get_env, QINIU_RESOLVE_TTL_S_ENV)? {
+            config.resolve_ttl_s = Some(resolve_ttl_s);
+        }
+        if let Some(max_upload_bytes_per_s) = parse_env(&get_env, QINIU_MAX_UPLOAD_BYTES_PER_S_ENV)?
+        {
+            config.max_upload_bytes_per_s = Some(max_upload_bytes_per_s);
+        }
+        if let Some(resolve_overrides) = parse_list_env(QINIU_RESOLVE_OVERRIDES_ENV)? {
+            let mut overrides = HashMap::<String, Vec<String>>::new();
+            for item in resolve_overrides {
+                let (domain, ip) =
+                    item.split_once('=')
+                        .ok_or_else(|| ConfigError::InvalidEnvVar {
+                            name: QINIU_RESOLVE_OVERRIDES_ENV,
+                            message: format!("{:?} is not in the format of domain=ip", item),
+                        })?;
+                overrides
+                    .entry(domain.trim().to_owned())
+                    .or_default()
+                    .push(ip.trim().to_owned());
+            }
+            config.resolve_overrides = Some(overrides);
+        }
+        for (name, service_name) in [
+            (QINIU_UP_TIMEOUT_MULTIPLE_PERCENT_ENV, ServiceName::Up),
+            (QINIU_UC_TIMEOUT_MULTIPLE_PERCENT_ENV, ServiceName::Uc),
+        ] {
+            if let Some(percent) = parse_env(&get_env, name)? {
+                config
+                    .base_timeout_multiple_percents
+                    .get_or_insert_with(Default::default)
+                    .insert(service_name.to_string(), percent);
+            }
+        }
+        config.validate()?;
+        return Ok(Some(config));
+
+        fn parse_env<T: FromStr>(
+            get_env: &dyn Fn(&'static str) -> ConfigResult<Option<String>>,
+            name: &'static str,
+        ) -> ConfigResult<Option<T>>
+        where
+            T::Err: fmt::Display,
+        {
+            get_env(name)?
+                .map(|value| {
+                    value.parse().map_err(|err| ConfigError::InvalidEnvVar {
+                        name,
+                        message: format!("{:?} is not valid: {}", value, err),
+                    })
+                })
+                .transpose()
+        }
+    }
+
+    /// 从配置文件加载七牛配置信息
+    ///
+    /// 以 `.toml` 结尾的文件按 TOML 格式解析，否则按 JSON 格式解析
+    pub fn load_from_path(path: impl AsRef<Path>) -> ConfigResult<Self> {
+        let path = path.as_ref();
+        let config = fs::read(path).map_err(|err| ConfigError::ReadFileError {
+            path: path.to_owned(),
+            source: err,
+        })?;
+        let config: Self = if is_toml_path(path) {
+            toml::from_slice(&config)?
+        } else {
+            serde_json::from_slice(&config)?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 从 TOML 字符串解析七牛配置信息
+    #[inline]
+    pub fn from_toml_str(config: &str) -> ConfigResult<Self> {
+        let config: Self = toml::from_str(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 从 JSON 字符串解析七牛配置信息
+    #[inline]
+    pub fn from_json_str(config: &str) -> ConfigResult<Self> {
+        let config: Self = serde_json::from_str(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 将七牛配置信息保存到配置文件
+    ///
+    /// 以 `.toml` 结尾的文件按 TOML 格式保存，否则按 JSON 格式保存。
+    /// 配置内容将先写入同目录下的临时文件，再重命名为目标文件，避免配置文件监听器读取到不完整的内容
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> ConfigResult<()> {
+        let path = path.as_ref();
+        let config = if is_toml_path(path) {
+            toml::to_vec(self)?
+        } else {
+            serde_json::to_vec_pretty(self).map_err(ConfigError::JsonEncodeError)?
+        };
+        let temp_path = {
+            let mut temp_path = path.as_os_str().to_owned();
+            temp_path.push(format!(
+                ".{}.{:016x}.tmp",
+                process::id(),
+                rand::random::<u64>()
+            ));
+            PathBuf::from(temp_path)
+        };
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .and_then(|mut temp_file| temp_file.write_all(&config))
+            .and_then(|_| fs::rename(&temp_path, path))
+            .map_err(|err| {
+                fs::remove_file(&temp_path).ok();
+                ConfigError::WriteFileError {
+                    path: path.to_owned(),
+                    source: err,
+                }
+            })
+    }
+
     /// 创建七牛配置信息构建器
     pub fn builder(
         access_key: impl Into<String>,
@@ -219,6 +697,243 @@ impl Config {
     ) -> ConfigBuilder {
         ConfigBuilder::new(access_key, secret_key, bucket)
     }
+
+    /// 获取所有命名配置的名称
+    #[inline]
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(|name| name.as_str())
+    }
+
+    /// 获取命名配置
+    ///
+    /// 返回的配置以顶层配置项作为默认值，并用命名配置中设置的配置项覆盖。如果命名配置不存在，则返回 None
+    pub fn profile(&self, name: &str) -> Option<Config> {
+        let profile = self.profiles.get(name)?;
+        let or_default = |value: &str, default: &str| {
+            if value.is_empty() {
+                default.to_owned()
+            } else {
+                value.to_owned()
+            }
+        };
+        Some(Config {
+            access_key: or_default(&profile.access_key, &self.access_key),
+            secret_key: or_default(&profile.secret_key, &self.secret_key),
+            bucket: or_default(&profile.bucket, &self.bucket),
+            up_urls: profile
+                .up_urls
+                .to_owned()
+                .or_else(|| self.up_urls.to_owned()),
+            uc_urls: profile
+                .uc_urls
+                .to_owned()
+                .or_else(|| self.uc_urls.to_owned()),
+            part_size: profile.part_size.or(self.part_size),
+            form_upload_threshold: profile.form_upload_threshold.or(self.form_upload_threshold),
+            use_https: profile.use_https.or(self.use_https),
+            retry: profile.retry.or(self.retry),
+            up_tries: profile.up_tries.or(self.up_tries),
+            uc_tries: profile.uc_tries.or(self.uc_tries),
+            update_interval_s: profile.update_interval_s.or(self.update_interval_s),
+            punish_time_s: profile.punish_time_s.or(self.punish_time_s),
+            base_timeout_ms: profile.base_timeout_ms.or(self.base_timeout_ms),
+            max_punished_times: profile.max_punished_times.or(self.max_punished_times),
+            max_punished_hosts_percent: profile
+                .max_punished_hosts_percent
+                .or(self.max_punished_hosts_percent),
+            proxy: profile.proxy.to_owned().or_else(|| self.proxy.to_owned()),
+            no_proxy: profile
+                .no_proxy
+                .to_owned()
+                .or_else(|| self.no_proxy.to_owned()),
+            ca_cert_paths: profile
+                .ca_cert_paths
+                .to_owned()
+                .or_else(|| self.ca_cert_paths.to_owned()),
+            client_cert_path: profile
+                .client_cert_path
+                .to_owned()
+                .or_else(|| self.client_cert_path.to_owned()),
+            client_key_path: profile
+                .client_key_path
+                .to_owned()
+                .or_else(|| self.client_key_path.to_owned()),
+            min_tls_version: profile
+                .min_tls_version
+                .to_owned()
+                .or_else(|| self.min_tls_version.to_owned()),
+            resolve_ttl_s: profile.resolve_ttl_s.or(self.resolve_ttl_s),
+            max_upload_bytes_per_s: profile
+                .max_upload_bytes_per_s
+                .or(self.max_upload_bytes_per_s),
+            base_timeout_multiple_percents: profile
+                .base_timeout_multiple_percents
+                .to_owned()
+                .or_else(|| self.base_timeout_multiple_percents.to_owned()),
+            resolve_overrides: profile
+                .resolve_overrides
+                .to_owned()
+                .or_else(|| self.resolve_overrides.to_owned()),
+            dial_timeout_ms: profile.dial_timeout_ms.or(self.dial_timeout_ms),
+            profiles: Default::default(),
+        })
+    }
+
+    /// 校验七牛配置信息
+    ///
+    /// 检查配置项是否在合法范围内，命名配置将与顶层配置项合并后再做校验。
+    /// 通过 [`Config::load_from_path`]，[`Config::from_toml_str`]，[`Config::from_json_str`] 和 [`Config::from_env`] 加载的配置都已经过校验
+    pub fn validate(&self) -> ConfigResult<()> {
+        self.validate_self()?;
+        for name in self.profiles.keys() {
+            if let Some(profile) = self.profile(name) {
+                profile
+                    .validate_self()
+                    .map_err(|err| ConfigError::InvalidProfile {
+                        name: name.to_owned(),
+                        source: Box::new(err),
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_self(&self) -> ConfigResult<()> {
+        let invalid = |field: &'static str, message: String| {
+            Err(ConfigError::InvalidConfig { field, message })
+        };
+        if let Some(part_size) = self.part_size {
+            if !(MIN_PART_SIZE_MB..=MAX_PART_SIZE_MB).contains(&part_size) {
+                return invalid(
+                    "part_size",
+                    format!(
+                        "{} MB is out of range [{}, {}] MB",
+                        part_size, MIN_PART_SIZE_MB, MAX_PART_SIZE_MB
+                    ),
+                );
+            }
+        }
+        if let Some(form_upload_threshold) = self.form_upload_threshold {
+            if form_upload_threshold > MAX_FORM_UPLOAD_THRESHOLD_MB {
+                return invalid(
+                    "form_upload_threshold",
+                    format!(
+                        "{} MB is greater than {} MB",
+                        form_upload_threshold, MAX_FORM_UPLOAD_THRESHOLD_MB
+                    ),
+                );
+            }
+        }
+        for (field, tries) in [
+            ("retry", self.retry),
+            ("up_tries", self.up_tries),
+            ("uc_tries", self.uc_tries),
+        ] {
+            if tries == Some(0) {
+                return invalid(field, "must be greater than 0".to_owned());
+            }
+        }
+        for (field, duration) in [
+            ("update_interval_s", self.update_interval_s),
+            ("base_timeout_ms", self.base_timeout_ms),
+            ("dial_timeout_ms", self.dial_timeout_ms),
+            ("resolve_ttl_s", self.resolve_ttl_s),
+        ] {
+            if duration == Some(0) {
+                return invalid(field, "must be greater than 0".to_owned());
+            }
+        }
+        if let Some(percent) = self.max_punished_hosts_percent {
+            if percent > 100 {
+                return invalid(
+                    "max_punished_hosts_percent",
+                    format!("{} is greater than 100", percent),
+                );
+            }
+        }
+        for (field, urls) in [("up_urls", &self.up_urls), ("uc_urls", &self.uc_urls)] {
+            for url in urls.iter().flatten() {
+                if let Err(err) = Url::parse(url) {
+                    return invalid(field, format!("{:?} is not a valid URL: {}", url, err));
+                }
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            if let Err(err) = Proxy::all(proxy.as_str()) {
+                return invalid(
+                    "proxy",
+                    format!("{:?} is not a valid proxy: {}", proxy, err),
+                );
+            }
+        }
+        if self.client_cert_path.is_some() != self.client_key_path.is_some() {
+            return invalid(
+                "client_cert_path",
+                "client_cert_path and client_key_path must be set together".to_owned(),
+            );
+        }
+        if let Some(min_tls_version) = &self.min_tls_version {
+            if min_tls_version == "1.3" {
+                return invalid(
+                    "min_tls_version",
+                    "TLS 1.3 is not supported as the minimum version by the native TLS backend"
+                        .to_owned(),
+                );
+            }
+            if parse_tls_version(min_tls_version).is_none() {
+                return invalid(
+                    "min_tls_version",
+                    format!(
+                        "{:?} is not one of \"1.0\", \"1.1\" and \"1.2\"",
+                        min_tls_version
+                    ),
+                );
+            }
+        }
+        if let Some(percents) = &self.base_timeout_multiple_percents {
+            for (service_name, &percent) in percents.iter() {
+                if service_name != &ServiceName::Up.to_string()
+                    && service_name != &ServiceName::Uc.to_string()
+                {
+                    return invalid(
+                        "base_timeout_multiple_percents",
+                        format!("unknown service name {:?}", service_name),
+                    );
+                }
+                if percent == 0 {
+                    return invalid(
+                        "base_timeout_multiple_percents",
+                        format!("percent of {} must be greater than 0", service_name),
+                    );
+                }
+            }
+        }
+        for (domain, ips) in self.resolve_overrides.iter().flatten() {
+            if ips.is_empty() {
+                return invalid(
+                    "resolve_overrides",
+                    format!("no IP address is specified for {:?}", domain),
+                );
+            }
+            for ip in ips {
+                if let Err(err) = ip.parse::<IpAddr>() {
+                    return invalid(
+                        "resolve_overrides",
+                        format!(
+                            "{:?} of {:?} is not a valid IP address: {}",
+                            ip, domain, err
+                        ),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn has_credential(&self) -> bool {
+        !self.access_key.is_empty() && !self.secret_key.is_empty() && !self.bucket.is_empty()
+    }
 }
 
 /// 七牛配置信息构建器
@@ -240,14 +955,7 @@ impl ConfigBuilder {
                 access_key: access_key.into(),
                 secret_key: secret_key.into(),
                 bucket: bucket.into(),
-                up_urls: None,
-                uc_urls: None,
-                part_size: None,
-                retry: None,
-                punish_time_s: None,
-                base_timeout_ms: None,
-                base_timeout_multiple_percents: None,
-                dial_timeout_ms: None,
+                ..Default::default()
             },
         }
     }
@@ -258,6 +966,15 @@ impl ConfigBuilder {
         self.inner
     }
 
+    /// 添加命名配置
+    ///
+    /// 命名配置中值为空字符串的 `access_key`，`secret_key` 和 `bucket`，以及未设置的配置项，都将使用当前配置中的值
+    #[inline]
+    pub fn add_profile(mut self, name: impl Into<String>, profile: Config) -> Self {
+        self.inner.profiles.insert(name.into(), profile);
+        self
+    }
+
     /// 配置 UP 服务器域名列表
     #[inline]
     pub fn up_urls(mut self, up_urls: Vec<String>) -> Self {
@@ -279,6 +996,22 @@ impl ConfigBuilder {
         self
     }
 
+    /// 配置表单上传的阈值，单位为 MB，默认与分片大小相同
+    ///
+    /// 数据长度不超过该阈值时使用表单上传，否则使用分片上传，配置为 0 表示总是使用分片上传
+    #[inline]
+    pub fn form_upload_threshold(mut self, form_upload_threshold: u64) -> Self {
+        self.inner.form_upload_threshold = Some(form_upload_threshold);
+        self
+    }
+
+    /// 配置是否使用 HTTPS 协议来访问 UP / UC 服务器，默认为 false
+    #[inline]
+    pub fn use_https(mut self, use_https: bool) -> Self {
+        self.inner.use_https = Some(use_https);
+        self
+    }
+
     /// 配置 UP 和 UC 服务器访问重试次数，默认为 10
     #[inline]
     pub fn retry(mut self, retry: usize) -> Self {
@@ -286,10 +1019,45 @@ impl ConfigBuilder {
         self
     }
 
+    /// 配置 UP 服务器访问重试次数，优先级高于 [`ConfigBuilder::retry`]
+    #[inline]
+    pub fn up_tries(mut self, up_tries: usize) -> Self {
+        self.inner.up_tries = Some(up_tries);
+        self
+    }
+
+    /// 配置 UC 服务器访问重试次数，优先级高于 [`ConfigBuilder::retry`]
+    #[inline]
+    pub fn uc_tries(mut self, uc_tries: usize) -> Self {
+        self.inner.uc_tries = Some(uc_tries);
+        self
+    }
+
+    /// 配置 UC 查询的频率，默认为 60 秒
+    #[inline]
+    pub fn update_interval_s(mut self, update_interval: Duration) -> Self {
+        self.inner.update_interval_s = Some(update_interval.as_secs());
+        self
+    }
+
     /// 配置域名访问失败后的惩罚时长，默认为 30 分钟
     #[inline]
     pub fn punish_time_s(mut self, punish_duration: Duration) -> Self {
-        self.inner.punish_time_s = Some(punish_duration.as_millis().try_into().unwrap_or(u64::MAX));
+        self.inner.punish_time_s = Some(punish_duration.as_secs());
+        self
+    }
+
+    /// 配置失败域名的最大重试次数，默认为 5
+    #[inline]
+    pub fn max_punished_times(mut self, max_punished_times: usize) -> Self {
+        self.inner.max_punished_times = Some(max_punished_times);
+        self
+    }
+
+    /// 配置被惩罚的域名最大比例，默认为 50
+    #[inline]
+    pub fn max_punished_hosts_percent(mut self, max_punished_hosts_percent: u8) -> Self {
+        self.inner.max_punished_hosts_percent = Some(max_punished_hosts_percent);
         self
     }
 
@@ -333,6 +1101,94 @@ impl ConfigBuilder {
         );
         self
     }
+
+    /// 配置访问 UP / UC 服务器时使用的代理，支持 `http://`，`https://`，`socks5://` 和 `socks5h://` 协议的代理 URL
+    #[inline]
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.inner.proxy = Some(proxy.into());
+        self
+    }
+
+    /// 配置不使用代理的域名列表，格式与 `NO_PROXY` 环境变量相同，仅在配置了代理时生效
+    #[inline]
+    pub fn no_proxy(mut self, no_proxy: impl Into<String>) -> Self {
+        self.inner.no_proxy = Some(no_proxy.into());
+        self
+    }
+
+    /// 追加额外信任的 PEM 格式的根证书文件路径，文件中可以包含多个证书
+    #[inline]
+    pub fn add_ca_cert_path(mut self, ca_cert_path: impl Into<String>) -> Self {
+        self.inner
+            .ca_cert_paths
+            .get_or_insert_with(Default::default)
+            .push(ca_cert_path.into());
+        self
+    }
+
+    /// 配置双向 TLS 认证使用的 PEM 格式的客户端证书路径和 PKCS #8 格式的私钥路径
+    #[inline]
+    pub fn client_identity_paths(
+        mut self,
+        client_cert_path: impl Into<String>,
+        client_key_path: impl Into<String>,
+    ) -> Self {
+        self.inner.client_cert_path = Some(client_cert_path.into());
+        self.inner.client_key_path = Some(client_key_path.into());
+        self
+    }
+
+    /// 配置最低 TLS 版本，取值为 `1.0`，`1.1` 或 `1.2`
+    ///
+    /// 当前使用的 Native TLS 后端不支持将 TLS 1.3 设为最低版本，配置为 `1.3` 将无法通过校验
+    #[inline]
+    pub fn min_tls_version(mut self, min_tls_version: impl Into<String>) -> Self {
+        self.inner.min_tls_version = Some(min_tls_version.into());
+        self
+    }
+
+    /// 配置域名解析结果的缓存时长，默认为 120 秒
+    ///
+    /// 配置该项或 `resolve_overrides` 后，将以（域名，IP）为单位选择和惩罚服务器
+    #[inline]
+    pub fn resolve_ttl_s(mut self, resolve_ttl_duration: Duration) -> Self {
+        self.inner.resolve_ttl_s = Some(resolve_ttl_duration.as_secs());
+        self
+    }
+
+    /// 追加静态域名解析规则，指定域名将直接解析为给定的 IP 地址，通常用于对接本地的测试服务器
+    #[inline]
+    pub fn add_resolve_override(
+        mut self,
+        domain: impl Into<String>,
+        ip: impl Into<String>,
+    ) -> Self {
+        self.inner
+            .resolve_overrides
+            .get_or_insert_with(Default::default)
+            .entry(domain.into())
+            .or_default()
+            .push(ip.into());
+        self
+    }
+
+    /// 配置上传带宽上限，单位为字节每秒，0 表示不限制
+    ///
+    /// 该上限由同一个对象上传器发起的所有上传共享
+    #[inline]
+    pub fn max_upload_bytes_per_s(mut self, max_upload_bytes_per_s: u64) -> Self {
+        self.inner.max_upload_bytes_per_s = Some(max_upload_bytes_per_s);
+        self
+    }
+}
+
+fn parse_tls_version(version: &str) -> Option<TlsVersion> {
+    match version {
+        "1.0" => Some(TlsVersion::TLS_1_0),
+        "1.1" => Some(TlsVersion::TLS_1_1),
+        "1.2" => Some(TlsVersion::TLS_1_2),
+        _ => None,
+    }
 }
 
 #[inline]
@@ -341,10 +1197,25 @@ pub(super) fn build_uploader_builder_from_env() -> Option<UploaderBuilder> {
         .read()
         .unwrap()
         .as_ref()
-        .map(build_uploader_builder_from_config)
+        .filter(|config| config.has_credential())
+        .and_then(|config| {
+            build_uploader_builder_from_config(config)
+                .tap_err(|err| error!("UploaderBuilder cannot be built from QINIU_CONFIG: {}", err))
+                .ok()
+        })
+}
+
+#[inline]
+pub(super) fn load_env_profile_config(profile: &str) -> Option<Config> {
+    QINIU_CONFIG
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|config| config.profile(profile))
+        .filter(|config| config.has_credential())
 }
 
-fn build_uploader_builder_from_config(config: &Config) -> UploaderBuilder {
+pub(super) fn build_uploader_builder_from_config(config: &Config) -> ConfigResult<UploaderBuilder> {
     let mut builder = UploaderBuilder::new(&config.access_key, &config.secret_key, &config.bucket);
     if let Some(up_urls) = config.up_urls.as_ref() {
         builder = builder.up_urls(up_urls.to_owned());
@@ -352,10 +1223,23 @@ fn build_uploader_builder_from_config(config: &Config) -> UploaderBuilder {
     if let Some(uc_urls) = config.uc_urls.as_ref() {
         builder = builder.uc_urls(uc_urls.to_owned());
     }
-    if let Some(retry) = config.retry.as_ref() {
-        builder = builder
-            .up_tries(retry.to_owned())
-            .uc_tries(retry.to_owned());
+    if let Some(use_https) = config.use_https {
+        builder = builder.use_https(use_https);
+    }
+    if let Some(up_tries) = config.up_tries.or(config.retry) {
+        builder = builder.up_tries(up_tries);
+    }
+    if let Some(uc_tries) = config.uc_tries.or(config.retry) {
+        builder = builder.uc_tries(uc_tries);
+    }
+    if let Some(update_interval_s) = config.update_interval_s {
+        builder = builder.update_interval(Duration::from_secs(update_interval_s));
+    }
+    if let Some(max_punished_times) = config.max_punished_times {
+        builder = builder.max_punished_times(max_punished_times);
+    }
+    if let Some(max_punished_hosts_percent) = config.max_punished_hosts_percent {
+        builder = builder.max_punished_hosts_percent(max_punished_hosts_percent);
     }
     if let Some(base_timeout_multiple_percents) = config.base_timeout_multiple_percents.as_ref() {
         if let Some(&uc_timeout_multiple_percents) =
@@ -379,7 +1263,82 @@ fn build_uploader_builder_from_config(config: &Config) -> UploaderBuilder {
     if let Some(part_size) = config.part_size.as_ref() {
         builder = builder.part_size(part_size.to_owned() * (1 << 20));
     }
-    builder
+    if let Some(form_upload_threshold) = config.form_upload_threshold {
+        builder = builder.form_upload_threshold(form_upload_threshold.saturating_mul(1 << 20));
+    }
+    if let Some(proxy) = config.proxy.as_ref() {
+        let proxy = Proxy::all(proxy.as_str())
+            .map_err(|err| ConfigError::InvalidConfig {
+                field: "proxy",
+                message: err.to_string(),
+            })?
+            .no_proxy(config.no_proxy.as_deref().and_then(NoProxy::from_string));
+        builder = builder.proxy(proxy);
+    }
+    for ca_cert_path in config.ca_cert_paths.iter().flatten() {
+        let certificates =
+            Certificate::from_pem_bundle(&read_file(ca_cert_path)?).map_err(|err| {
+                ConfigError::InvalidConfig {
+                    field: "ca_cert_paths",
+                    message: format!("{:?} is not a valid PEM certificate: {}", ca_cert_path, err),
+                }
+            })?;
+        if certificates.is_empty() {
+            return Err(ConfigError::InvalidConfig {
+                field: "ca_cert_paths",
+                message: format!("no certificate is found in {:?}", ca_cert_path),
+            });
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if let (Some(client_cert_path), Some(client_key_path)) = (
+        config.client_cert_path.as_ref(),
+        config.client_key_path.as_ref(),
+    ) {
+        let identity =
+            Identity::from_pkcs8_pem(&read_file(client_cert_path)?, &read_file(client_key_path)?)
+                .map_err(|err| ConfigError::InvalidConfig {
+                field: "client_cert_path",
+                message: err.to_string(),
+            })?;
+        builder = builder.identity(identity);
+    }
+    if let Some(min_tls_version) = config.min_tls_version.as_deref() {
+        let min_tls_version =
+            parse_tls_version(min_tls_version).ok_or_else(|| ConfigError::InvalidConfig {
+                field: "min_tls_version",
+                message: format!("{:?} is not a valid TLS version", min_tls_version),
+            })?;
+        builder = builder.min_tls_version(min_tls_version);
+    }
+    if let Some(max_upload_bytes_per_s) = config.max_upload_bytes_per_s {
+        builder = builder.rate_limiter(RateLimiter::new(max_upload_bytes_per_s));
+    }
+    if let Some(resolve_ttl_s) = config.resolve_ttl_s {
+        builder = builder.resolve_ttl(Duration::from_secs(resolve_ttl_s));
+    }
+    for (domain, ips) in config.resolve_overrides.iter().flatten() {
+        let ips = ips
+            .iter()
+            .map(|ip| {
+                ip.parse().map_err(|err| ConfigError::InvalidConfig {
+                    field: "resolve_overrides",
+                    message: format!("{:?} is not a valid IP address: {}", ip, err),
+                })
+            })
+            .collect::<ConfigResult<Vec<IpAddr>>>()?;
+        builder = builder.add_resolve_override(domain, ips);
+    }
+    return Ok(builder);
+
+    fn read_file(path: &str) -> ConfigResult<Vec<u8>> {
+        fs::read(path).map_err(|err| ConfigError::ReadFileError {
+            path: path.into(),
+            source: err,
+        })
+    }
 }
 
 /// 服务名称
@@ -409,7 +1368,10 @@ mod tests {
         error::Error,
         fs::{remove_file, rename, OpenOptions},
         io::Write,
-        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+        sync::{
+            atomic::{AtomicUsize, Ordering::Relaxed},
+            Arc, Mutex,
+        },
         thread::sleep,
     };
     use tempfile::Builder as TempFileBuilder;
@@ -423,13 +1385,7 @@ mod tests {
             secret_key: "test-sk-1".into(),
             bucket: "test-bucket-1".into(),
             up_urls: Some(vec!["http://up1.com".into(), "http://up2.com".into()]),
-            uc_urls: Default::default(),
-            retry: Default::default(),
-            punish_time_s: Default::default(),
-            base_timeout_ms: Default::default(),
-            dial_timeout_ms: Default::default(),
-            part_size: Default::default(),
-            base_timeout_multiple_percents: Default::default(),
+            ..Default::default()
         };
         let tempfile_path = {
             let mut tempfile = TempFileBuilder::new().suffix(".toml").tempfile()?;
@@ -444,6 +1400,31 @@ mod tests {
 
         let loaded = load_config().unwrap();
         assert_eq!(loaded, config);
+        *QINIU_CONFIG.write().unwrap() = Some(loaded);
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        {
+            let changes = changes.to_owned();
+            subscribe_config_updates(move |change| {
+                changes.lock().unwrap().push(match change {
+                    ConfigChange::Updated { old, new } => Ok((
+                        old.as_ref().map(|old| old.access_key.to_owned()),
+                        new.access_key.to_owned(),
+                    )),
+                    ConfigChange::Failed(err) => Err(err.to_string()),
+                    ConfigChange::Rejected { new, reason } => {
+                        Err(format!("{} is rejected: {}", new.access_key, reason))
+                    }
+                });
+            });
+        }
+        validate_config_updates(|_, new| {
+            if new.access_key == "test-ak-rejected" {
+                Err("access key is revoked".to_owned())
+            } else {
+                Ok(())
+            }
+        });
 
         on_config_updated(|| {
             UPDATED.fetch_add(1, Relaxed);
@@ -482,6 +1463,7 @@ mod tests {
             let mut tempfile = OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .open(&tempfile_path)?;
             tempfile.write_all(&toml::to_vec(&config)?)?;
             tempfile.flush()?;
@@ -490,6 +1472,10 @@ mod tests {
         sleep(Duration::from_secs(1));
         assert_eq!(UPDATED.load(Relaxed), 6);
 
+        config.access_key = "test-ak-4".into();
+        config.secret_key = "test-sk-4".into();
+        config.bucket = "test-bucket-4".into();
+
         {
             let new_tempfile_path = {
                 let mut new_path = tempfile_path.to_owned().into_os_string();
@@ -499,6 +1485,7 @@ mod tests {
             let mut tempfile = OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .open(&new_tempfile_path)?;
             tempfile.write_all(&toml::to_vec(&config)?)?;
             tempfile.flush()?;
@@ -517,6 +1504,7 @@ mod tests {
             let mut tempfile = OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .open(&new_tempfile_path)?;
             tempfile.write_all(&toml::to_vec(&config)?)?;
             tempfile.flush()?;
@@ -524,11 +1512,453 @@ mod tests {
             rename(&new_tempfile_path, &tempfile_path)?;
         }
 
+        // 配置内容没有变化，不会触发更新
+        sleep(Duration::from_secs(1));
+        assert_eq!(UPDATED.load(Relaxed), 9);
+
+        {
+            let mut tempfile = OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(&tempfile_path)?;
+            tempfile.write_all(b"access_key = ")?;
+            tempfile.flush()?;
+        }
+
+        // 配置无法解析，继续使用上一次成功加载的配置
+        sleep(Duration::from_secs(1));
+        assert_eq!(UPDATED.load(Relaxed), 9);
+        assert_eq!(
+            QINIU_CONFIG.read().unwrap().as_ref().unwrap().access_key,
+            "test-ak-4"
+        );
+
+        config.access_key = "test-ak-rejected".into();
+        {
+            let mut tempfile = OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(&tempfile_path)?;
+            tempfile.write_all(&toml::to_vec(&config)?)?;
+            tempfile.flush()?;
+        }
+
+        // 配置被校验函数拒绝，继续使用上一次成功加载的配置
         sleep(Duration::from_secs(1));
-        assert_eq!(UPDATED.load(Relaxed), 12);
+        assert_eq!(UPDATED.load(Relaxed), 9);
+        assert_eq!(
+            QINIU_CONFIG.read().unwrap().as_ref().unwrap().access_key,
+            "test-ak-4"
+        );
+
+        {
+            let changes = changes.lock().unwrap();
+            assert_eq!(changes.len(), 5);
+            assert_eq!(changes[0].as_ref().unwrap().1, "test-ak-2");
+            assert_eq!(
+                changes[1].as_ref().unwrap(),
+                &(Some("test-ak-2".to_owned()), "test-ak-3".to_owned())
+            );
+            assert_eq!(
+                changes[2].as_ref().unwrap(),
+                &(Some("test-ak-3".to_owned()), "test-ak-4".to_owned())
+            );
+            assert!(changes[3].is_err());
+            assert_eq!(
+                changes[4].as_ref().unwrap_err(),
+                "test-ak-rejected is rejected: access key is revoked"
+            );
+        }
 
         remove_file(&tempfile_path)?;
 
         Ok(())
     }
+
+    #[test]
+    fn test_load_config_from_env_vars() -> Result<(), Box<dyn Error>> {
+        env_logger::try_init().ok();
+
+        assert_eq!(Config::from_env_with(&|_| None)?, None);
+
+        let envs = make_envs(&[
+            (QINIU_ACCESS_KEY_ENV, "test-ak-1"),
+            (QINIU_SECRET_KEY_ENV, "test-sk-1"),
+            (QINIU_BUCKET_ENV, "test-bucket-1"),
+            (QINIU_UP_URLS_ENV, "http://up1.com, http://up2.com"),
+            (QINIU_PART_SIZE_MB_ENV, "16"),
+            (QINIU_FORM_UPLOAD_THRESHOLD_MB_ENV, "1"),
+            (QINIU_USE_HTTPS_ENV, "true"),
+            (QINIU_UC_TIMEOUT_MULTIPLE_PERCENT_ENV, "200"),
+            (QINIU_PROXY_ENV, "http://proxy.internal:3128"),
+            (QINIU_NO_PROXY_ENV, "localhost"),
+            (QINIU_CA_CERT_PATHS_ENV, "/etc/ca-1.pem, /etc/ca-2.pem"),
+            (QINIU_MIN_TLS_VERSION_ENV, "1.2"),
+            (QINIU_RESOLVE_TTL_S_ENV, "60"),
+            (QINIU_MAX_UPLOAD_BYTES_PER_S_ENV, "1048576"),
+            (
+                QINIU_RESOLVE_OVERRIDES_ENV,
+                "up1.com=127.0.0.1, up1.com=::1, up2.com=127.0.0.2",
+            ),
+        ]);
+        let config = Config::from_env_with(&|name| envs.get(name).cloned())?.unwrap();
+        assert_eq!(config.access_key, "test-ak-1");
+        assert_eq!(config.secret_key, "test-sk-1");
+        assert_eq!(config.bucket, "test-bucket-1");
+        assert_eq!(
+            config.up_urls,
+            Some(vec![
+                "http://up1.com".to_owned(),
+                "http://up2.com".to_owned()
+            ])
+        );
+        assert_eq!(config.uc_urls, None);
+        assert_eq!(config.part_size, Some(16));
+        assert_eq!(config.form_upload_threshold, Some(1));
+        assert_eq!(config.use_https, Some(true));
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy.internal:3128"));
+        assert_eq!(config.no_proxy.as_deref(), Some("localhost"));
+        assert_eq!(
+            config.ca_cert_paths,
+            Some(vec!["/etc/ca-1.pem".to_owned(), "/etc/ca-2.pem".to_owned()])
+        );
+        assert_eq!(config.min_tls_version.as_deref(), Some("1.2"));
+        assert_eq!(config.resolve_ttl_s, Some(60));
+        assert_eq!(config.max_upload_bytes_per_s, Some(1 << 20));
+        {
+            let resolve_overrides = config.resolve_overrides.as_ref().unwrap();
+            assert_eq!(
+                resolve_overrides.get("up1.com"),
+                Some(&vec!["127.0.0.1".to_owned(), "::1".to_owned()])
+            );
+            assert_eq!(
+                resolve_overrides.get("up2.com"),
+                Some(&vec!["127.0.0.2".to_owned()])
+            );
+        }
+        assert_eq!(
+            config
+                .base_timeout_multiple_percents
+                .unwrap()
+                .get(&ServiceName::Uc.to_string()),
+            Some(&200)
+        );
+
+        let envs = make_envs(&[
+            (QINIU_ACCESS_KEY_ENV, "test-ak-1"),
+            (QINIU_BUCKET_ENV, "test-bucket-1"),
+        ]);
+        match Config::from_env_with(&|name| envs.get(name).cloned()) {
+            Err(ConfigError::MissingEnvVar(name)) => assert_eq!(name, QINIU_SECRET_KEY_ENV),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let envs = make_envs(&[
+            (QINIU_ACCESS_KEY_ENV, "test-ak-1"),
+            (QINIU_SECRET_KEY_ENV, "test-sk-1"),
+            (QINIU_BUCKET_ENV, "test-bucket-1"),
+            (QINIU_RETRY_ENV, "ten"),
+        ]);
+        match Config::from_env_with(&|name| envs.get(name).cloned()) {
+            Err(ConfigError::InvalidEnvVar { name, .. }) => assert_eq!(name, QINIU_RETRY_ENV),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let envs = make_envs(&[
+            (QINIU_ACCESS_KEY_ENV, "test-ak-1"),
+            (QINIU_SECRET_KEY_ENV, "test-sk-1"),
+            (QINIU_BUCKET_ENV, "test-bucket-1"),
+            (QINIU_RESOLVE_OVERRIDES_ENV, "up1.com"),
+        ]);
+        match Config::from_env_with(&|name| envs.get(name).cloned()) {
+            Err(ConfigError::InvalidEnvVar { name, .. }) => {
+                assert_eq!(name, QINIU_RESOLVE_OVERRIDES_ENV)
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_config_with_env_overrides() -> Result<(), Box<dyn Error>> {
+        env_logger::try_init().ok();
+
+        let config = ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+            .up_urls(vec!["http://up1.com".into()])
+            .retry(3)
+            .build();
+        let tempfile = {
+            let mut tempfile = TempFileBuilder::new().suffix(".json").tempfile()?;
+            tempfile.write_all(&serde_json::to_vec(&config)?)?;
+            tempfile.flush()?;
+            tempfile
+        };
+        let qiniu_config_path = tempfile.path().to_string_lossy().into_owned();
+
+        let envs = make_envs(&[
+            (QINIU_ENV, &qiniu_config_path),
+            (QINIU_BUCKET_ENV, "test-bucket-2"),
+            (QINIU_RETRY_ENV, "5"),
+        ]);
+        let loaded = Config::from_env_with(&|name| envs.get(name).cloned())?.unwrap();
+        assert_eq!(loaded.access_key, "test-ak-1");
+        assert_eq!(loaded.bucket, "test-bucket-2");
+        assert_eq!(loaded.up_urls, Some(vec!["http://up1.com".to_owned()]));
+        assert_eq!(loaded.retry, Some(5));
+
+        let envs = make_envs(&[(QINIU_ENV, "/not/existed/qiniu.toml")]);
+        match Config::from_env_with(&|name| envs.get(name).cloned()) {
+            Err(ConfigError::ReadFileError { .. }) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_profiles() -> Result<(), Box<dyn Error>> {
+        env_logger::try_init().ok();
+
+        let config: Config = toml::from_str(
+            r#"
+            access_key = "test-ak-1"
+            secret_key = "test-sk-1"
+            up_urls = ["http://up1.com"]
+            retry = 3
+
+            [profiles.images]
+            bucket = "images"
+
+            [profiles.archive]
+            ak = "test-ak-2"
+            sk = "test-sk-2"
+            bucket = "archive"
+            up_hosts = ["http://up2.com"]
+            part_size = 16
+            "#,
+        )?;
+        assert!(!config.has_credential());
+        let mut profile_names = config.profile_names().collect::<Vec<_>>();
+        profile_names.sort_unstable();
+        assert_eq!(profile_names, vec!["archive", "images"]);
+
+        let images = config.profile("images").unwrap();
+        assert_eq!(images.access_key, "test-ak-1");
+        assert_eq!(images.secret_key, "test-sk-1");
+        assert_eq!(images.bucket, "images");
+        assert_eq!(images.up_urls, Some(vec!["http://up1.com".to_owned()]));
+        assert_eq!(images.retry, Some(3));
+        assert_eq!(images.part_size, None);
+        assert!(images.has_credential());
+
+        let archive = config.profile("archive").unwrap();
+        assert_eq!(archive.access_key, "test-ak-2");
+        assert_eq!(archive.secret_key, "test-sk-2");
+        assert_eq!(archive.bucket, "archive");
+        assert_eq!(archive.up_urls, Some(vec!["http://up2.com".to_owned()]));
+        assert_eq!(archive.retry, Some(3));
+        assert_eq!(archive.part_size, Some(16));
+
+        assert!(config.profile("videos").is_none());
+
+        let built = ConfigBuilder::new("test-ak-1", "test-sk-1", "")
+            .up_urls(vec!["http://up1.com".into()])
+            .retry(3)
+            .add_profile("images", ConfigBuilder::new("", "", "images").build())
+            .build();
+        assert_eq!(built.profile("images"), Some(images));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_and_load_config() -> Result<(), Box<dyn Error>> {
+        env_logger::try_init().ok();
+
+        let config = ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+            .up_urls(vec!["http://up1.com".into()])
+            .part_size(8)
+            .add_base_timeout_multiple_percent(ServiceName::Up, 500)
+            .resolve_ttl_s(Duration::from_secs(60))
+            .add_resolve_override("up1.com", "127.0.0.1")
+            .add_profile(
+                "images",
+                ConfigBuilder::new("", "", "images")
+                    .add_base_timeout_multiple_percent(ServiceName::Uc, 200)
+                    .build(),
+            )
+            .build();
+        let temp_dir = TempFileBuilder::new().tempdir()?;
+        for file_name in ["qiniu.toml", "qiniu.json"].iter() {
+            let path = temp_dir.path().join(file_name);
+            config.save_to_path(&path)?;
+            assert_eq!(Config::load_from_path(&path)?, config);
+        }
+
+        let path = temp_dir.path().join("qiniu.toml");
+        let threads = (0..8)
+            .map(|_| {
+                let config = config.to_owned();
+                let path = path.to_owned();
+                std::thread::spawn(move || config.save_to_path(path))
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+        assert_eq!(Config::load_from_path(&path)?, config);
+        assert_eq!(temp_dir.path().read_dir()?.count(), 2);
+
+        let toml_config = fs::read_to_string(temp_dir.path().join("qiniu.toml"))?;
+        assert_eq!(Config::from_toml_str(&toml_config)?, config);
+        let json_config = fs::read_to_string(temp_dir.path().join("qiniu.json"))?;
+        assert_eq!(Config::from_json_str(&json_config)?, config);
+
+        assert!(matches!(
+            Config::from_toml_str("access_key = "),
+            Err(ConfigError::TomlDecodeError(_))
+        ));
+        assert!(matches!(
+            Config::from_json_str("{"),
+            Err(ConfigError::JsonDecodeError(_))
+        ));
+        assert!(matches!(
+            Config::load_from_path(temp_dir.path().join("not-existed.toml")),
+            Err(ConfigError::ReadFileError { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_config() -> Result<(), Box<dyn Error>> {
+        env_logger::try_init().ok();
+
+        let valid = ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+            .part_size(16)
+            .up_tries(3)
+            .max_punished_hosts_percent(100)
+            .punish_time_s(Duration::from_secs(60))
+            .build();
+        valid.validate()?;
+        assert_eq!(valid.punish_time_s, Some(60));
+
+        for (config, expected_field) in [
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .part_size(0)
+                    .build(),
+                "part_size",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .part_size(2048)
+                    .build(),
+                "part_size",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .max_punished_hosts_percent(101)
+                    .build(),
+                "max_punished_hosts_percent",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .uc_tries(0)
+                    .build(),
+                "uc_tries",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .up_urls(vec!["http://up1.com:99999".into()])
+                    .build(),
+                "up_urls",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .proxy("not a proxy")
+                    .build(),
+                "proxy",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .min_tls_version("1.4")
+                    .build(),
+                "min_tls_version",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .form_upload_threshold(u64::MAX)
+                    .build(),
+                "form_upload_threshold",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .min_tls_version("1.3")
+                    .build(),
+                "min_tls_version",
+            ),
+            (
+                ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1")
+                    .add_resolve_override("up1.com", "not an ip")
+                    .build(),
+                "resolve_overrides",
+            ),
+            (
+                Config {
+                    client_cert_path: Some("cert.pem".into()),
+                    ..ConfigBuilder::new("test-ak-1", "test-sk-1", "test-bucket-1").build()
+                },
+                "client_cert_path",
+            ),
+        ] {
+            match config.validate() {
+                Err(ConfigError::InvalidConfig { field, .. }) => assert_eq!(field, expected_field),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+
+        match Config::from_toml_str(
+            r#"
+            access_key = "test-ak-1"
+            secret_key = "test-sk-1"
+            bucket = "test-bucket-1"
+
+            [profiles.images]
+            retry = 0
+            "#,
+        ) {
+            Err(ConfigError::InvalidProfile { name, source }) => {
+                assert_eq!(name, "images");
+                assert!(matches!(
+                    *source,
+                    ConfigError::InvalidConfig { field: "retry", .. }
+                ));
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let envs = make_envs(&[
+            (QINIU_ACCESS_KEY_ENV, "test-ak-1"),
+            (QINIU_SECRET_KEY_ENV, "test-sk-1"),
+            (QINIU_BUCKET_ENV, "test-bucket-1"),
+            (QINIU_MAX_PUNISHED_HOSTS_PERCENT_ENV, "120"),
+        ]);
+        assert!(matches!(
+            Config::from_env_with(&|name| envs.get(name).cloned()),
+            Err(ConfigError::InvalidConfig {
+                field: "max_punished_hosts_percent",
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    fn make_envs(envs: &[(&str, &str)]) -> HashMap<String, OsString> {
+        envs.iter()
+            .map(|(name, value)| (name.to_string(), OsString::from(value)))
+            .collect()
+    }
 }
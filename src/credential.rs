@@ -58,7 +58,7 @@ impl<'a> Credential<'a> {
 
 impl Credential<'_> {
     pub(super) fn sign(&self, data: &[u8]) -> String {
-        self.access_key.to_owned().into_owned()
+        self.access_key.clone().into_owned()
             + ":"
             + &base64ed_hmac_digest(self.secret_key.as_ref(), data)
     }
@@ -76,7 +76,7 @@ fn base64ed_hmac_digest(secret_key: &str, data: &[u8]) -> String {
 }
 
 pub(super) trait CredentialProvider: Any + Debug + Sync + Send {
-    fn get(&self) -> Result<Credential>;
+    fn get(&self) -> Result<Credential<'_>>;
     fn as_any(&self) -> &dyn Any;
     fn as_credential_provider(&self) -> &dyn CredentialProvider;
 }
@@ -101,7 +101,7 @@ impl StaticCredentialProvider {
 
 impl CredentialProvider for StaticCredentialProvider {
     #[inline]
-    fn get(&self) -> Result<Credential> {
+    fn get(&self) -> Result<Credential<'_>> {
         Ok(Credential::new(
             Cow::Borrowed(self.access_key.as_ref()),
             Cow::Borrowed(self.secret_key.as_ref()),
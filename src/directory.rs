@@ -0,0 +1,417 @@
+use super::{
+    batch::{BatchUploadJob, BatchUploadProgressCallback, BatchUploadResults, BatchUploader},
+    cancellation::CancellationToken,
+    sync::SyncHook,
+    uploader::Uploader,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::warn;
+use std::{
+    fmt,
+    io::{Error as IOError, ErrorKind as IOErrorKind, Result as IOResult},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use walkdir::{DirEntry, WalkDir};
+
+/// 对象名称映射函数
+///
+/// 参数为文件相对于上传目录的路径，路径分隔符统一为 `/`，返回 None 表示跳过该文件
+pub type ObjectNameMapper = Box<dyn Fn(&str) -> Option<String> + Send + Sync + 'static>;
+
+/// 目录上传构建器
+///
+/// 遍历目录下的文件，通过批量上传器并发上传，每个文件将根据大小各自选择表单上传或分片上传
+pub struct DirUploadBuilder {
+    uploader: Uploader,
+    batch: BatchUploader,
+    walker: DirWalker,
+    manifest_path: Option<PathBuf>,
+    check_remote: bool,
+}
+
+pub(super) struct DirWalker {
+    pub(super) root: PathBuf,
+    pub(super) key_prefix: String,
+    pub(super) key_mapper: Option<ObjectNameMapper>,
+    pub(super) includes: Vec<String>,
+    pub(super) excludes: Vec<String>,
+    pub(super) include_hidden_files: bool,
+    pub(super) follow_symlinks: bool,
+    pub(super) max_depth: Option<usize>,
+}
+
+impl DirUploadBuilder {
+    #[inline]
+    pub(super) fn new(uploader: Uploader, root: PathBuf) -> Self {
+        Self {
+            batch: BatchUploader::new(uploader.to_owned()),
+            uploader,
+            walker: DirWalker::new(root),
+            manifest_path: None,
+            check_remote: false,
+        }
+    }
+
+    /// 设置对象名称前缀，将被添加在每个对象名称之前
+    #[inline]
+    pub fn key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.walker.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// 设置对象名称映射函数，默认使用文件的相对路径作为对象名称
+    #[inline]
+    pub fn key_mapper(mut self, key_mapper: ObjectNameMapper) -> Self {
+        self.walker.key_mapper = Some(key_mapper);
+        self
+    }
+
+    /// 追加需要上传的文件的 Glob 模式，匹配文件的相对路径，未设置时上传所有文件
+    #[inline]
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.walker.includes.push(pattern.into());
+        self
+    }
+
+    /// 追加需要排除的文件的 Glob 模式，匹配文件的相对路径，优先于需要上传的文件的模式
+    #[inline]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.walker.excludes.push(pattern.into());
+        self
+    }
+
+    /// 是否上传隐藏文件，默认为否
+    ///
+    /// 文件名或任意一级目录名以 `.` 开头的文件均被视为隐藏文件
+    #[inline]
+    pub fn include_hidden_files(mut self, include_hidden_files: bool) -> Self {
+        self.walker.include_hidden_files = include_hidden_files;
+        self
+    }
+
+    /// 是否跟随符号链接，默认为否，此时所有符号链接都将被跳过
+    #[inline]
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.walker.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// 设置最大遍历深度，1 表示仅上传目录下的文件，不进入子目录
+    #[inline]
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.walker.max_depth = Some(max_depth);
+        self
+    }
+
+    /// 设置工作线程数量，默认为 4
+    #[inline]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.batch = self.batch.concurrency(concurrency);
+        self
+    }
+
+    /// 设置批量上传进度回调函数
+    #[inline]
+    pub fn progress_callback(mut self, progress_callback: BatchUploadProgressCallback) -> Self {
+        self.batch = self.batch.progress_callback(progress_callback);
+        self
+    }
+
+    /// 设置取消令牌
+    #[inline]
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.batch = self.batch.cancellation_token(cancellation_token);
+        self
+    }
+
+    /// 设置同步清单文件路径，启用同步上传
+    ///
+    /// 同步上传时，大小、修改时间与清单记录一致，或内容 Etag 与清单记录一致的文件将被跳过，
+    /// 上传成功的文件将被记录在清单中，清单在所有文件上传结束后保存
+    #[inline]
+    pub fn manifest(mut self, manifest_path: impl Into<PathBuf>) -> Self {
+        self.manifest_path = Some(manifest_path.into());
+        self
+    }
+
+    /// 同步上传时，是否在上传前查询对象是否已经存在，默认为否
+    ///
+    /// 如果对象已经存在且 Etag 与文件一致，则跳过该文件并记录在清单中。
+    /// 分片上传 v2 生成的对象的 Etag 无法与文件比较，此时大小一致且上传时间不早于文件修改时间即跳过。
+    /// 仅在设置同步清单文件后生效
+    #[inline]
+    pub fn check_remote(mut self, check_remote: bool) -> Self {
+        self.check_remote = check_remote;
+        self
+    }
+
+    /// 开始上传目录，返回每个文件的上传结果的迭代器
+    ///
+    /// 如果 Glob 模式非法或同步清单无法读取则返回错误，目录在上传的同时被遍历，遍历时遇到的错误将被记录在日志中并跳过
+    pub fn start(self) -> IOResult<BatchUploadResults> {
+        let jobs = self.walker.walk()?;
+        let mut batch = self.batch;
+        if let Some(manifest_path) = self.manifest_path {
+            let remote_checker = if self.check_remote {
+                Some(self.uploader)
+            } else {
+                None
+            };
+            batch = batch.hook(Arc::new(SyncHook::load(manifest_path, remote_checker)?));
+        }
+        Ok(batch.start(jobs))
+    }
+}
+
+impl fmt::Debug for DirUploadBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirUploadBuilder")
+            .field("batch", &self.batch)
+            .field("root", &self.walker.root)
+            .field("key_prefix", &self.walker.key_prefix)
+            .field("includes", &self.walker.includes)
+            .field("excludes", &self.walker.excludes)
+            .field("include_hidden_files", &self.walker.include_hidden_files)
+            .field("follow_symlinks", &self.walker.follow_symlinks)
+            .field("max_depth", &self.walker.max_depth)
+            .field("manifest_path", &self.manifest_path)
+            .field("check_remote", &self.check_remote)
+            .finish()
+    }
+}
+
+impl DirWalker {
+    #[inline]
+    pub(super) fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            key_prefix: Default::default(),
+            key_mapper: None,
+            includes: Default::default(),
+            excludes: Default::default(),
+            include_hidden_files: false,
+            follow_symlinks: false,
+            max_depth: None,
+        }
+    }
+
+    pub(super) fn walk(self) -> IOResult<impl Iterator<Item = BatchUploadJob> + Send + 'static> {
+        let mut walk_dir = WalkDir::new(&self.root)
+            .follow_links(self.follow_symlinks)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        if let Some(max_depth) = self.max_depth {
+            walk_dir = walk_dir.max_depth(max_depth);
+        }
+        let include_hidden_files = self.include_hidden_files;
+        let filter = self.into_filter()?;
+        Ok(walk_dir
+            .into_iter()
+            .filter_entry(move |entry| {
+                include_hidden_files || entry.depth() == 0 || !is_hidden(entry)
+            })
+            .filter_map(|entry| {
+                entry
+                    .map_err(|err| warn!("failed to walk directory: {}", err))
+                    .ok()
+            })
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(move |entry| {
+                let object_name = filter.object_name(entry.path())?;
+                Some(BatchUploadJob::path(entry.into_path()).object_name(object_name))
+            }))
+    }
+
+    /// 转换为对象名称过滤器，用于判断单个文件是否需要上传
+    pub(super) fn into_filter(self) -> IOResult<ObjectNameFilter> {
+        Ok(ObjectNameFilter {
+            includes: build_glob_set(&self.includes)?,
+            excludes: build_glob_set(&self.excludes)?,
+            root: self.root,
+            key_prefix: self.key_prefix,
+            key_mapper: self.key_mapper,
+            include_hidden_files: self.include_hidden_files,
+            max_depth: self.max_depth,
+        })
+    }
+}
+
+/// 对象名称过滤器
+///
+/// 根据目录上传的过滤规则判断文件是否需要上传，并计算文件的对象名称
+pub(super) struct ObjectNameFilter {
+    root: PathBuf,
+    key_prefix: String,
+    key_mapper: Option<ObjectNameMapper>,
+    includes: GlobSet,
+    excludes: GlobSet,
+    include_hidden_files: bool,
+    max_depth: Option<usize>,
+}
+
+impl ObjectNameFilter {
+    #[inline]
+    pub(super) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 获取文件的对象名称，文件不在上传目录下或不需要上传时返回 None
+    pub(super) fn object_name(&self, path: &Path) -> Option<String> {
+        let relative_path = path.strip_prefix(&self.root).ok()?;
+        let depth = relative_path.components().count();
+        if depth == 0 || self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return None;
+        }
+        if !self.include_hidden_files
+            && relative_path
+                .components()
+                .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return None;
+        }
+        let relative_path = normalize_relative_path(relative_path);
+        if !self.includes.is_empty() && !self.includes.is_match(&relative_path) {
+            return None;
+        }
+        if self.excludes.is_match(&relative_path) {
+            return None;
+        }
+        let key = match &self.key_mapper {
+            Some(key_mapper) => key_mapper(&relative_path)?,
+            None => relative_path,
+        };
+        Some(format!("{}{}", self.key_prefix, key))
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> IOResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder
+            .add(Glob::new(pattern).map_err(|err| IOError::new(IOErrorKind::InvalidInput, err))?);
+    }
+    builder
+        .build()
+        .map_err(|err| IOError::new(IOErrorKind::InvalidInput, err))
+}
+
+#[inline]
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// 将相对路径转换为以 `/` 分隔的字符串，与操作系统的路径分隔符无关
+fn normalize_relative_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UploaderBuilder;
+    use std::fs::{create_dir_all, write};
+    use tempfile::tempdir;
+
+    fn walker(root: &Path) -> DirWalker {
+        DirUploadBuilder::new(
+            UploaderBuilder::new("test-ak", "test-sk", "test-bucket").build(),
+            root.to_owned(),
+        )
+        .walker
+    }
+
+    fn object_names(walker: DirWalker) -> IOResult<Vec<String>> {
+        Ok(walker
+            .walk()?
+            .map(|job| job.key().unwrap().to_owned())
+            .collect())
+    }
+
+    #[test]
+    fn test_walk_dir() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        create_dir_all(dir.path().join("a/b"))?;
+        create_dir_all(dir.path().join(".git"))?;
+        write(dir.path().join("1.txt"), b"1")?;
+        write(dir.path().join("2.log"), b"2")?;
+        write(dir.path().join(".hidden"), b"3")?;
+        write(dir.path().join("a/3.txt"), b"4")?;
+        write(dir.path().join("a/b/4.txt"), b"5")?;
+        write(dir.path().join(".git/config"), b"6")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path().join("1.txt"), dir.path().join("link.txt"))?;
+
+        assert_eq!(
+            object_names(walker(dir.path()))?,
+            vec!["1.txt", "2.log", "a/3.txt", "a/b/4.txt"]
+        );
+        assert_eq!(
+            object_names(DirWalker {
+                key_prefix: "prefix/".to_owned(),
+                include_hidden_files: true,
+                ..walker(dir.path())
+            })?,
+            vec![
+                "prefix/.git/config",
+                "prefix/.hidden",
+                "prefix/1.txt",
+                "prefix/2.log",
+                "prefix/a/3.txt",
+                "prefix/a/b/4.txt"
+            ]
+        );
+        assert_eq!(
+            object_names(DirWalker {
+                includes: vec!["**/*.txt".to_owned()],
+                excludes: vec!["a/b/**".to_owned()],
+                ..walker(dir.path())
+            })?,
+            vec!["1.txt", "a/3.txt"]
+        );
+        assert_eq!(
+            object_names(DirWalker {
+                max_depth: Some(2),
+                key_mapper: Some(Box::new(|path| {
+                    if path.ends_with(".log") {
+                        None
+                    } else {
+                        Some(path.replace('/', "-"))
+                    }
+                })),
+                ..walker(dir.path())
+            })?,
+            vec!["1.txt", "a-3.txt"]
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            object_names(DirWalker {
+                max_depth: Some(1),
+                follow_symlinks: true,
+                ..walker(dir.path())
+            })?,
+            vec!["1.txt", "2.log", "link.txt"]
+        );
+        assert!(object_names(DirWalker {
+            includes: vec!["a/[".to_owned()],
+            ..walker(dir.path())
+        })
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_relative_path() {
+        assert_eq!(
+            normalize_relative_path(&Path::new("a").join("b").join("c.txt")),
+            "a/b/c.txt"
+        );
+        assert_eq!(normalize_relative_path(Path::new("./a/c.txt")), "a/c.txt");
+    }
+}
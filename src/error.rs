@@ -1,12 +1,19 @@
+use super::http::HttpResponse;
+use mime::FromStrError as MimeFromStrError;
 use reqwest::{
-    blocking::Response,
     header::{HeaderName, HeaderValue},
     Error as ReqwestError, StatusCode,
 };
 use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::Error as JSONError;
-use std::{error::Error, fmt, io::Error as IOError};
+use std::{
+    error::Error,
+    fmt,
+    io::{Error as IOError, ErrorKind as IOErrorKind},
+    path::PathBuf,
+};
 use thiserror::Error;
+use toml::{de::Error as TOMLDecodeError, ser::Error as TOMLEncodeError};
 use url::ParseError as URLParseError;
 
 /// HTTP 调用错误
@@ -21,6 +28,10 @@ pub enum HttpCallError {
     #[error("Invalid URL error: {0}")]
     InvalidUrl(#[from] URLParseError),
 
+    /// 非法的 MIME 类型
+    #[error("Invalid MIME type: {0}")]
+    InvalidMimeType(#[from] MimeFromStrError),
+
     /// Reqwest 库调用错误
     #[error("HTTP Call error: {0}")]
     ReqwestError(#[from] ReqwestError),
@@ -32,7 +43,55 @@ pub enum HttpCallError {
     /// 状态码错误
     #[error("HTTP Status Code error: {0}")]
     StatusCodeError(#[from] StatusCodeError),
+
+    /// 上传已被取消
+    #[error("Upload is cancelled")]
+    Cancelled,
+
+    /// 上传超出总时限
+    #[error("Upload deadline exceeded")]
+    DeadlineExceeded,
+
+    /// 没有可用的服务器域名，通常是因为未配置域名且查询域名失败
+    #[error("No available hosts")]
+    NoAvailableHosts,
+
+    /// 上传被上传进度回调函数中止，包含回调函数返回的错误
+    #[error("Upload is aborted by progress callback: {0}")]
+    Aborted(#[source] Box<HttpCallError>),
+}
+
+impl HttpCallError {
+    /// 判断是否为请求超时错误
+    #[inline]
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            Self::ReqwestError(err) => err.is_timeout(),
+            Self::LocalIoError(err) => err.kind() == IOErrorKind::TimedOut,
+            Self::DeadlineExceeded => true,
+            _ => false,
+        }
+    }
+
+    /// 判断是否为上传超出总时限错误
+    #[inline]
+    pub fn is_deadline_exceeded(&self) -> bool {
+        matches!(self, Self::DeadlineExceeded)
+    }
+
+    /// 判断是否为上传被取消错误
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, Self::Cancelled)
+    }
+
+    /// 判断是否为上传被上传进度回调函数中止错误
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        matches!(self, Self::Aborted(_))
+    }
 }
+
 /// HTTP 调用结果
 pub type HttpCallResult<T> = Result<T, HttpCallError>;
 
@@ -157,15 +216,15 @@ impl Error for StatusCodeError {}
 
 const X_REQ_ID: &str = "x-reqid";
 
-impl From<Response> for HttpCallError {
+impl From<HttpResponse> for HttpCallError {
     #[inline]
-    fn from(response: Response) -> Self {
+    fn from(response: HttpResponse) -> Self {
         #[derive(Debug, Clone, Deserialize)]
         struct ErrorBody {
             error: Option<Box<str>>,
         }
 
-        let status_code = response.status();
+        let status_code = response.status_code();
         let request_id = response
             .headers()
             .get(HeaderName::from_static(X_REQ_ID))
@@ -187,9 +246,9 @@ impl From<Response> for HttpCallError {
 }
 
 pub(super) fn json_decode_response<T: DeserializeOwned>(
-    response: Response,
+    response: HttpResponse,
 ) -> HttpCallResult<(T, Option<HeaderValue>)> {
-    let status_code = response.status();
+    let status_code = response.status_code();
     let request_id = response
         .headers()
         .get(HeaderName::from_static(X_REQ_ID))
@@ -203,3 +262,79 @@ pub(super) fn json_decode_response<T: DeserializeOwned>(
         })),
     }
 }
+
+/// 七牛配置加载错误
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// 配置文件读取错误
+    #[error("Failed to read config file {path:?}: {source}")]
+    ReadFileError {
+        /// 配置文件路径
+        path: PathBuf,
+        /// 读取错误
+        #[source]
+        source: IOError,
+    },
+
+    /// 配置文件写入错误
+    #[error("Failed to write config file {path:?}: {source}")]
+    WriteFileError {
+        /// 配置文件路径
+        path: PathBuf,
+        /// 写入错误
+        #[source]
+        source: IOError,
+    },
+
+    /// TOML 配置解析错误
+    #[error("TOML decode error: {0}")]
+    TomlDecodeError(#[from] TOMLDecodeError),
+
+    /// TOML 配置序列化错误
+    #[error("TOML encode error: {0}")]
+    TomlEncodeError(#[from] TOMLEncodeError),
+
+    /// JSON 配置解析错误
+    #[error("JSON decode error: {0}")]
+    JsonDecodeError(#[from] JSONError),
+
+    /// JSON 配置序列化错误
+    #[error("JSON encode error: {0}")]
+    JsonEncodeError(#[source] JSONError),
+
+    /// 非法的配置项
+    #[error("Invalid config {field}: {message}")]
+    InvalidConfig {
+        /// 配置项名称
+        field: &'static str,
+        /// 错误信息
+        message: String,
+    },
+
+    /// 非法的命名配置
+    #[error("Invalid profile {name}: {source}")]
+    InvalidProfile {
+        /// 命名配置名称
+        name: String,
+        /// 命名配置错误
+        #[source]
+        source: Box<ConfigError>,
+    },
+
+    /// 缺少必要的环境变量
+    #[error("Environment variable {0} is required")]
+    MissingEnvVar(&'static str),
+
+    /// 非法的环境变量
+    #[error("Invalid environment variable {name}: {message}")]
+    InvalidEnvVar {
+        /// 环境变量名称
+        name: &'static str,
+        /// 错误信息
+        message: String,
+    },
+}
+
+/// 七牛配置加载结果
+pub type ConfigResult<T> = Result<T, ConfigError>;
@@ -0,0 +1,151 @@
+use super::base64::urlsafe_encode;
+use digest::Digest;
+use sha1::Sha1;
+use std::{
+    io::{Read, Result as IOResult},
+    sync::{Arc, Mutex},
+};
+
+/// 七牛 Etag 计算时的数据块大小
+const ETAG_BLOCK_SIZE: usize = 1 << 22;
+
+/// 七牛 Etag 计算器
+///
+/// 数据按 4 MB 分块计算 SHA-1，仅有一块时 Etag 为该块的 SHA-1，否则为所有块的 SHA-1 拼接后再次计算的 SHA-1
+#[derive(Debug, Default)]
+pub(super) struct EtagHasher {
+    block_hasher: Sha1,
+    block_size: usize,
+    block_digests: Vec<u8>,
+}
+
+impl EtagHasher {
+    #[inline]
+    pub(super) fn new() -> Self {
+        Default::default()
+    }
+
+    pub(super) fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let size = data.len().min(ETAG_BLOCK_SIZE - self.block_size);
+            self.block_hasher.update(&data[..size]);
+            self.block_size += size;
+            data = &data[size..];
+            if self.block_size == ETAG_BLOCK_SIZE {
+                self.finish_block();
+            }
+        }
+    }
+
+    pub(super) fn finalize(mut self) -> String {
+        if self.block_size > 0 || self.block_digests.is_empty() {
+            self.finish_block();
+        }
+        let mut etag = Vec::with_capacity(21);
+        if self.block_digests.len() <= 20 {
+            etag.push(0x16);
+            etag.extend_from_slice(&self.block_digests);
+        } else {
+            etag.push(0x96);
+            etag.extend_from_slice(&Sha1::digest(&self.block_digests));
+        }
+        urlsafe_encode(&etag)
+    }
+
+    fn finish_block(&mut self) {
+        self.block_digests
+            .extend_from_slice(&self.block_hasher.finalize_reset());
+        self.block_size = 0;
+    }
+}
+
+/// 透传读取到的数据，同时计算数据的七牛 Etag
+#[derive(Debug)]
+pub(super) struct EtagReader<R> {
+    inner: R,
+    hasher: Arc<Mutex<EtagHasher>>,
+}
+
+impl<R> EtagReader<R> {
+    #[inline]
+    pub(super) fn new(inner: R, hasher: Arc<Mutex<EtagHasher>>) -> Self {
+        Self { inner, hasher }
+    }
+}
+
+impl<R: Read> Read for EtagReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let have_read = self.inner.read(buf)?;
+        self.hasher.lock().unwrap().update(&buf[..have_read]);
+        Ok(have_read)
+    }
+}
+
+/// 读取输入流直到结束，计算数据的七牛 Etag
+pub(super) fn etag_of_reader(mut reader: impl Read) -> IOResult<String> {
+    let mut hasher = EtagHasher::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let have_read = reader.read(&mut buf)?;
+        if have_read == 0 {
+            break;
+        }
+        hasher.update(&buf[..have_read]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// 判断 Etag 是否由七牛 Etag 算法计算得到
+///
+/// 分片上传 v2 生成的对象的 Etag 并非由七牛 Etag 算法计算得到，无法与本地计算的 Etag 比较
+#[inline]
+pub(super) fn is_qetag(etag: &str) -> bool {
+    etag.len() == 28 && (etag.starts_with('F') || etag.starts_with('l'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{repeat, Read},
+        mem::take,
+    };
+
+    #[test]
+    fn test_etag() -> anyhow::Result<()> {
+        assert_eq!(etag_of_reader(&b""[..])?, "Fto5o-5ea0sNMlW_75VgGJCv2AcJ");
+        assert_eq!(
+            etag_of_reader(&b"etag"[..])?,
+            "FpLiADEaVoALPkdb8tJEJyRTXoe_"
+        );
+        assert_eq!(
+            etag_of_reader(repeat(b'a').take(4 << 20))?,
+            "FuwQ-vpd56Izwiom1JHzCIdrQa4_"
+        );
+        assert_eq!(
+            etag_of_reader(repeat(b'a').take((4 << 20) + 1))?,
+            "lieGn00gWdbfwEIHaUpzu4drHeun"
+        );
+
+        let mut hasher = EtagHasher::new();
+        for _ in 0..9 {
+            hasher.update(&vec![b'a'; 1 << 20]);
+        }
+        assert_eq!(hasher.finalize(), "lo-Big7b5RTsoCkykeYLTTzo72ZD");
+        assert!(is_qetag("FpLiADEaVoALPkdb8tJEJyRTXoe_"));
+        assert!(is_qetag("lo-Big7b5RTsoCkykeYLTTzo72ZD"));
+        assert!(!is_qetag("d41d8cd98f00b204e9800998ecf8427e"));
+
+        let hasher = Arc::new(Mutex::new(EtagHasher::new()));
+        let mut reader = EtagReader::new(&b"etag"[..], hasher.to_owned());
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        assert_eq!(buf, b"etag");
+        assert_eq!(
+            take(&mut *hasher.lock().unwrap()).finalize(),
+            "FpLiADEaVoALPkdb8tJEJyRTXoe_"
+        );
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use crate::error::{HttpCallError, HttpCallResult};
+use crate::{
+    error::{HttpCallError, HttpCallResult},
+    resolver::Resolver,
+};
 use dashmap::DashMap;
 use log::{info, warn};
 use rand::{seq::SliceRandom, thread_rng};
@@ -6,6 +9,7 @@ use std::{
     cmp::{min, Ordering},
     collections::HashSet,
     fmt::{Debug, Formatter, Result as FormatResult},
+    net::IpAddr,
     ops::Deref,
     sync::{
         atomic::{AtomicUsize, Ordering::Relaxed},
@@ -15,6 +19,7 @@ use std::{
     time::{Duration, Instant},
 };
 use tap::prelude::*;
+use url::{Host, Url};
 
 #[derive(Default, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 struct OptionalInstantTime(Option<Instant>);
@@ -42,7 +47,7 @@ struct PunishedInfo {
     timeout_power: usize,
 }
 
-impl<'a> Ord for PunishedInfo {
+impl Ord for PunishedInfo {
     fn cmp(&self, other: &Self) -> Ordering {
         if self.timeout_power != other.timeout_power {
             return self.timeout_power.cmp(&other.timeout_power);
@@ -56,7 +61,7 @@ impl<'a> Ord for PunishedInfo {
     }
 }
 
-impl<'a> PartialOrd for PunishedInfo {
+impl PartialOrd for PunishedInfo {
     #[inline]
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -124,8 +129,10 @@ type UpdateFn = Box<dyn Fn() -> HttpCallResult<Vec<String>> + Sync + Send + 'sta
 struct HostsUpdater {
     hosts: RwLock<Vec<String>>,
     hosts_map: DashMap<String, PunishedInfo>,
+    ips_map: DashMap<(String, IpAddr), PunishedInfo>,
     update_option: Option<UpdateOption>,
     index: AtomicUsize,
+    ip_index: AtomicUsize,
     current_timeout_power: AtomicUsize,
 }
 
@@ -154,8 +161,10 @@ impl HostsUpdater {
                 .map(|host| (host.to_owned(), Default::default()))
                 .collect(),
             hosts: RwLock::new(hosts),
+            ips_map: Default::default(),
             update_option,
             index: AtomicUsize::new(0),
+            ip_index: AtomicUsize::new(0),
             current_timeout_power: AtomicUsize::new(0),
         })
     }
@@ -168,6 +177,8 @@ impl HostsUpdater {
         }
         self.hosts_map
             .retain(|host, _| new_hosts_set.contains(host));
+        self.ips_map
+            .retain(|(host, _), _| new_hosts_set.contains(host));
         hosts.shuffle(&mut thread_rng());
         *self.hosts.write().unwrap() = hosts;
     }
@@ -241,6 +252,7 @@ impl Debug for HostsUpdater {
     fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
         f.debug_struct("HostsUpdater")
             .field("hosts_map", &self.hosts_map)
+            .field("ips_map", &self.ips_map)
             .finish()
     }
 }
@@ -275,10 +287,10 @@ impl HostPunisher {
     }
 
     #[inline]
-    fn timeout(&self, punished_info: &PunishedInfo) -> Duration {
+    fn timeout(&self, base_timeout: Duration, punished_info: &PunishedInfo) -> Duration {
         min(
             // 超时时长有上限，否则可能超过 tokio 极限
-            self.base_timeout * (1 << punished_info.timeout_power),
+            base_timeout * (1 << punished_info.timeout_power),
             Duration::from_secs(600),
         )
     }
@@ -287,7 +299,7 @@ impl HostPunisher {
     fn should_punish(&self, error: &HttpCallError) -> bool {
         self.should_punish_func
             .as_ref()
-            .map_or(true, |should_punish_func| should_punish_func(error))
+            .is_none_or(|should_punish_func| should_punish_func(error))
     }
 }
 
@@ -310,12 +322,14 @@ impl Debug for HostPunisher {
 pub(super) struct HostSelector {
     hosts_updater: Arc<HostsUpdater>,
     host_punisher: Arc<HostPunisher>,
+    resolver: Option<Arc<dyn Resolver>>,
 }
 
 pub(super) struct HostSelectorBuilder {
     hosts: Vec<String>,
     update_func: Option<UpdateFn>,
     should_punish_func: Option<ShouldPunishFn>,
+    resolver: Option<Arc<dyn Resolver>>,
     update_interval: Duration,
     punish_duration: Duration,
     base_timeout: Duration,
@@ -329,6 +343,7 @@ impl HostSelectorBuilder {
             hosts,
             update_func: None,
             should_punish_func: None,
+            resolver: None,
             update_interval: Duration::from_secs(60),
             punish_duration: Duration::from_secs(30 * 60),
             base_timeout: Duration::from_millis(3000),
@@ -349,6 +364,12 @@ impl HostSelectorBuilder {
         self
     }
 
+    #[inline]
+    pub(super) fn resolver(mut self, resolver: Option<Arc<dyn Resolver>>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
     #[inline]
     pub(super) fn update_interval(mut self, interval: Duration) -> Self {
         self.update_interval = interval;
@@ -403,12 +424,49 @@ impl HostSelectorBuilder {
                 max_punished_times: self.max_punished_times,
                 max_punished_hosts_percent: self.max_punished_hosts_percent,
             }),
+            resolver: self.resolver,
         }
     }
 }
 
+/// 域名状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostStat {
+    host: String,
+    continuous_punished_times: usize,
+    timeout_power: usize,
+    punished: bool,
+}
+
+impl HostStat {
+    /// 获取域名
+    #[inline]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// 获取域名连续被惩罚的次数
+    #[inline]
+    pub fn continuous_punished_times(&self) -> usize {
+        self.continuous_punished_times
+    }
+
+    /// 获取域名的超时时长倍数的指数
+    #[inline]
+    pub fn timeout_power(&self) -> usize {
+        self.timeout_power
+    }
+
+    /// 判断域名当前是否处于惩罚期内
+    #[inline]
+    pub fn is_punished(&self) -> bool {
+        self.punished
+    }
+}
+
 pub(super) struct HostInfo {
     pub(super) host: String,
+    pub(super) ip: Option<IpAddr>,
     pub(super) timeout_power: usize,
     pub(super) timeout: Duration,
 }
@@ -419,7 +477,27 @@ impl HostSelector {
         HostSelectorBuilder::new(hosts)
     }
 
-    pub(super) fn select_host(&self) -> HostInfo {
+    /// 创建使用指定域名列表的域名选择器
+    ///
+    /// 新的选择器沿用当前选择器的惩罚策略和域名解析器，但惩罚信息仅属于新的选择器，不会影响当前选择器。
+    /// 需要在多次上传之间共享惩罚信息时，应当缓存并复用新的选择器
+    pub(super) fn with_hosts(&self, hosts: Vec<String>) -> HostSelector {
+        HostSelector {
+            hosts_updater: HostsUpdater::new(hosts, None),
+            host_punisher: self.host_punisher.to_owned(),
+            resolver: self.resolver.to_owned(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn select_host(&self) -> Option<HostInfo> {
+        self.select_host_with(None)
+    }
+
+    /// 使用指定的基础超时时长选择域名，未指定时使用选择器自身的基础超时时长
+    ///
+    /// 域名列表为空时返回 None
+    pub(super) fn select_host_with(&self, base_timeout: Option<Duration>) -> Option<HostInfo> {
         struct CurrentHostInfo<'a> {
             host: &'a str,
             timeout: Duration,
@@ -428,6 +506,11 @@ impl HostSelector {
         let mut chosen_host_info = None;
 
         let hosts = self.hosts_updater.hosts.read().unwrap();
+        if hosts.is_empty() {
+            warn!("no hosts to select");
+            return None;
+        }
+        let base_timeout = base_timeout.unwrap_or(self.host_punisher.base_timeout);
         let max_seek_times = self.host_punisher.max_seek_times(hosts.len());
         let mut candidates = Vec::with_capacity(max_seek_times + 1);
         for _ in 0..=max_seek_times {
@@ -435,10 +518,10 @@ impl HostSelector {
             let host = hosts[index % hosts.len()].as_str();
             if let Some(punished_info) = self.hosts_updater.hosts_map.get(host) {
                 if self.host_punisher.is_punishment_expired(&punished_info) {
-                    info!("host {} is selected directly because there is no punishment or punishment is expired, timeout: {:?}", host,self.host_punisher.base_timeout);
+                    info!("host {} is selected directly because there is no punishment or punishment is expired, timeout: {:?}", host, base_timeout);
                     chosen_host_info = Some(CurrentHostInfo {
                         host,
-                        timeout: self.host_punisher.base_timeout,
+                        timeout: base_timeout,
                         timeout_power: 0,
                     });
                     break;
@@ -448,12 +531,12 @@ impl HostSelector {
                     info!(
                         "host {} is selected, timeout: {:?}, timeout power: {:?}",
                         host,
-                        self.host_punisher.timeout(&punished_info),
+                        self.host_punisher.timeout(base_timeout, &punished_info),
                         punished_info.timeout_power,
                     );
                     chosen_host_info = Some(CurrentHostInfo {
                         host,
-                        timeout: self.host_punisher.timeout(&punished_info),
+                        timeout: self.host_punisher.timeout(base_timeout, &punished_info),
                         timeout_power: punished_info.timeout_power,
                     });
                     break;
@@ -461,7 +544,7 @@ impl HostSelector {
                     info!(
                         "host {} is unsatisfied, put it into candidates, timeout: {:?}, timeout power: {:?}",
                         host,
-                        self.host_punisher.timeout(&punished_info),
+                        self.host_punisher.timeout(base_timeout, &punished_info),
                         punished_info.timeout_power,
                     );
                     candidates.push(Candidate {
@@ -479,7 +562,7 @@ impl HostSelector {
                 .max()
                 .map(|c| CurrentHostInfo {
                     host: c.host,
-                    timeout: self.host_punisher.timeout(&c.punished_info),
+                    timeout: self.host_punisher.timeout(base_timeout, &c.punished_info),
                     timeout_power: c.punished_info.timeout_power,
                 })
                 .unwrap()
@@ -493,11 +576,112 @@ impl HostSelector {
         self.hosts_updater
             .current_timeout_power
             .store(chosen_host_info.timeout_power, Relaxed);
-        HostInfo {
+        Some(HostInfo {
             host: chosen_host_info.host.to_owned(),
+            ip: self.select_ip(chosen_host_info.host),
             timeout: chosen_host_info.timeout,
             timeout_power: chosen_host_info.timeout_power,
+        })
+    }
+
+    fn select_ip(&self, host: &str) -> Option<IpAddr> {
+        let ips = self.resolve(host)?;
+        let start = self.hosts_updater.ip_index.fetch_add(1, Relaxed);
+        let mut candidates = Vec::with_capacity(ips.len());
+        for i in 0..ips.len() {
+            let ip = ips[(start + i) % ips.len()];
+            match self.hosts_updater.ips_map.get(&(host.to_owned(), ip)) {
+                Some(punished_info)
+                    if !self.host_punisher.is_punishment_expired(&punished_info) =>
+                {
+                    candidates.push((
+                        ip,
+                        Candidate {
+                            host,
+                            punish_duration: self.host_punisher.punish_duration,
+                            max_punished_times: self.host_punisher.max_punished_times,
+                            punished_info: punished_info.to_owned(),
+                        },
+                    ));
+                }
+                _ => {
+                    info!("ip {} of host {} is selected", ip, host);
+                    return Some(ip);
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .max_by(|(_, c1), (_, c2)| c1.cmp(c2))
+            .map(|(ip, _)| ip)
+            .tap_some(|ip| info!("candidate ip {} of host {} is selected", ip, host))
+    }
+
+    fn resolve(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let resolver = self.resolver.as_ref()?;
+        let url = Url::parse(host).ok()?;
+        let domain = match url.host()? {
+            Host::Domain(domain) => domain,
+            _ => return None,
+        };
+        resolver
+            .resolve(domain)
+            .tap_err(|err| warn!("Failed to resolve host {}: {:?}", host, err))
+            .ok()
+            .filter(|ips| !ips.is_empty())
+    }
+
+    /// 奖励选中的域名，如果选中了 IP，则同时解除该 IP 的惩罚
+    #[inline]
+    pub(super) fn reward_chosen(&self, host_info: &HostInfo) {
+        if let Some(ip) = host_info.ip {
+            self.hosts_updater
+                .ips_map
+                .remove(&(host_info.host.to_owned(), ip));
+        }
+        self.reward(&host_info.host);
+    }
+
+    /// 惩罚选中的域名
+    ///
+    /// 如果选中了 IP，则仅惩罚该 IP，直到该域名的所有 IP 都被惩罚后才会惩罚域名
+    pub(super) fn punish_chosen(&self, host_info: &HostInfo, error: &HttpCallError) -> bool {
+        if !self.host_punisher.should_punish(error) {
+            return false;
+        }
+        if let Some(ip) = host_info.ip {
+            {
+                let mut punished_info = self
+                    .hosts_updater
+                    .ips_map
+                    .entry((host_info.host.to_owned(), ip))
+                    .or_default();
+                punished_info.continuous_punished_times += 1;
+                punished_info.last_punished_at = OptionalInstantTime::now();
+                info!(
+                    "Punish ip {} of host {}, now continuous_punished_times is {}",
+                    ip, host_info.host, punished_info.continuous_punished_times
+                );
+            }
+            if self.has_unpunished_ip(&host_info.host) {
+                return true;
+            }
         }
+        self.punish_host(&host_info.host);
+        true
+    }
+
+    fn has_unpunished_ip(&self, host: &str) -> bool {
+        self.resolve(host).is_some_and(|ips| {
+            ips.into_iter().any(|ip| {
+                self.hosts_updater
+                    .ips_map
+                    .get(&(host.to_owned(), ip))
+                    .is_none_or(|punished_info| {
+                        self.host_punisher.is_punishment_expired(&punished_info)
+                    })
+            })
+        })
     }
 
     #[inline]
@@ -514,26 +698,47 @@ impl HostSelector {
 
     pub(super) fn punish(&self, host: &str, error: &HttpCallError) -> bool {
         if self.host_punisher.should_punish(error) {
-            if let Some(mut punished_info) = self.hosts_updater.hosts_map.get_mut(host) {
-                punished_info.continuous_punished_times += 1;
-                punished_info.last_punished_at = OptionalInstantTime::now();
-                info!(
-                    "Punish host {}, now continuous_punished_times is {}, and timeout_power is {}",
-                    host, punished_info.continuous_punished_times, punished_info.timeout_power
-                );
-            }
+            self.punish_host(host);
             true
         } else {
             false
         }
     }
 
+    fn punish_host(&self, host: &str) {
+        if let Some(mut punished_info) = self.hosts_updater.hosts_map.get_mut(host) {
+            punished_info.continuous_punished_times += 1;
+            punished_info.last_punished_at = OptionalInstantTime::now();
+            info!(
+                "Punish host {}, now continuous_punished_times is {}, and timeout_power is {}",
+                host, punished_info.continuous_punished_times, punished_info.timeout_power
+            );
+        }
+    }
+
     #[inline]
     pub(super) fn increase_timeout_power_by(&self, host: &str, timeout_power: usize) {
         self.hosts_updater
             .increase_timeout_power_by(host, timeout_power)
     }
 
+    /// 获取所有域名的当前状态，按域名排序
+    pub(super) fn host_stats(&self) -> Vec<HostStat> {
+        let mut host_stats = self
+            .hosts_updater
+            .hosts_map
+            .iter()
+            .map(|entry| HostStat {
+                host: entry.key().to_owned(),
+                continuous_punished_times: entry.continuous_punished_times,
+                timeout_power: entry.timeout_power,
+                punished: !self.host_punisher.is_punishment_expired(entry.value()),
+            })
+            .collect::<Vec<_>>();
+        host_stats.sort_by(|a, b| a.host.cmp(&b.host));
+        host_stats
+    }
+
     #[inline]
     fn is_satisfied_with(&self, punished_info: &PunishedInfo) -> bool {
         self.host_punisher.is_available(punished_info)
@@ -546,6 +751,7 @@ mod tests {
     use crate::error::StatusCodeError;
 
     use super::*;
+    use crate::resolver::StaticResolver;
     use reqwest::StatusCode;
     use std::{sync::Mutex, thread::sleep};
 
@@ -601,7 +807,7 @@ mod tests {
             "http://host4".to_owned(),
             "http://host5".to_owned(),
         ]
-        .contains(&host_selector.select_host().host))
+        .contains(&host_selector.select_host().unwrap().host))
     }
 
     #[test]
@@ -662,13 +868,22 @@ mod tests {
             .max_punished_times(2)
             .build();
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host1".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
-            assert_eq!(host_selector.select_host().host, "http://host2".to_owned());
-            assert_eq!(host_selector.select_host().host, "http://host3".to_owned());
-            assert_eq!(host_selector.select_host().host, "http://host1".to_owned());
+            assert_eq!(
+                host_selector.select_host().unwrap().host,
+                "http://host2".to_owned()
+            );
+            assert_eq!(
+                host_selector.select_host().unwrap().host,
+                "http://host3".to_owned()
+            );
+            assert_eq!(
+                host_selector.select_host().unwrap().host,
+                "http://host1".to_owned()
+            );
             host_selector.increase_timeout_power_by("http://host1", 0);
             host_selector.punish(
                 "http://host1",
@@ -679,7 +894,7 @@ mod tests {
                 )),
             );
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
@@ -692,12 +907,12 @@ mod tests {
                 )),
             );
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
@@ -710,7 +925,10 @@ mod tests {
                     None,
                 )),
             );
-            assert_eq!(host_selector.select_host().host, "http://host3".to_owned());
+            assert_eq!(
+                host_selector.select_host().unwrap().host,
+                "http://host3".to_owned()
+            );
             host_selector.punish(
                 "http://host2",
                 &HttpCallError::StatusCodeError(StatusCodeError::new(
@@ -719,7 +937,10 @@ mod tests {
                     None,
                 )),
             );
-            assert_eq!(host_selector.select_host().host, "http://host2".to_owned());
+            assert_eq!(
+                host_selector.select_host().unwrap().host,
+                "http://host2".to_owned()
+            );
             host_selector.increase_timeout_power_by("http://host2", 0);
             host_selector.punish(
                 "http://host2",
@@ -739,22 +960,22 @@ mod tests {
                 )),
             );
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(400));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(400));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
@@ -768,54 +989,54 @@ mod tests {
                 )),
             );
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(800));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
             host_selector.reward("http://host1");
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host1".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host1".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(200));
             }
             sleep(Duration::from_millis(500));
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host1".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
@@ -870,12 +1091,12 @@ mod tests {
                 )),
             );
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(800));
             }
@@ -889,23 +1110,23 @@ mod tests {
                 )),
             );
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host1".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(1600));
             }
             host_selector.reward("http://host3");
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host2".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(100));
             }
             {
-                let host_info = host_selector.select_host();
+                let host_info = host_selector.select_host().unwrap();
                 assert_eq!(host_info.host, "http://host3".to_owned());
                 assert_eq!(host_info.timeout, Duration::from_millis(800));
             }
@@ -919,4 +1140,128 @@ mod tests {
             14
         );
     }
+
+    #[test]
+    fn test_hosts_selector_with_overrides() {
+        env_logger::try_init().ok();
+
+        let host_selector =
+            HostSelectorBuilder::new(vec!["http://host1".to_owned(), "http://host2".to_owned()])
+                .should_punish_callback(Box::new(|_| true))
+                .base_timeout(Duration::from_millis(100))
+                .max_punished_times(0)
+                .build();
+        let override_selector =
+            host_selector.with_hosts(vec!["http://host2".to_owned(), "http://host3".to_owned()]);
+        let err = HttpCallError::StatusCodeError(StatusCodeError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            None,
+            None,
+        ));
+
+        override_selector.punish("http://host2", &err);
+        let host_info = override_selector
+            .select_host_with(Some(Duration::from_millis(300)))
+            .unwrap();
+        assert_eq!(host_info.host, "http://host3");
+        assert_eq!(host_info.timeout, Duration::from_millis(300));
+
+        override_selector.punish("http://host3", &err);
+        override_selector.punish("http://host3", &err);
+        let host_info = override_selector
+            .select_host_with(Some(Duration::from_millis(300)))
+            .unwrap();
+        assert_eq!(host_info.host, "http://host2");
+
+        assert_eq!(
+            override_selector
+                .host_stats()
+                .iter()
+                .map(|stat| (
+                    stat.host(),
+                    stat.continuous_punished_times(),
+                    stat.is_punished()
+                ))
+                .collect::<Vec<_>>(),
+            vec![("http://host2", 1, true), ("http://host3", 2, true)]
+        );
+        assert_eq!(
+            host_selector
+                .host_stats()
+                .iter()
+                .map(|stat| (
+                    stat.host(),
+                    stat.continuous_punished_times(),
+                    stat.is_punished()
+                ))
+                .collect::<Vec<_>>(),
+            vec![("http://host1", 0, false), ("http://host2", 0, false)]
+        );
+    }
+
+    #[test]
+    fn test_hosts_selector_with_resolver() {
+        env_logger::try_init().ok();
+
+        let (ip1, ip2, ip3) = (
+            IpAddr::from([10, 0, 0, 1]),
+            IpAddr::from([10, 0, 0, 2]),
+            IpAddr::from([10, 0, 0, 3]),
+        );
+        let mut overrides = std::collections::HashMap::new();
+        overrides.insert("host1".to_owned(), vec![ip1, ip2]);
+        overrides.insert("host2".to_owned(), vec![ip3]);
+        let host_selector = HostSelectorBuilder::new(vec![
+            "http://host1".to_owned(),
+            "http://host2".to_owned(),
+            "http://127.0.0.1:8080".to_owned(),
+        ])
+        .should_punish_callback(Box::new(|_| true))
+        .punish_duration(Duration::from_millis(500))
+        .resolver(Some(Arc::new(StaticResolver::new(overrides))))
+        .build();
+        let err = HttpCallError::StatusCodeError(StatusCodeError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            None,
+            None,
+        ));
+        let punished_times = |host: &str| {
+            host_selector
+                .hosts_updater
+                .hosts_map
+                .get(host)
+                .unwrap()
+                .continuous_punished_times
+        };
+
+        let host_info = host_selector.select_host().unwrap();
+        assert_eq!(host_info.host, "http://host1");
+        assert_eq!(host_info.ip, Some(ip1));
+        assert!(host_selector.punish_chosen(&host_info, &err));
+        assert_eq!(punished_times("http://host1"), 0);
+
+        let host_info = host_selector.select_host().unwrap();
+        assert_eq!(host_info.host, "http://host2");
+        assert_eq!(host_info.ip, Some(ip3));
+
+        let host_info = host_selector.select_host().unwrap();
+        assert_eq!(host_info.host, "http://127.0.0.1:8080");
+        assert_eq!(host_info.ip, None);
+
+        let host_info = host_selector.select_host().unwrap();
+        assert_eq!(host_info.host, "http://host1");
+        assert_eq!(host_info.ip, Some(ip2));
+        assert!(host_selector.punish_chosen(&host_info, &err));
+        assert_eq!(punished_times("http://host1"), 1);
+
+        host_selector.reward_chosen(&HostInfo {
+            host: "http://host1".to_owned(),
+            ip: Some(ip1),
+            timeout_power: 0,
+            timeout: Duration::from_secs(1),
+        });
+        assert_eq!(punished_times("http://host1"), 0);
+        assert!(host_selector.has_unpunished_ip("http://host1"));
+        assert_eq!(host_selector.hosts_updater.ips_map.len(), 1);
+    }
 }
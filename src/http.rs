@@ -0,0 +1,724 @@
+use super::{
+    config::{http_client_builder, on_config_updated, HTTP_CLIENT},
+    error::{ConfigError, ConfigResult, HttpCallResult},
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use rand::{thread_rng, Rng};
+use reqwest::{
+    blocking::{Body, Client as HTTPClient, ClientBuilder as HTTPClientBuilder},
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    tls::{Certificate, Identity, Version as TlsVersion},
+    Method, Proxy, StatusCode,
+};
+use std::{
+    fmt,
+    io::{empty, Cursor, Read, Result as IOResult},
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+        Arc,
+    },
+    time::Duration,
+};
+use tap::prelude::*;
+use url::{Host, Url};
+
+/// HTTP 调用器
+///
+/// 上传器发出的所有 HTTP 请求都将通过该接口发送，可以通过 [`crate::UploaderBuilder::http_caller`] 设置自定义实现，
+/// 用于添加中间件，替换 HTTP 客户端，或在测试中模拟服务器响应。
+///
+/// 请求超时应当以 [`std::io::ErrorKind::TimedOut`] 类型的 [`HttpCallError::LocalIoError`] 返回，
+/// 以便上传器正确调整域名的超时时长。
+///
+/// 如果实现支持直接连接请求指定的 [`HttpRequest::resolved_ip`]，应当令 [`HttpCaller::supports_resolved_ip`] 返回 true，
+/// 以便上传器以（域名，IP）为单位惩罚服务器。
+pub trait HttpCaller: fmt::Debug + Send + Sync {
+    /// 发送 HTTP 请求并返回响应
+    fn call(&self, request: HttpRequest) -> HttpCallResult<HttpResponse>;
+
+    /// 是否会直接连接请求指定的 IP 地址，默认为否
+    ///
+    /// 返回 false 时，上传器将不再为请求指定 IP 地址，并以域名为单位惩罚服务器
+    #[inline]
+    fn supports_resolved_ip(&self) -> bool {
+        false
+    }
+}
+
+/// HTTP 请求
+#[derive(Debug)]
+pub struct HttpRequest {
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: HttpRequestBody,
+    timeout: Option<Duration>,
+    resolved_ip: Option<IpAddr>,
+}
+
+impl HttpRequest {
+    /// 创建 HTTP 请求
+    #[inline]
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Default::default(),
+            body: Default::default(),
+            timeout: None,
+            resolved_ip: None,
+        }
+    }
+
+    /// 获取请求方法
+    #[inline]
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// 获取请求 URL
+    #[inline]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 获取请求头
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// 获取请求头的可变引用
+    #[inline]
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// 获取请求体
+    #[inline]
+    pub fn body(&self) -> &HttpRequestBody {
+        &self.body
+    }
+
+    /// 获取请求体的可变引用
+    #[inline]
+    pub fn body_mut(&mut self) -> &mut HttpRequestBody {
+        &mut self.body
+    }
+
+    /// 获取请求超时时长
+    #[inline]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// 获取请求超时时长的可变引用
+    #[inline]
+    pub fn timeout_mut(&mut self) -> &mut Option<Duration> {
+        &mut self.timeout
+    }
+
+    /// 获取请求域名解析后选中的 IP 地址
+    #[inline]
+    pub fn resolved_ip(&self) -> Option<IpAddr> {
+        self.resolved_ip
+    }
+
+    /// 获取请求域名解析后选中的 IP 地址的可变引用
+    #[inline]
+    pub fn resolved_ip_mut(&mut self) -> &mut Option<IpAddr> {
+        &mut self.resolved_ip
+    }
+}
+
+/// HTTP 请求体
+#[non_exhaustive]
+pub enum HttpRequestBody {
+    /// 空请求体
+    Empty,
+
+    /// 内存数据
+    Bytes(Vec<u8>),
+
+    /// 已知长度的输入流
+    Reader {
+        /// 输入流
+        reader: Box<dyn Read + Send>,
+        /// 输入流长度
+        size: u64,
+    },
+}
+
+impl HttpRequestBody {
+    /// 创建已知长度的输入流请求体
+    #[inline]
+    pub fn reader(reader: impl Read + Send + 'static, size: u64) -> Self {
+        Self::Reader {
+            reader: Box::new(reader),
+            size,
+        }
+    }
+
+    /// 获取请求体长度
+    #[inline]
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Empty => 0,
+            Self::Bytes(bytes) => bytes.len() as u64,
+            Self::Reader { size, .. } => *size,
+        }
+    }
+
+    /// 转换为输入流
+    #[inline]
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        match self {
+            Self::Empty => Box::new(empty()),
+            Self::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+            Self::Reader { reader, .. } => reader,
+        }
+    }
+}
+
+impl Default for HttpRequestBody {
+    #[inline]
+    fn default() -> Self {
+        Self::Empty
+    }
+}
+
+impl From<Vec<u8>> for HttpRequestBody {
+    #[inline]
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl fmt::Debug for HttpRequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("Empty"),
+            Self::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Self::Reader { size, .. } => f.debug_struct("Reader").field("size", size).finish(),
+        }
+    }
+}
+
+/// HTTP 响应
+pub struct HttpResponse {
+    status_code: StatusCode,
+    headers: HeaderMap,
+    body: Box<dyn Read + Send>,
+}
+
+impl HttpResponse {
+    /// 创建 HTTP 响应
+    #[inline]
+    pub fn new(
+        status_code: StatusCode,
+        headers: HeaderMap,
+        body: impl Read + Send + 'static,
+    ) -> Self {
+        Self {
+            status_code,
+            headers,
+            body: Box::new(body),
+        }
+    }
+
+    /// 获取响应状态码
+    #[inline]
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    /// 获取响应头
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// 获取响应头的可变引用
+    #[inline]
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+}
+
+impl Read for HttpResponse {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl fmt::Debug for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("status_code", &self.status_code)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+/// 基于 Reqwest 的 HTTP 调用器
+///
+/// 默认使用全局共享的 HTTP 客户端，该客户端将随七牛配置的更新而重新创建。
+/// 对于指定了 IP 地址的请求，将为每个（域名，端口，IP）创建专用的客户端
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestHttpCaller {
+    client: Option<HTTPClient>,
+    options: Option<HttpClientOptions>,
+    pinned_clients: Arc<PinnedClients>,
+}
+
+/// 固定 IP 地址的客户端缓存
+#[derive(Debug, Default)]
+struct PinnedClients {
+    clients: DashMap<(String, u16, IpAddr), PinnedClient>,
+    config_generation: AtomicUsize,
+    clock: AtomicU64,
+}
+
+#[derive(Debug)]
+struct PinnedClient {
+    client: HTTPClient,
+    last_used: AtomicU64,
+}
+
+/// 缓存的固定 IP 地址的客户端的最大数量，超出后将淘汰最久未使用的客户端
+const MAX_PINNED_CLIENTS: usize = 64;
+
+/// 七牛配置的更新次数，配置更新后需要重新创建固定 IP 地址的客户端
+static CONFIG_GENERATION: Lazy<AtomicUsize> = Lazy::new(|| {
+    AtomicUsize::new(0).tap(|_| {
+        on_config_updated(|| {
+            CONFIG_GENERATION.fetch_add(1, Relaxed);
+        })
+    })
+});
+
+impl ReqwestHttpCaller {
+    /// 使用指定的 Reqwest 客户端创建 HTTP 调用器
+    ///
+    /// 由于无法修改指定客户端的域名解析，请求指定的 IP 地址将被忽略
+    #[inline]
+    pub fn new(client: HTTPClient) -> Self {
+        Self {
+            client: Some(client),
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub(super) fn with_options(options: HttpClientOptions) -> ConfigResult<Self> {
+        Ok(Self {
+            client: Some(options.build_http_client()?),
+            options: Some(options),
+            ..Default::default()
+        })
+    }
+
+    #[inline]
+    fn pinned_client(&self, url: &str, ip: IpAddr) -> HttpCallResult<Option<HTTPClient>> {
+        self.pinned_client_of_generation(url, ip, CONFIG_GENERATION.load(Relaxed))
+    }
+
+    /// 获取指定七牛配置版本下固定 IP 地址的客户端，配置版本变化后此前缓存的客户端均将失效
+    fn pinned_client_of_generation(
+        &self,
+        url: &str,
+        ip: IpAddr,
+        config_generation: usize,
+    ) -> HttpCallResult<Option<HTTPClient>> {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return Ok(None),
+        };
+        let (domain, port) = match (url.host(), url.port_or_known_default()) {
+            (Some(Host::Domain(domain)), Some(port)) => (domain.to_owned(), port),
+            _ => return Ok(None),
+        };
+        if self
+            .pinned_clients
+            .config_generation
+            .swap(config_generation, Relaxed)
+            != config_generation
+        {
+            // 七牛配置更新后，客户端的超时时长等设置可能已经改变
+            self.pinned_clients.clients.clear();
+        }
+        let key = (domain, port, ip);
+        let now = self.pinned_clients.clock.fetch_add(1, Relaxed);
+        if let Some(pinned) = self.pinned_clients.clients.get(&key) {
+            pinned.last_used.store(now, Relaxed);
+            return Ok(Some(pinned.client.to_owned()));
+        }
+        let builder = match &self.options {
+            Some(options) => options.apply(http_client_builder()),
+            None => http_client_builder(),
+        };
+        let client = builder.resolve(&key.0, SocketAddr::new(ip, port)).build()?;
+        if self.pinned_clients.clients.len() >= MAX_PINNED_CLIENTS {
+            self.pinned_clients.evict_least_recently_used();
+        }
+        self.pinned_clients.clients.insert(
+            key,
+            PinnedClient {
+                client: client.to_owned(),
+                last_used: AtomicU64::new(now),
+            },
+        );
+        Ok(Some(client))
+    }
+}
+
+impl PinnedClients {
+    fn evict_least_recently_used(&self) {
+        let least_recently_used = self
+            .clients
+            .iter()
+            .min_by_key(|entry| entry.value().last_used.load(Relaxed))
+            .map(|entry| entry.key().to_owned());
+        if let Some(key) = least_recently_used {
+            self.clients.remove(&key);
+        }
+    }
+}
+
+impl HttpCaller for ReqwestHttpCaller {
+    fn call(&self, request: HttpRequest) -> HttpCallResult<HttpResponse> {
+        let HttpRequest {
+            method,
+            url,
+            headers,
+            body,
+            timeout,
+            resolved_ip,
+        } = request;
+        let pinned_client = match resolved_ip.filter(|_| self.supports_resolved_ip()) {
+            Some(ip) => self.pinned_client(&url, ip)?,
+            None => None,
+        };
+        let mut request_builder =
+            if let Some(client) = pinned_client.as_ref().or(self.client.as_ref()) {
+                client.request(method, url)
+            } else {
+                HTTP_CLIENT.read().unwrap().request(method, url)
+            }
+            .headers(headers);
+        if let Some(timeout) = timeout {
+            request_builder = request_builder.timeout(timeout);
+        }
+        request_builder = match body {
+            HttpRequestBody::Empty => request_builder,
+            HttpRequestBody::Bytes(bytes) => request_builder.body(bytes),
+            HttpRequestBody::Reader { reader, size } => {
+                request_builder.body(Body::sized(reader, size))
+            }
+        };
+        let response = request_builder.send()?;
+        Ok(HttpResponse {
+            status_code: response.status(),
+            headers: response.headers().to_owned(),
+            body: Box::new(response),
+        })
+    }
+
+    /// 用户指定的客户端无法固定 IP 地址，此时不支持
+    #[inline]
+    fn supports_resolved_ip(&self) -> bool {
+        self.client.is_none() || self.options.is_some()
+    }
+}
+
+/// 上传器专属的 Reqwest 客户端选项
+#[derive(Debug, Clone, Default)]
+pub(super) struct HttpClientOptions {
+    pub(super) proxy: Option<Proxy>,
+    pub(super) root_certificates: Vec<Certificate>,
+    pub(super) identity: Option<Identity>,
+    pub(super) min_tls_version: Option<TlsVersion>,
+}
+
+impl HttpClientOptions {
+    #[inline]
+    pub(super) fn is_empty(&self) -> bool {
+        self.proxy.is_none()
+            && self.root_certificates.is_empty()
+            && self.identity.is_none()
+            && self.min_tls_version.is_none()
+    }
+
+    /// 创建 Reqwest 客户端，TLS 后端不支持选项中的设置时返回错误
+    pub(super) fn build_http_client(&self) -> ConfigResult<HTTPClient> {
+        self.apply(http_client_builder())
+            .build()
+            .map_err(|err| ConfigError::InvalidConfig {
+                field: "http_client",
+                message: err.to_string(),
+            })
+    }
+
+    fn apply(&self, mut builder: HTTPClientBuilder) -> HTTPClientBuilder {
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.to_owned());
+        }
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.to_owned());
+        }
+        if let Some(identity) = &self.identity {
+            builder = builder.identity(identity.to_owned());
+        }
+        if let Some(min_tls_version) = self.min_tls_version {
+            builder = builder.min_tls_version(min_tls_version);
+        }
+        builder
+    }
+}
+
+/// multipart/form-data 请求体构建器
+pub(super) struct MultipartForm {
+    boundary: String,
+    body: Box<dyn Read + Send>,
+    size: u64,
+}
+
+impl MultipartForm {
+    pub(super) fn new() -> Self {
+        let mut rng = thread_rng();
+        Self {
+            boundary: format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>()),
+            body: Box::new(empty()),
+            size: 0,
+        }
+    }
+
+    pub(super) fn text(self, name: &str, value: impl Into<String>) -> Self {
+        let value = value.into().into_bytes();
+        let size = value.len() as u64;
+        self.part(name, None, None, Cursor::new(value), size)
+    }
+
+    pub(super) fn part(
+        mut self,
+        name: &str,
+        file_name: Option<&str>,
+        mime_type: Option<&str>,
+        reader: impl Read + Send + 'static,
+        size: u64,
+    ) -> Self {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape_quoted(name)
+        );
+        if let Some(file_name) = file_name {
+            header.push_str(&format!("; filename=\"{}\"", escape_quoted(file_name)));
+        }
+        if let Some(mime_type) = mime_type {
+            header.push_str(&format!("\r\nContent-Type: {}", mime_type));
+        }
+        header.push_str("\r\n\r\n");
+        self.size += header.len() as u64 + size + 2;
+        self.body = Box::new(
+            self.body
+                .chain(Cursor::new(header.into_bytes()))
+                .chain(reader.take(size))
+                .chain(&b"\r\n"[..]),
+        );
+        self
+    }
+
+    pub(super) fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("multipart/form-data; boundary={}", self.boundary))
+            .expect("Invalid multipart boundary")
+    }
+
+    pub(super) fn into_body(self) -> HttpRequestBody {
+        let footer = format!("--{}--\r\n", self.boundary);
+        let size = self.size + footer.len() as u64;
+        HttpRequestBody::reader(self.body.chain(Cursor::new(footer.into_bytes())), size)
+    }
+
+    pub(super) fn into_request(self, mut request: HttpRequest) -> HttpRequest {
+        request.headers.insert(CONTENT_TYPE, self.content_type());
+        request.body = self.into_body();
+        request
+    }
+}
+
+fn escape_quoted(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::oneshot::channel;
+    use serde_json::{json, Value as JSONValue};
+    use tokio::task::{spawn, spawn_blocking};
+    use warp::{header, path, reply::json as reply_json, Filter};
+
+    macro_rules! starts_with_server {
+        ($addr:ident, $routes:ident, $code:block) => {{
+            let (tx, rx) = channel();
+            let ($addr, server) =
+                warp::serve($routes).bind_with_graceful_shutdown(([127, 0, 0, 1], 0), async move {
+                    rx.await.ok();
+                });
+            let handler = spawn(server);
+            $code;
+            tx.send(()).ok();
+            handler.await.ok();
+        }};
+    }
+
+    #[test]
+    fn test_multipart_form() -> anyhow::Result<()> {
+        let form = MultipartForm::new()
+            .text("key", "test\"key")
+            .part(
+                "file",
+                Some("a.txt"),
+                Some("text/plain"),
+                &b"hello world, ignored"[..],
+                11,
+            )
+            .text("x:var", "value");
+        let boundary = form.boundary.to_owned();
+        assert_eq!(
+            form.content_type(),
+            HeaderValue::from_str(&format!("multipart/form-data; boundary={}", boundary))?
+        );
+        let body = form.into_body();
+        let size = body.size();
+        let mut buf = String::new();
+        body.into_reader().read_to_string(&mut buf)?;
+        assert_eq!(
+            buf,
+            format!(
+                "--{0}\r\nContent-Disposition: form-data; name=\"key\"\r\n\r\ntest\"key\r\n\
+                 --{0}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                 Content-Type: text/plain\r\n\r\nhello world\r\n\
+                 --{0}\r\nContent-Disposition: form-data; name=\"x:var\"\r\n\r\nvalue\r\n\
+                 --{0}--\r\n",
+                boundary
+            )
+        );
+        assert_eq!(size, buf.len() as u64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_http_caller_with_proxy() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        const HOST: &str = "uc.qiniu-proxy-test.invalid";
+
+        let routes = path!("v4" / "query")
+            .and(header::<String>("host"))
+            .map(|host: String| {
+                assert_eq!(host, HOST);
+                reply_json(&json!({ "proxied": true }))
+            });
+        starts_with_server!(addr, routes, {
+            spawn_blocking(move || -> anyhow::Result<()> {
+                let http_caller = ReqwestHttpCaller::new(
+                    HttpClientOptions {
+                        proxy: Some(Proxy::all(format!("http://{}", addr))?),
+                        ..Default::default()
+                    }
+                    .build_http_client()?,
+                );
+                let mut response = http_caller.call(HttpRequest::new(
+                    Method::GET,
+                    format!("http://{}/v4/query", HOST),
+                ))?;
+                assert_eq!(response.status_code(), StatusCode::OK);
+                let body: JSONValue = serde_json::from_reader(&mut response)?;
+                assert_eq!(body["proxied"], true);
+                Ok(())
+            })
+            .await??;
+        });
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reqwest_http_caller_with_resolved_ip() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        const DOMAIN: &str = "up.qiniu-resolve-test.invalid";
+
+        let routes = path!("v4" / "query").map(|| reply_json(&json!({ "pinned": true })));
+        starts_with_server!(addr, routes, {
+            spawn_blocking(move || -> anyhow::Result<()> {
+                let http_caller = ReqwestHttpCaller::default();
+                for _ in 0..2 {
+                    let mut request = HttpRequest::new(
+                        Method::GET,
+                        format!("http://{}:{}/v4/query", DOMAIN, addr.port()),
+                    );
+                    *request.resolved_ip_mut() = Some(addr.ip());
+                    let mut response = http_caller.call(request)?;
+                    assert_eq!(response.status_code(), StatusCode::OK);
+                    let body: JSONValue = serde_json::from_reader(&mut response)?;
+                    assert_eq!(body["pinned"], true);
+                }
+                assert_eq!(http_caller.pinned_clients.clients.len(), 1);
+                Ok(())
+            })
+            .await??;
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_clients_are_bounded() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let http_caller = ReqwestHttpCaller::default();
+        assert!(http_caller.supports_resolved_ip());
+        assert!(!ReqwestHttpCaller::new(HTTPClient::new()).supports_resolved_ip());
+
+        // 使用独立的配置版本，避免影响并行运行的其他测试
+        let generation = 0;
+        let ip_of = |i: usize| IpAddr::from([10, 0, (i >> 8) as u8, i as u8]);
+        let pinned_client = |i: usize, generation: usize| {
+            http_caller.pinned_client_of_generation("http://up.qiniup.com", ip_of(i), generation)
+        };
+        for i in 0..=MAX_PINNED_CLIENTS {
+            assert!(pinned_client(i, generation)?.is_some());
+            // 持续使用第一个客户端，使其不会被淘汰
+            pinned_client(0, generation)?;
+            assert!(http_caller.pinned_clients.clients.len() <= MAX_PINNED_CLIENTS);
+        }
+        let contains = |i: usize| {
+            http_caller.pinned_clients.clients.contains_key(&(
+                "up.qiniup.com".to_owned(),
+                80,
+                ip_of(i),
+            ))
+        };
+        assert_eq!(http_caller.pinned_clients.clients.len(), MAX_PINNED_CLIENTS);
+        assert!(contains(0));
+        assert!(!contains(1));
+        assert!(contains(MAX_PINNED_CLIENTS));
+        assert!(http_caller
+            .pinned_client_of_generation(
+                "http://127.0.0.1",
+                IpAddr::from([127, 0, 0, 1]),
+                generation
+            )?
+            .is_none());
+
+        pinned_client(0, generation + 1)?;
+        assert_eq!(http_caller.pinned_clients.clients.len(), 1);
+        Ok(())
+    }
+}
@@ -8,19 +8,54 @@
 //! 负责上传七牛对象
 
 mod base64;
+mod batch;
+mod buffer_pool;
+mod cancellation;
 mod config;
 mod credential;
+mod directory;
 mod error;
+mod etag;
 mod host_selector;
+mod http;
+mod progress;
 mod query;
+mod rate_limiter;
 mod reader;
+mod resolver;
+mod sync;
 mod upload_apis;
 mod upload_policy;
 mod upload_token;
 mod uploader;
+mod watch;
+mod writer;
 
-pub use config::{Config, ConfigBuilder, ServiceName};
-pub use error::{HttpCallError, HttpCallResult, JsonDecodeError, StatusCodeError};
-pub use uploader::{
-    UploadProgressCallback, UploadProgressInfo, UploadRequestBuilder, Uploader, UploaderBuilder,
+#[cfg(test)]
+mod test_utils;
+
+pub use batch::{
+    BatchUploadJob, BatchUploadJobResult, BatchUploadOutcome, BatchUploadProgressCallback,
+    BatchUploadProgressInfo, BatchUploadResults, BatchUploadSummary, BatchUploader,
+};
+pub use cancellation::{CancellationToken, PauseHandle};
+pub use config::{
+    subscribe_config_updates, validate_config_updates, Config, ConfigBuilder, ConfigChange,
+    ServiceName,
+};
+pub use directory::{DirUploadBuilder, ObjectNameMapper};
+pub use error::{
+    ConfigError, ConfigResult, HttpCallError, HttpCallResult, JsonDecodeError, StatusCodeError,
+};
+pub use host_selector::HostStat;
+pub use http::{HttpCaller, HttpRequest, HttpRequestBody, HttpResponse, ReqwestHttpCaller};
+pub use progress::{UploadPhase, UploadProgressCallback, UploadProgressInfo};
+pub use rate_limiter::RateLimiter;
+pub use reqwest;
+pub use resolver::{CachedResolver, Resolver, StaticResolver, SystemResolver};
+pub use sync::{SyncManifest, SyncManifestEntry};
+pub use uploader::{ObjectStat, UploadRequestBuilder, Uploader, UploaderBuilder};
+pub use watch::{
+    AfterUploadAction, DirWatcher, WatchEvent, WatchEventCallback, WatchUploadBuilder,
 };
+pub use writer::UploadWriter;
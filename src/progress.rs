@@ -0,0 +1,322 @@
+use super::error::{HttpCallError, HttpCallResult};
+use once_cell::sync::OnceCell;
+use std::{
+    fmt,
+    io::{Error as IOError, Read, Result as IOResult},
+    sync::{
+        atomic::{
+            AtomicU64, AtomicUsize,
+            Ordering::{Relaxed, SeqCst},
+        },
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// 上传进度回调函数
+///
+/// 回调函数返回错误将导致上传中止，上传将返回包含该错误的 [`HttpCallError::Aborted`]，且不会重试
+pub type UploadProgressCallback =
+    Box<dyn Fn(&UploadProgressInfo) -> HttpCallResult<()> + Send + Sync + 'static>;
+
+/// 上传阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UploadPhase {
+    /// 正在计算数据校验和
+    Hashing,
+    /// 正在以表单方式上传数据
+    FormUploading,
+    /// 正在初始化分片上传
+    InitParts,
+    /// 分片开始上传
+    PartStarted(u32),
+    /// 分片上传失败，即将重试
+    PartRetrying(u32),
+    /// 分片上传成功
+    PartFinished(u32),
+    /// 正在合并分片
+    CompletingParts,
+}
+
+/// 上传进度信息
+#[derive(Debug, Clone)]
+pub struct UploadProgressInfo<'a> {
+    phase: UploadPhase,
+    upload_id: Option<&'a str>,
+    uploaded: u64,
+    total_size: Option<u64>,
+    bytes_per_second: u64,
+    eta: Option<Duration>,
+    retries: usize,
+}
+
+impl<'a> UploadProgressInfo<'a> {
+    /// 获取当前上传阶段
+    #[inline]
+    pub fn phase(&self) -> UploadPhase {
+        self.phase
+    }
+
+    /// 获取 Upload ID，仅在分片上传初始化成功后存在
+    #[inline]
+    pub fn upload_id(&self) -> Option<&str> {
+        self.upload_id
+    }
+
+    /// 获取已经上传的数据量
+    #[inline]
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    /// 获取数据总长度，如果数据源长度未知则返回 None
+    #[inline]
+    pub fn total_size(&self) -> Option<u64> {
+        self.total_size
+    }
+
+    /// 获取自上传开始以来的平均上传速度，单位为字节每秒
+    #[inline]
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// 获取预计剩余上传时间，如果数据总长度未知或尚未上传任何数据则返回 None
+    #[inline]
+    pub fn eta(&self) -> Option<Duration> {
+        self.eta
+    }
+
+    /// 获取本次上传累计重试的次数
+    #[inline]
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// 获取当前阶段对应的分片号码，如果当前阶段与分片无关则返回 None
+    #[inline]
+    pub fn part_number(&self) -> Option<u32> {
+        match self.phase {
+            UploadPhase::PartStarted(part_number)
+            | UploadPhase::PartRetrying(part_number)
+            | UploadPhase::PartFinished(part_number) => Some(part_number),
+            _ => None,
+        }
+    }
+}
+
+/// 上传进度汇报器，负责统计上传进度并调用上传进度回调函数
+#[derive(Clone)]
+pub(super) struct ProgressReporter {
+    inner: Arc<ProgressReporterInner>,
+}
+
+struct ProgressReporterInner {
+    callback: UploadProgressCallback,
+    total_size: Option<u64>,
+    started_at: Instant,
+    uploaded: AtomicU64,
+    transferring: AtomicU64,
+    retries: AtomicUsize,
+    upload_id: OnceCell<String>,
+    aborted: Mutex<Option<HttpCallError>>,
+}
+
+impl ProgressReporter {
+    #[inline]
+    pub(super) fn new(callback: UploadProgressCallback, total_size: Option<u64>) -> Self {
+        Self {
+            inner: Arc::new(ProgressReporterInner {
+                callback,
+                total_size,
+                started_at: Instant::now(),
+                uploaded: Default::default(),
+                transferring: Default::default(),
+                retries: Default::default(),
+                upload_id: Default::default(),
+                aborted: Default::default(),
+            }),
+        }
+    }
+
+    #[inline]
+    pub(super) fn set_upload_id(&self, upload_id: &str) {
+        self.inner.upload_id.set(upload_id.to_owned()).ok();
+    }
+
+    /// 汇报进入新的上传阶段
+    pub(super) fn report(&self, phase: UploadPhase) -> HttpCallResult<()> {
+        let uploaded = self
+            .inner
+            .uploaded
+            .load(SeqCst)
+            .saturating_add(self.inner.transferring.load(SeqCst));
+        let elapsed = self.inner.started_at.elapsed().as_secs_f64();
+        let bytes_per_second = if elapsed > 0.0 {
+            (uploaded as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let eta = self.inner.total_size.and_then(|total_size| {
+            if bytes_per_second > 0 {
+                Some(Duration::from_secs_f64(
+                    total_size.saturating_sub(uploaded) as f64 / bytes_per_second as f64,
+                ))
+            } else {
+                None
+            }
+        });
+        (self.inner.callback)(&UploadProgressInfo {
+            phase,
+            upload_id: self
+                .inner
+                .upload_id
+                .get()
+                .map(|upload_id| upload_id.as_str()),
+            uploaded,
+            total_size: self.inner.total_size,
+            bytes_per_second,
+            eta,
+            retries: self.inner.retries.load(Relaxed),
+        })
+        .map_err(|err| HttpCallError::Aborted(Box::new(err)))
+    }
+
+    /// 取出在输入流中发生的中止错误
+    ///
+    /// 输入流只能返回 IO 错误，因此回调函数在读取数据时中止上传，中止错误将暂存在汇报器中
+    #[inline]
+    pub(super) fn take_aborted(&self) -> Option<HttpCallError> {
+        self.inner.aborted.lock().unwrap().take()
+    }
+
+    /// 汇报请求失败即将重试，正在传输的数据量将被清零
+    pub(super) fn retried(&self, phase: UploadPhase) -> HttpCallResult<()> {
+        self.inner.retries.fetch_add(1, Relaxed);
+        self.inner.transferring.store(0, SeqCst);
+        self.report(phase)
+    }
+
+    /// 记录已经上传成功的数据量
+    #[inline]
+    pub(super) fn add_uploaded(&self, uploaded: u64) {
+        self.inner.transferring.store(0, SeqCst);
+        self.inner.uploaded.fetch_add(uploaded, SeqCst);
+    }
+
+    fn transferred(&self, transferred: u64) -> HttpCallResult<()> {
+        self.inner.transferring.fetch_add(transferred, SeqCst);
+        self.report(UploadPhase::FormUploading)
+    }
+}
+
+impl fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("total_size", &self.inner.total_size)
+            .field("uploaded", &self.inner.uploaded)
+            .field("transferring", &self.inner.transferring)
+            .field("retries", &self.inner.retries)
+            .field("upload_id", &self.inner.upload_id)
+            .finish()
+    }
+}
+
+/// 汇报字节级上传进度的输入流，每次读取后都将调用上传进度回调函数
+#[derive(Debug)]
+pub(super) struct ProgressReader<R> {
+    inner: R,
+    reporter: Option<ProgressReporter>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    #[inline]
+    pub(super) fn new(inner: R, reporter: Option<ProgressReporter>) -> Self {
+        Self { inner, reporter }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let have_read = self.inner.read(buf)?;
+        if let Some(reporter) = &self.reporter {
+            if have_read > 0 {
+                if let Err(err) = reporter.transferred(have_read as u64) {
+                    let io_err = IOError::other(err.to_string());
+                    *reporter.inner.aborted.lock().unwrap() = Some(err);
+                    return Err(io_err);
+                }
+            }
+        }
+        Ok(have_read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{copy, repeat, sink},
+        sync::Mutex,
+    };
+
+    #[test]
+    fn test_progress_reporter() -> anyhow::Result<()> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let reporter = {
+            let events = events.to_owned();
+            ProgressReporter::new(
+                Box::new(move |info| {
+                    events.lock().unwrap().push((
+                        info.phase(),
+                        info.uploaded(),
+                        info.retries(),
+                        info.upload_id().map(|id| id.to_owned()),
+                    ));
+                    Ok(())
+                }),
+                Some(300),
+            )
+        };
+
+        let mut reader = ProgressReader::new(repeat(b'a').take(100), Some(reporter.to_owned()));
+        copy(&mut reader, &mut sink())?;
+        reporter.retried(UploadPhase::FormUploading)?;
+        reporter.set_upload_id("fake-upload-id");
+        reporter.add_uploaded(200);
+        reporter.report(UploadPhase::PartFinished(1))?;
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events.as_slice(),
+            &[
+                (UploadPhase::FormUploading, 100, 0, None),
+                (UploadPhase::FormUploading, 0, 1, None),
+                (
+                    UploadPhase::PartFinished(1),
+                    200,
+                    1,
+                    Some("fake-upload-id".to_owned())
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_reader_aborted_by_callback() {
+        let reporter = ProgressReporter::new(
+            Box::new(|info| {
+                if info.uploaded() > 0 {
+                    Err(IOError::other("aborted").into())
+                } else {
+                    Ok(())
+                }
+            }),
+            None,
+        );
+        let mut reader = ProgressReader::new(repeat(b'a').take(100), Some(reporter));
+        assert!(copy(&mut reader, &mut sink()).is_err());
+    }
+}
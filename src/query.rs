@@ -1,9 +1,15 @@
-use super::{config::HTTP_CLIENT, host_selector::HostSelector};
+use super::{
+    host_selector::{HostInfo, HostSelector},
+    http::{HttpCaller, HttpRequest},
+};
 use crate::error::{json_decode_response, HttpCallError, HttpCallResult};
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use directories::BaseDirs;
+use fs2::FileExt;
+use log::warn;
 use once_cell::sync::Lazy;
-use reqwest::{header::HeaderValue, StatusCode};
+use rand::{thread_rng, Rng};
+use reqwest::{header::HeaderValue, Method, StatusCode};
 use serde::{
     de::{Error as DeError, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -13,11 +19,12 @@ use std::{
     collections::HashMap,
     env::temp_dir,
     fmt,
-    fs::{create_dir_all, OpenOptions},
-    io::{Error as IOError, ErrorKind as IOErrorKind, Result as IOResult},
-    path::PathBuf,
+    fs::{create_dir_all, remove_file, rename, File, OpenOptions},
+    io::{BufReader, BufWriter, Error as IOError, ErrorKind as IOErrorKind, Result as IOResult},
+    path::{Path, PathBuf},
+    process,
     result::Result,
-    sync::Mutex,
+    sync::{Arc, Mutex},
     thread::spawn,
     time::{Duration, SystemTime},
 };
@@ -88,6 +95,8 @@ struct ResponseBody {
 struct RegionResponseBody {
     ttl: u64,
     up: DomainsResponseBody,
+    #[serde(default)]
+    rs: Option<DomainsResponseBody>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,14 +119,20 @@ static CACHE_INIT: Lazy<()> = Lazy::new(|| {
 pub(super) struct HostsQuerier {
     uc_selector: HostSelector,
     uc_tries: usize,
+    http_caller: Arc<dyn HttpCaller>,
 }
 
 impl HostsQuerier {
     #[inline]
-    pub(super) fn new(uc_selector: HostSelector, uc_tries: usize) -> Self {
+    pub(super) fn new(
+        uc_selector: HostSelector,
+        uc_tries: usize,
+        http_caller: Arc<dyn HttpCaller>,
+    ) -> Self {
         Self {
             uc_selector,
             uc_tries,
+            http_caller,
         }
     }
 
@@ -130,7 +145,7 @@ impl HostsQuerier {
         Lazy::force(&CACHE_INIT);
 
         let response_body = self.query_for_domains(ak, bucket)?;
-        return Ok(response_body
+        Ok(response_body
             .hosts
             .first()
             .expect("No host in uc query v4 response body")
@@ -138,17 +153,31 @@ impl HostsQuerier {
             .domains
             .iter()
             .map(|domain| normalize_domain(domain, use_https))
-            .collect());
-
-        fn normalize_domain(domain: &str, use_https: bool) -> String {
-            if domain.contains("://") {
-                domain.to_string()
-            } else if use_https {
-                "https://".to_owned() + domain
-            } else {
-                "http://".to_owned() + domain
-            }
-        }
+            .collect())
+    }
+
+    pub(super) fn query_for_rs_urls(
+        &self,
+        ak: &str,
+        bucket: &str,
+        use_https: bool,
+    ) -> HttpCallResult<Vec<String>> {
+        Lazy::force(&CACHE_INIT);
+
+        let response_body = self.query_for_domains(ak, bucket)?;
+        Ok(response_body
+            .hosts
+            .first()
+            .expect("No host in uc query v4 response body")
+            .rs
+            .as_ref()
+            .map(|rs| {
+                rs.domains
+                    .iter()
+                    .map(|domain| normalize_domain(domain, use_https))
+                    .collect()
+            })
+            .unwrap_or_default())
     }
 
     fn query_for_domains(&self, ak: &str, bucket: &str) -> HttpCallResult<ResponseBody> {
@@ -158,8 +187,13 @@ impl HostsQuerier {
         let cache_value = CACHE_MAP
             .entry(cache_key.to_owned())
             .or_try_insert_with(|| {
-                let result =
-                    query_for_domains_without_cache(ak, bucket, &self.uc_selector, self.uc_tries);
+                let result = query_for_domains_without_cache(
+                    ak,
+                    bucket,
+                    &self.uc_selector,
+                    self.uc_tries,
+                    self.http_caller.as_ref(),
+                );
                 if result.is_ok() {
                     modified = true;
                 }
@@ -171,13 +205,18 @@ impl HostsQuerier {
             let bucket = bucket.to_owned();
             let uc_selector = self.uc_selector.to_owned();
             let uc_tries = self.uc_tries;
+            let http_caller = self.http_caller.to_owned();
             spawn(move || {
                 let mut modified = false;
                 CACHE_MAP.entry(cache_key).and_modify(|cache_value| {
                     if cache_value.cache_deadline < SystemTime::now() {
-                        if let Ok(new_cache_value) =
-                            query_for_domains_without_cache(ak, bucket, &uc_selector, uc_tries)
-                        {
+                        if let Ok(new_cache_value) = query_for_domains_without_cache(
+                            ak,
+                            bucket,
+                            &uc_selector,
+                            uc_tries,
+                            http_caller.as_ref(),
+                        ) {
                             *cache_value = new_cache_value;
                             modified = true;
                         }
@@ -197,32 +236,42 @@ impl HostsQuerier {
     }
 }
 
+fn normalize_domain(domain: &str, use_https: bool) -> String {
+    if domain.contains("://") {
+        domain.to_string()
+    } else if use_https {
+        "https://".to_owned() + domain
+    } else {
+        "http://".to_owned() + domain
+    }
+}
+
 fn query_for_domains_without_cache(
     ak: impl AsRef<str>,
     bucket: impl AsRef<str>,
     uc_selector: &HostSelector,
     uc_tries: usize,
+    http_caller: &dyn HttpCaller,
 ) -> HttpCallResult<CacheValue> {
-    return query_with_retry(uc_selector, uc_tries, |host, timeout_power, timeout| {
+    let supports_resolved_ip = http_caller.supports_resolved_ip();
+    return query_with_retry(uc_selector, uc_tries, supports_resolved_ip, |host_info| {
         let url = Url::parse_with_params(
-            &format!("{}/v4/query", host),
+            &format!("{}/v4/query", host_info.host),
             &[("ak", ak.as_ref()), ("bucket", bucket.as_ref())],
         )?;
 
-        HTTP_CLIENT
-            .read()
-            .unwrap()
-            .get(&url.to_string())
-            .timeout(timeout)
-            .send()
+        let mut request = HttpRequest::new(Method::GET, url.to_string());
+        *request.timeout_mut() = Some(host_info.timeout);
+        *request.resolved_ip_mut() = host_info.ip;
+        http_caller
+            .call(request)
             .tap_err(|err| {
                 if err.is_timeout() {
-                    uc_selector.increase_timeout_power_by(host, timeout_power);
+                    uc_selector.increase_timeout_power_by(&host_info.host, host_info.timeout_power);
                 }
             })
-            .map_err(HttpCallError::from)
             .and_then(|resp| {
-                if resp.status() != StatusCode::OK {
+                if resp.status_code() != StatusCode::OK {
                     Err(HttpCallError::from(resp))
                 } else {
                     json_decode_response(resp)
@@ -245,18 +294,25 @@ fn query_for_domains_without_cache(
     fn query_with_retry<T>(
         uc_selector: &HostSelector,
         tries: usize,
-        mut for_each_host: impl FnMut(&str, usize, Duration) -> HttpCallResult<T>,
+        supports_resolved_ip: bool,
+        mut for_each_host: impl FnMut(&HostInfo) -> HttpCallResult<T>,
     ) -> HttpCallResult<T> {
         let mut last_error = None;
         for _ in 0..tries {
-            let host_info = uc_selector.select_host();
-            match for_each_host(&host_info.host, host_info.timeout_power, host_info.timeout) {
+            let mut host_info = uc_selector
+                .select_host()
+                .ok_or(HttpCallError::NoAvailableHosts)?;
+            if !supports_resolved_ip {
+                // HTTP 调用器不会连接选中的 IP 地址，因此只能以域名为单位惩罚服务器
+                host_info.ip = None;
+            }
+            match for_each_host(&host_info) {
                 Ok(response) => {
-                    uc_selector.reward(&host_info.host);
+                    uc_selector.reward_chosen(&host_info);
                     return Ok(response);
                 }
                 Err(err) => {
-                    let punished = uc_selector.punish(&host_info.host, &err);
+                    let punished = uc_selector.punish_chosen(&host_info, &err);
                     if !punished {
                         return Err(err);
                     }
@@ -268,68 +324,139 @@ fn query_for_domains_without_cache(
     }
 }
 
+const CACHE_FILE_NAME: &str = "query-cache.json";
+const CACHE_LOCK_FILE_NAME: &str = "query-cache.lock";
+
 fn load_cache() -> IOResult<()> {
-    let cache_file_path = CACHE_DIR.join("query-cache.json");
-    if let Ok(cache_file) = OpenOptions::new().read(true).open(&cache_file_path) {
-        let cache: HashMap<CacheKey, CacheValue> =
-            json_from_reader(cache_file).map_err(|err| IOError::new(IOErrorKind::Other, err))?;
-        CACHE_MAP.clear();
-        for (key, value) in cache.into_iter() {
-            CACHE_MAP.insert(key, value);
-        }
-    }
+    let cache = read_cache_file(&CACHE_DIR)?;
+    merge_cache(&CACHE_MAP, cache);
     Ok(())
 }
 
 fn save_cache() -> IOResult<()> {
-    let cache_file_path = CACHE_DIR.join("query-cache.json");
+    // 进程内的写入由互斥锁串行化，进程间的写入则由缓存目录下的文件锁串行化
+    let _cache_file_lock = CACHE_FILE_LOCK.lock().unwrap();
+    write_cache_file(&CACHE_DIR, &CACHE_MAP)
+}
+
+fn read_cache_file(cache_dir: &Path) -> IOResult<HashMap<CacheKey, CacheValue>> {
+    let lock_file = match open_lock_file(cache_dir, false) {
+        Ok(lock_file) => lock_file,
+        // 旧版本写入缓存文件时不会创建锁文件，此时直接读取缓存文件
+        Err(err) if err.kind() == IOErrorKind::NotFound => {
+            return Ok(read_cache_file_without_lock(cache_dir))
+        }
+        Err(err) => return Err(err),
+    };
+    lock_file.lock_shared()?;
+    let cache = read_cache_file_without_lock(cache_dir);
+    lock_file.unlock()?;
+    Ok(cache)
+}
 
-    let cache_file_lock_result = CACHE_FILE_LOCK.try_lock();
-    if cache_file_lock_result.is_err() {
-        return Ok(());
+fn read_cache_file_without_lock(cache_dir: &Path) -> HashMap<CacheKey, CacheValue> {
+    let cache_file_path = cache_dir.join(CACHE_FILE_NAME);
+    let cache_file = match OpenOptions::new().read(true).open(&cache_file_path) {
+        Ok(cache_file) => cache_file,
+        Err(_) => return Default::default(),
+    };
+    match json_from_reader(BufReader::new(cache_file)) {
+        Ok(cache) => cache,
+        Err(err) => {
+            // 缓存文件损坏时直接忽略，下一次写入时会被整体替换
+            warn!(
+                "Qiniu query cache file {:?} is corrupted, ignore it: {}",
+                cache_file_path, err
+            );
+            Default::default()
+        }
     }
+}
 
-    let mut cache_file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&cache_file_path)
-        .or_else(|err| {
-            if err.kind() == IOErrorKind::NotFound {
-                create_dir_all(&*CACHE_DIR)?;
+fn write_cache_file(cache_dir: &Path, cache_map: &DashMap<CacheKey, CacheValue>) -> IOResult<()> {
+    create_dir_all(cache_dir)?;
+    let lock_file = open_lock_file(cache_dir, true)?;
+    lock_file.lock_exclusive()?;
+    let result = write_cache_file_without_lock(cache_dir, cache_map);
+    lock_file.unlock()?;
+    return result;
+
+    fn write_cache_file_without_lock(
+        cache_dir: &Path,
+        cache_map: &DashMap<CacheKey, CacheValue>,
+    ) -> IOResult<()> {
+        // 其他进程可能已经写入了更新的缓存，先合并再写入
+        merge_cache(cache_map, read_cache_file_without_lock(cache_dir));
+
+        let temp_file_path = cache_dir.join(format!(
+            ".{}.{}.{}.tmp",
+            CACHE_FILE_NAME,
+            process::id(),
+            thread_rng().gen::<u32>(),
+        ));
+        let result = (|| {
+            let mut temp_file = BufWriter::new(
                 OpenOptions::new()
                     .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(&cache_file_path)
-            } else {
-                Err(err)
+                    .create_new(true)
+                    .open(&temp_file_path)?,
+            );
+            json_to_writer(&mut temp_file, cache_map).map_err(IOError::other)?;
+            temp_file
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()?;
+            rename(&temp_file_path, cache_dir.join(CACHE_FILE_NAME))
+        })();
+        if result.is_err() {
+            remove_file(&temp_file_path).ok();
+        }
+        result
+    }
+}
+
+fn open_lock_file(cache_dir: &Path, create: bool) -> IOResult<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(create)
+        .truncate(false)
+        .open(cache_dir.join(CACHE_LOCK_FILE_NAME))
+}
+
+fn merge_cache(cache_map: &DashMap<CacheKey, CacheValue>, cache: HashMap<CacheKey, CacheValue>) {
+    for (key, value) in cache.into_iter() {
+        match cache_map.entry(key) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().cache_deadline < value.cache_deadline {
+                    entry.insert(value);
+                }
             }
-        })?;
-    json_to_writer(&mut cache_file, &*CACHE_MAP)
-        .map_err(|err| IOError::new(IOErrorKind::Other, err))?;
-    Ok(())
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 fn clear_cache() -> IOResult<()> {
-    let cache_file_path = CACHE_DIR.join("query-cache.json");
+    let cache_file_path = CACHE_DIR.join(CACHE_FILE_NAME);
     std::fs::remove_file(&cache_file_path)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http::ReqwestHttpCaller;
     use futures::channel::oneshot::channel;
     use serde::Serialize;
     use serde_json::json;
     use std::{
-        sync::{
-            atomic::{AtomicUsize, Ordering::Relaxed},
-            Arc,
-        },
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
         thread::sleep,
     };
+    use tempfile::tempdir;
     use tokio::task::{spawn, spawn_blocking};
     use warp::{path, reply::json as reply_json, Filter};
 
@@ -376,6 +503,11 @@ mod tests {
                           "domains": [
                             "up.qiniup.com"
                           ]
+                        },
+                        "rs": {
+                          "domains": [
+                            "rs.qiniu.com"
+                          ]
                         }
                     }]
                 }))
@@ -384,12 +516,12 @@ mod tests {
             spawn_blocking(move || -> anyhow::Result<()> {
                 let host_selector =
                     HostSelector::builder(vec!["http://".to_owned() + &addr.to_string()]).build();
-                let up_urls = HostsQuerier::new(host_selector, 1).query_for_up_urls(
-                    ACCESS_KEY,
-                    BUCKET_NAME,
-                    false,
-                )?;
+                let hosts_querier =
+                    HostsQuerier::new(host_selector, 1, Arc::new(ReqwestHttpCaller::default()));
+                let up_urls = hosts_querier.query_for_up_urls(ACCESS_KEY, BUCKET_NAME, false)?;
                 assert_eq!(up_urls, vec!["http://up.qiniup.com".to_owned()]);
+                let rs_urls = hosts_querier.query_for_rs_urls(ACCESS_KEY, BUCKET_NAME, true)?;
+                assert_eq!(rs_urls, vec!["https://rs.qiniu.com".to_owned()]);
                 Ok(())
             })
             .await??;
@@ -405,7 +537,7 @@ mod tests {
         let _ = clear_cache();
 
         const ACCESS_KEY: &str = "0123456789001234567890";
-        const BUCKET_NAME: &str = "test-bucket";
+        const BUCKET_NAME: &str = "test-cache-bucket";
         let counter = Arc::new(AtomicUsize::new(0));
 
         let routes = {
@@ -433,7 +565,8 @@ mod tests {
             spawn_blocking(move || -> anyhow::Result<()> {
                 let host_selector =
                     HostSelector::builder(vec!["http://".to_owned() + &addr.to_string()]).build();
-                let hosts_querier = HostsQuerier::new(host_selector, 1);
+                let hosts_querier =
+                    HostsQuerier::new(host_selector, 1, Arc::new(ReqwestHttpCaller::default()));
                 let mut up_urls =
                     hosts_querier.query_for_up_urls(ACCESS_KEY, BUCKET_NAME, false)?;
                 assert_eq!(up_urls, vec!["http://up.qiniup.com".to_owned()]);
@@ -465,4 +598,124 @@ mod tests {
         });
         Ok(())
     }
+
+    #[test]
+    fn test_load_corrupted_cache_file() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let cache_dir = tempdir()?;
+        std::fs::write(cache_dir.path().join(CACHE_FILE_NAME), b"{\"ak:bucket\": {")?;
+        open_lock_file(cache_dir.path(), true)?;
+        assert!(read_cache_file(cache_dir.path())?.is_empty());
+
+        let cache_map = DashMap::new();
+        cache_map.insert(
+            CacheKey::new("ak".into(), "bucket".into()),
+            make_cache_value("up.qiniup.com", 60),
+        );
+        write_cache_file(cache_dir.path(), &cache_map)?;
+        let cache = read_cache_file(cache_dir.path())?;
+        assert_eq!(cache.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_cache_file_without_lock_file() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let cache_dir = tempdir()?;
+        let cache = HashMap::from([(
+            CacheKey::new("ak".into(), "bucket".into()),
+            make_cache_value("up.qiniup.com", 60),
+        )]);
+        std::fs::write(
+            cache_dir.path().join(CACHE_FILE_NAME),
+            serde_json::to_vec(&cache)?,
+        )?;
+        assert!(!cache_dir.path().join(CACHE_LOCK_FILE_NAME).exists());
+        assert_eq!(read_cache_file(cache_dir.path())?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_cache_merges_entries_by_deadline() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let cache_dir = tempdir()?;
+        let key_1 = CacheKey::new("ak".into(), "bucket-1".into());
+        let key_2 = CacheKey::new("ak".into(), "bucket-2".into());
+
+        let cache_map_1 = DashMap::new();
+        cache_map_1.insert(key_1.to_owned(), make_cache_value("new.qiniup.com", 60));
+        cache_map_1.insert(key_2.to_owned(), make_cache_value("old.qiniup.com", 10));
+        write_cache_file(cache_dir.path(), &cache_map_1)?;
+
+        let cache_map_2 = DashMap::new();
+        cache_map_2.insert(key_1.to_owned(), make_cache_value("old.qiniup.com", 10));
+        cache_map_2.insert(key_2.to_owned(), make_cache_value("new.qiniup.com", 60));
+        write_cache_file(cache_dir.path(), &cache_map_2)?;
+
+        let cache = read_cache_file(cache_dir.path())?;
+        assert_eq!(cache.len(), 2);
+        for key in [key_1, key_2].iter() {
+            assert_eq!(
+                cache.get(key).unwrap().cached_response_body.hosts[0]
+                    .up
+                    .domains[0]
+                    .as_ref(),
+                "new.qiniup.com"
+            );
+            assert_eq!(
+                cache_map_2.get(key).unwrap().cached_response_body.hosts[0]
+                    .up
+                    .domains[0]
+                    .as_ref(),
+                "new.qiniup.com"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_cache_concurrently() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let cache_dir = Arc::new(tempdir()?);
+        let threads = (0..16)
+            .map(|i| {
+                let cache_dir = cache_dir.to_owned();
+                std::thread::spawn(move || -> IOResult<()> {
+                    let cache_map = DashMap::new();
+                    cache_map.insert(
+                        CacheKey::new("ak".into(), format!("bucket-{}", i).into()),
+                        make_cache_value("up.qiniup.com", 60),
+                    );
+                    for _ in 0..10 {
+                        write_cache_file(cache_dir.path(), &cache_map)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+        assert_eq!(read_cache_file(cache_dir.path())?.len(), 16);
+        Ok(())
+    }
+
+    fn make_cache_value(domain: &str, ttl: u64) -> CacheValue {
+        CacheValue {
+            cached_response_body: ResponseBody {
+                hosts: vec![RegionResponseBody {
+                    ttl,
+                    up: DomainsResponseBody {
+                        domains: vec![domain.into()].into_boxed_slice(),
+                    },
+                    rs: None,
+                }],
+            },
+            cache_deadline: SystemTime::now() + Duration::from_secs(ttl),
+        }
+    }
 }
@@ -0,0 +1,196 @@
+use std::{
+    fmt,
+    io::{Read, Result as IOResult},
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc, Mutex,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// 上传带宽限制器
+///
+/// 基于令牌桶算法实现，克隆得到的限制器共享同一个令牌桶，因此可以将同一个限制器设置给多个上传器或上传请求，
+/// 以限制它们的总带宽。限制的速率可以在运行时随时调整
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<RateLimiterInner>,
+}
+
+struct RateLimiterInner {
+    bytes_per_second: AtomicU64,
+    bucket: Mutex<TokenBucket>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// 创建上传带宽限制器，单位为字节每秒，0 表示不限制
+    #[inline]
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            inner: Arc::new(RateLimiterInner {
+                bytes_per_second: AtomicU64::new(bytes_per_second),
+                bucket: Mutex::new(TokenBucket {
+                    tokens: bytes_per_second as f64,
+                    refilled_at: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    /// 获取当前限制的速率，单位为字节每秒，0 表示不限制
+    #[inline]
+    pub fn bytes_per_second(&self) -> u64 {
+        self.inner.bytes_per_second.load(Relaxed)
+    }
+
+    /// 调整限制的速率，单位为字节每秒，0 表示不限制，对正在进行的上传立即生效
+    #[inline]
+    pub fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        self.inner.bytes_per_second.store(bytes_per_second, Relaxed);
+    }
+
+    /// 消耗指定数量的令牌，令牌不足时阻塞当前线程直到令牌补足
+    ///
+    /// 等待期间分段睡眠，每段结束后按当前速率重新计算，因此等待中调整速率也能立即生效
+    pub(super) fn acquire(&self, bytes: u64) {
+        let mut bytes = bytes as f64;
+        loop {
+            let wait = {
+                let mut bucket = self.inner.bucket.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.refilled_at);
+                bucket.refilled_at = now;
+                let rate = self.bytes_per_second();
+                if rate == 0 {
+                    bucket.tokens = 0.0;
+                    return;
+                }
+                // 令牌桶容量为一秒钟的流量，令牌不足时允许透支，透支的部分由当前线程等待偿还
+                bucket.tokens =
+                    (bucket.tokens + elapsed.as_secs_f64() * rate as f64).min(rate as f64);
+                bucket.tokens -= bytes;
+                bytes = 0.0;
+                if bucket.tokens >= 0.0 {
+                    return;
+                }
+                Duration::from_secs_f64(-bucket.tokens / rate as f64).min(MAX_SLEEP_SLICE)
+            };
+            sleep(wait);
+        }
+    }
+}
+
+/// 令牌不足时单次睡眠的最长时间
+const MAX_SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("bytes_per_second", &self.bytes_per_second())
+            .finish()
+    }
+}
+
+/// 受带宽限制的输入流，每次读取后都将从所有限制器中消耗对应数量的令牌
+#[derive(Debug)]
+pub(super) struct RateLimitedReader<R> {
+    inner: R,
+    rate_limiters: Vec<RateLimiter>,
+}
+
+const MAX_CHUNK_SIZE: usize = 1 << 16;
+
+impl<R: Read> RateLimitedReader<R> {
+    #[inline]
+    pub(super) fn new(inner: R, rate_limiters: Vec<RateLimiter>) -> Self {
+        Self {
+            inner,
+            rate_limiters,
+        }
+    }
+}
+
+impl<R: Read> Read for RateLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        if self.rate_limiters.is_empty() {
+            return self.inner.read(buf);
+        }
+        // 限制单次读取的大小，避免一次性透支过多令牌导致流量不平滑
+        let max_len = buf.len().min(MAX_CHUNK_SIZE);
+        let have_read = self.inner.read(&mut buf[..max_len])?;
+        for rate_limiter in self.rate_limiters.iter() {
+            rate_limiter.acquire(have_read as u64);
+        }
+        Ok(have_read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{copy, repeat, sink};
+
+    #[test]
+    fn test_rate_limited_reader() -> IOResult<()> {
+        let rate_limiter = RateLimiter::new(100_000);
+        let begin_at = Instant::now();
+        let mut reader =
+            RateLimitedReader::new(repeat(b'a').take(300_000), vec![rate_limiter.to_owned()]);
+        assert_eq!(copy(&mut reader, &mut sink())?, 300_000);
+        // 令牌桶初始是满的，因此第一秒的流量不需要等待
+        let elapsed = begin_at.elapsed();
+        assert!(elapsed >= Duration::from_millis(1900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+
+        rate_limiter.set_bytes_per_second(0);
+        let begin_at = Instant::now();
+        let mut reader = RateLimitedReader::new(repeat(b'a').take(1 << 24), vec![rate_limiter]);
+        assert_eq!(copy(&mut reader, &mut sink())?, 1 << 24);
+        assert!(begin_at.elapsed() < Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_bytes_per_second_while_waiting() {
+        let rate_limiter = RateLimiter::new(1000);
+        let begin_at = Instant::now();
+        let thread = {
+            let rate_limiter = rate_limiter.to_owned();
+            std::thread::spawn(move || rate_limiter.acquire(3000))
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        rate_limiter.set_bytes_per_second(1_000_000);
+        thread.join().unwrap();
+        let elapsed = begin_at.elapsed();
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_shared_rate_limiter() -> IOResult<()> {
+        let rate_limiter = RateLimiter::new(100_000);
+        let begin_at = Instant::now();
+        let threads = (0..2)
+            .map(|_| {
+                let rate_limiter = rate_limiter.to_owned();
+                std::thread::spawn(move || {
+                    let mut reader =
+                        RateLimitedReader::new(repeat(b'a').take(100_000), vec![rate_limiter]);
+                    copy(&mut reader, &mut sink())
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap()?, 100_000);
+        }
+        let elapsed = begin_at.elapsed();
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+        Ok(())
+    }
+}
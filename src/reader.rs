@@ -1,22 +1,31 @@
+use super::{
+    buffer_pool::{BufferPool, BufferedPart, Md5Digest},
+    cancellation::{CancellableReader, CancellationToken},
+    etag::etag_of_reader,
+    http::HttpRequestBody,
+    rate_limiter::{RateLimitedReader, RateLimiter},
+};
 use crc32fast::Hasher as Crc32;
-use digest::{generic_array::GenericArray, Digest};
+use digest::Digest;
 use md5::Md5;
+use once_cell::sync::OnceCell;
 use positioned_io::{Cursor, ReadAt, Size};
-use reqwest::blocking::Body;
 use std::{
+    collections::VecDeque,
     fmt::Debug,
     fs::File,
     io::{copy, Cursor as IOCursor, Read, Result as IOResult},
     sync::{Arc, RwLock},
 };
 
-trait ThreadSafeReadDebug: Read + Sync + Send + Debug {}
+pub(super) trait ThreadSafeReadDebug: Read + Sync + Send + Debug {}
 impl<T: Read + Sync + Send + Debug> ThreadSafeReadDebug for T {}
 
 #[derive(Debug, Clone)]
 enum PartibleReader {
     File(Arc<RwLock<File>>),
     Data(Arc<Vec<u8>>),
+    Buffered(BufferedPart),
 }
 
 impl PartibleReader {
@@ -25,6 +34,7 @@ impl PartibleReader {
         match self {
             Self::File(file) => Ok(file.read().unwrap().metadata()?.len()),
             Self::Data(data) => Ok(data.len() as u64),
+            Self::Buffered(part) => Ok(part.len()),
         }
     }
 }
@@ -35,6 +45,7 @@ impl ReadAt for PartibleReader {
         match self {
             Self::File(file) => file.read().unwrap().read_at(pos, buf),
             Self::Data(data) => data.read_at(pos, buf),
+            Self::Buffered(part) => part.read_at(pos, buf),
         }
     }
 }
@@ -56,6 +67,9 @@ impl From<Arc<Vec<u8>>> for PartibleReader {
 #[derive(Debug, Clone)]
 pub(super) struct PartReader {
     inner: PartReaderInner,
+    md5: OnceCell<(u64, Md5Digest)>,
+    rate_limiters: Vec<RateLimiter>,
+    cancellation_token: Option<CancellationToken>,
 }
 
 #[derive(Debug, Clone)]
@@ -87,26 +101,84 @@ impl PartReader {
                 start_from,
                 len,
             },
+            md5: OnceCell::new(),
+            rate_limiters: Default::default(),
+            cancellation_token: None,
         }
     }
 
+    /// 缓冲池中的分片，其 MD5 已经在缓存时计算完毕
+    #[inline]
+    fn buffered(part: BufferedPart) -> Self {
+        let len = part.len();
+        let md5 = part.md5();
+        let reader = Self::read_at_based(PartibleReader::Buffered(part), 0, len);
+        reader.md5.set((len, md5)).ok();
+        reader
+    }
+
     #[inline]
     pub(super) fn data(data: Arc<Vec<u8>>) -> Self {
         Self {
             inner: PartReaderInner::Data(data),
+            md5: OnceCell::new(),
+            rate_limiters: Default::default(),
+            cancellation_token: None,
         }
     }
 
     #[inline]
-    pub(super) fn body(&self, size: u64) -> Body {
-        Body::sized(self.reader(), size)
+    pub(super) fn rate_limited(mut self, rate_limiters: Vec<RateLimiter>) -> Self {
+        self.rate_limiters = rate_limiters;
+        self
+    }
+
+    #[inline]
+    pub(super) fn cancellable(mut self, cancellation_token: Option<CancellationToken>) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// 分片的实际长度，最后一个分片可能短于分片大小
+    pub(super) fn len(&self) -> IOResult<u64> {
+        match &self.inner {
+            PartReaderInner::ReadAt {
+                source,
+                start_from,
+                len,
+            } => Ok(source.len()?.saturating_sub(*start_from).min(*len)),
+            PartReaderInner::Data(data) => Ok(data.len() as u64),
+        }
+    }
+
+    /// 分片上传请求体，受带宽限制，且可被取消
+    #[inline]
+    pub(super) fn body(&self, size: u64) -> HttpRequestBody {
+        HttpRequestBody::reader(
+            CancellableReader::new(
+                RateLimitedReader::new(self.reader(), self.rate_limiters.to_owned()),
+                self.cancellation_token.to_owned(),
+            ),
+            size,
+        )
+    }
+
+    /// 分片的长度及 MD5，仅在首次调用时读取分片计算，重试时直接使用缓存的结果
+    #[inline]
+    pub(super) fn md5(&self) -> IOResult<(u64, Md5Digest)> {
+        self.md5
+            .get_or_try_init(|| {
+                let mut hasher = Md5::new();
+                let size = copy(&mut self.reader(), &mut hasher)?;
+                Ok((size, hasher.finalize()))
+            })
+            .map(|md5| md5.to_owned())
     }
 
+    /// 读取分片计算其七牛 Etag，每次调用都将重新读取分片
     #[inline]
-    pub(super) fn md5(&self) -> IOResult<(u64, GenericArray<u8, <Md5 as Digest>::OutputSize>)> {
-        let mut hasher = Md5::new();
-        let size = copy(&mut self.reader(), &mut hasher)?;
-        Ok((size, hasher.finalize()))
+    pub(super) fn etag(&self) -> IOResult<String> {
+        etag_of_reader(self.reader())
     }
 
     #[inline]
@@ -157,16 +229,28 @@ impl AsRef<[u8]> for BytesAsRefAdapter {
 #[derive(Debug, Clone)]
 pub(super) struct FormUploadSource {
     inner: FormUploadSourceInner,
+    crc32: OnceCell<(u64, u32)>,
+    rate_limiters: Vec<RateLimiter>,
+    cancellation_token: Option<CancellationToken>,
 }
 
 #[derive(Debug, Clone)]
 enum FormUploadSourceInner {
     File(Arc<File>),
     Data(Arc<Vec<u8>>),
+    Buffered(BufferedPart),
 }
 
 impl FormUploadSource {
+    /// 数据的长度及 CRC32，仅在首次调用时读取数据计算，重试时直接使用缓存的结果
+    #[inline]
     pub(super) fn crc32(&self) -> IOResult<(u64, u32)> {
+        self.crc32
+            .get_or_try_init(|| self.compute_crc32())
+            .map(|crc32| crc32.to_owned())
+    }
+
+    fn compute_crc32(&self) -> IOResult<(u64, u32)> {
         let mut hasher = Crc32::new();
         let mut have_read: u64 = 0;
         let mut reader = self.reader();
@@ -186,7 +270,37 @@ impl FormUploadSource {
     }
 
     #[inline]
-    pub(super) fn reader(&self) -> impl Read + Send + 'static {
+    pub(super) fn size(&self) -> IOResult<u64> {
+        match &self.inner {
+            FormUploadSourceInner::File(file) => Ok(file.metadata()?.len()),
+            FormUploadSourceInner::Data(data) => Ok(data.len() as u64),
+            FormUploadSourceInner::Buffered(part) => Ok(part.len()),
+        }
+    }
+
+    #[inline]
+    pub(super) fn rate_limited(mut self, rate_limiters: Vec<RateLimiter>) -> Self {
+        self.rate_limiters = rate_limiters;
+        self
+    }
+
+    #[inline]
+    pub(super) fn cancellable(mut self, cancellation_token: Option<CancellationToken>) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// 表单上传请求体的输入流，受带宽限制，且可被取消
+    #[inline]
+    pub(super) fn body_reader(&self) -> impl Read + Send + 'static {
+        CancellableReader::new(
+            RateLimitedReader::new(self.reader(), self.rate_limiters.to_owned()),
+            self.cancellation_token.to_owned(),
+        )
+    }
+
+    #[inline]
+    fn reader(&self) -> impl Read + Send + 'static {
         match &self.inner {
             FormUploadSourceInner::File(file) => {
                 FormUploadSourceReader::File(Cursor::new(FileReadAtAdapter(file.to_owned())))
@@ -194,6 +308,9 @@ impl FormUploadSource {
             FormUploadSourceInner::Data(data) => {
                 FormUploadSourceReader::Data(IOCursor::new(BytesAsRefAdapter(data.to_owned())))
             }
+            FormUploadSourceInner::Buffered(part) => {
+                FormUploadSourceReader::Buffered(Cursor::new(part.to_owned()))
+            }
         }
     }
 }
@@ -202,6 +319,7 @@ impl FormUploadSource {
 enum FormUploadSourceReader {
     File(Cursor<FileReadAtAdapter>),
     Data(IOCursor<BytesAsRefAdapter>),
+    Buffered(Cursor<BufferedPart>),
 }
 
 impl Read for FormUploadSourceReader {
@@ -210,6 +328,7 @@ impl Read for FormUploadSourceReader {
         match self {
             Self::File(file) => file.read(buf),
             Self::Data(data) => data.read(buf),
+            Self::Buffered(part) => part.read(buf),
         }
     }
 }
@@ -219,6 +338,9 @@ impl From<Arc<File>> for FormUploadSource {
     fn from(file: Arc<File>) -> Self {
         Self {
             inner: FormUploadSourceInner::File(file),
+            crc32: OnceCell::new(),
+            rate_limiters: Default::default(),
+            cancellation_token: None,
         }
     }
 }
@@ -228,9 +350,27 @@ impl From<Arc<Vec<u8>>> for FormUploadSource {
     fn from(data: Arc<Vec<u8>>) -> Self {
         Self {
             inner: FormUploadSourceInner::Data(data),
+            crc32: OnceCell::new(),
+            rate_limiters: Default::default(),
+            cancellation_token: None,
+        }
+    }
+}
+
+impl From<BufferedPart> for FormUploadSource {
+    #[inline]
+    fn from(part: BufferedPart) -> Self {
+        Self {
+            crc32: part.crc32().map_or_else(OnceCell::new, |crc32| {
+                OnceCell::with_value((part.len(), crc32))
+            }),
+            inner: FormUploadSourceInner::Buffered(part),
+            rate_limiters: Default::default(),
+            cancellation_token: None,
         }
     }
 }
+
 #[derive(Debug)]
 pub(super) struct UploadSource {
     inner: UploadSourceInner,
@@ -257,20 +397,52 @@ impl From<Arc<Vec<u8>>> for UploadSource {
 #[derive(Debug)]
 enum UploadSourceInner {
     File(Arc<RwLock<File>>),
-    Reader(Box<dyn ThreadSafeReadDebug>),
+    Reader {
+        prefix: Vec<BufferedPart>,
+        reader: Box<dyn ThreadSafeReadDebug>,
+    },
     Data(Arc<Vec<u8>>),
 }
 
 impl UploadSource {
     #[inline]
     pub(super) fn from_reader(reader: impl Read + Sync + Send + Debug + 'static) -> Self {
+        Self::from_prefixed_reader(Vec::new(), reader)
+    }
+
+    /// 已经预先读取了部分数据的输入流
+    ///
+    /// 预读的数据长度与分片大小一致时将直接作为分片上传，否则将在分片时重新读取，读取完毕后立即释放
+    #[inline]
+    pub(super) fn from_prefixed_reader(
+        prefix: Vec<BufferedPart>,
+        reader: impl Read + Sync + Send + Debug + 'static,
+    ) -> Self {
         Self {
-            inner: UploadSourceInner::Reader(Box::new(reader)),
+            inner: UploadSourceInner::Reader {
+                prefix,
+                reader: Box::new(reader),
+            },
         }
     }
 
+    /// 数据总长度，对于无法获取长度的输入流返回 None
     #[inline]
-    pub(super) fn part(self, part_size: u64) -> IOResult<UploadSourcePartitioner> {
+    pub(super) fn size(&self) -> IOResult<Option<u64>> {
+        match &self.inner {
+            UploadSourceInner::File(file) => file.read().unwrap().size(),
+            UploadSourceInner::Data(data) => Ok(Some(data.len() as u64)),
+            UploadSourceInner::Reader { .. } => Ok(None),
+        }
+    }
+
+    /// 将数据源按分片大小切分，无法随机读取的数据源将通过缓冲池缓存每个分片
+    #[inline]
+    pub(super) fn part(
+        self,
+        part_size: u64,
+        buffer_pool: Arc<BufferPool>,
+    ) -> IOResult<UploadSourcePartitioner> {
         return match self.inner {
             UploadSourceInner::File(file) if file.read().unwrap().size()?.is_some() => {
                 Ok(UploadSourcePartitioner {
@@ -290,12 +462,33 @@ impl UploadSource {
             }),
             UploadSourceInner::File(source) => Ok(UploadSourcePartitioner {
                 inner: UploadSourcePartitionerInner::Impartible {
+                    prefix: Default::default(),
                     source: Box::new(ArcLockedFileAdapter(source)),
+                    buffer_pool,
                 },
                 part_size,
             }),
-            UploadSourceInner::Reader(source) => Ok(UploadSourcePartitioner {
-                inner: UploadSourcePartitionerInner::Impartible { source },
+            UploadSourceInner::Reader { prefix, reader }
+                if prefix.iter().all(|part| part.len() == part_size) =>
+            {
+                Ok(UploadSourcePartitioner {
+                    inner: UploadSourcePartitionerInner::Impartible {
+                        prefix: prefix.into(),
+                        source: reader,
+                        buffer_pool,
+                    },
+                    part_size,
+                })
+            }
+            UploadSourceInner::Reader { prefix, reader } => Ok(UploadSourcePartitioner {
+                inner: UploadSourcePartitionerInner::Impartible {
+                    prefix: Default::default(),
+                    source: Box::new(PrefixedReader {
+                        prefix: prefix.into_iter().map(Cursor::new).collect(),
+                        reader,
+                    }),
+                    buffer_pool,
+                },
                 part_size,
             }),
         };
@@ -303,7 +496,7 @@ impl UploadSource {
         #[derive(Debug)]
         struct ArcLockedFileAdapter(Arc<RwLock<File>>);
 
-        impl<'a> Read for ArcLockedFileAdapter {
+        impl Read for ArcLockedFileAdapter {
             #[inline]
             fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
                 self.0.write().unwrap().read(buf)
@@ -312,6 +505,29 @@ impl UploadSource {
     }
 }
 
+#[derive(Debug)]
+struct PrefixedReader<R> {
+    prefix: VecDeque<Cursor<BufferedPart>>,
+    reader: R,
+}
+
+impl<R: Read> Read for PrefixedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while let Some(prefix) = self.prefix.front_mut() {
+            match prefix.read(buf)? {
+                0 => {
+                    self.prefix.pop_front();
+                }
+                have_read => return Ok(have_read),
+            }
+        }
+        self.reader.read(buf)
+    }
+}
+
 #[derive(Debug)]
 pub(super) struct UploadSourcePartitioner {
     inner: UploadSourcePartitionerInner,
@@ -325,7 +541,9 @@ enum UploadSourcePartitionerInner {
         offset: u64,
     },
     Impartible {
+        prefix: VecDeque<BufferedPart>,
         source: Box<dyn ThreadSafeReadDebug>,
+        buffer_pool: Arc<BufferPool>,
     },
 }
 
@@ -346,15 +564,16 @@ impl UploadSourcePartitioner {
                     Ok(None)
                 }
             }
-            UploadSourcePartitionerInner::Impartible { source } => {
-                let mut part_buf = Vec::new();
-                source.take(self.part_size).read_to_end(&mut part_buf)?;
-                if part_buf.is_empty() {
-                    Ok(None)
-                } else {
-                    Ok(Some(PartReader::data(Arc::new(part_buf))))
-                }
-            }
+            UploadSourcePartitionerInner::Impartible {
+                prefix,
+                source,
+                buffer_pool,
+            } => match prefix.pop_front() {
+                Some(part) => Ok(Some(PartReader::buffered(part))),
+                None => Ok(buffer_pool
+                    .read_part(source, self.part_size, false)?
+                    .map(PartReader::buffered)),
+            },
         }
     }
 }
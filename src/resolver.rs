@@ -0,0 +1,226 @@
+use dashmap::DashMap;
+use log::warn;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{Error as IOError, ErrorKind as IOErrorKind, Result as IOResult},
+    net::{IpAddr, ToSocketAddrs},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 域名解析器
+///
+/// 设置域名解析器后，上传器将以（域名，IP）为单位选择和惩罚服务器，
+/// 一个域名下的部分 IP 访问失败时，该域名的其他 IP 仍然可以继续使用
+pub trait Resolver: Debug + Send + Sync {
+    /// 解析域名，返回该域名对应的 IP 地址列表
+    fn resolve(&self, domain: &str) -> IOResult<Vec<IpAddr>>;
+}
+
+/// 使用操作系统提供的域名解析服务的域名解析器
+#[derive(Debug, Clone, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, domain: &str) -> IOResult<Vec<IpAddr>> {
+        let mut ips = Vec::new();
+        for addr in (domain, 0).to_socket_addrs()? {
+            if !ips.contains(&addr.ip()) {
+                ips.push(addr.ip());
+            }
+        }
+        Ok(ips)
+    }
+}
+
+/// 带缓存的域名解析器
+///
+/// 解析结果将被缓存指定的时长，缓存过期后重新解析失败时，将继续使用过期的解析结果
+#[derive(Debug)]
+pub struct CachedResolver {
+    resolver: Arc<dyn Resolver>,
+    ttl: Duration,
+    cache: DashMap<String, CachedIps>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedIps {
+    ips: Vec<IpAddr>,
+    cached_at: Instant,
+}
+
+impl CachedResolver {
+    /// 创建带缓存的域名解析器
+    #[inline]
+    pub fn new(resolver: Arc<dyn Resolver>, ttl: Duration) -> Self {
+        Self {
+            resolver,
+            ttl,
+            cache: Default::default(),
+        }
+    }
+}
+
+impl Resolver for CachedResolver {
+    fn resolve(&self, domain: &str) -> IOResult<Vec<IpAddr>> {
+        let expired = match self.cache.get(domain) {
+            Some(cached) if cached.cached_at.elapsed() < self.ttl => {
+                return Ok(cached.ips.to_owned());
+            }
+            Some(cached) => Some(cached.ips.to_owned()),
+            None => None,
+        };
+        match self.resolver.resolve(domain) {
+            Ok(ips) if !ips.is_empty() => {
+                self.cache.insert(
+                    domain.to_owned(),
+                    CachedIps {
+                        ips: ips.to_owned(),
+                        cached_at: Instant::now(),
+                    },
+                );
+                Ok(ips)
+            }
+            result => match expired {
+                Some(ips) => {
+                    warn!(
+                        "Failed to resolve domain {}, use the expired result {:?}: {:?}",
+                        domain, ips, result
+                    );
+                    Ok(ips)
+                }
+                None => result,
+            },
+        }
+    }
+}
+
+/// 静态域名解析器
+///
+/// 优先使用指定的域名与 IP 地址的对应关系，其他域名交给后备解析器解析，通常用于测试
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    overrides: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Arc<dyn Resolver>>,
+}
+
+impl StaticResolver {
+    /// 创建静态域名解析器
+    #[inline]
+    pub fn new(overrides: HashMap<String, Vec<IpAddr>>) -> Self {
+        Self {
+            overrides,
+            fallback: None,
+        }
+    }
+
+    /// 设置后备域名解析器
+    #[inline]
+    pub fn fallback(mut self, fallback: Arc<dyn Resolver>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, domain: &str) -> IOResult<Vec<IpAddr>> {
+        if let Some(ips) = self.overrides.get(domain) {
+            Ok(ips.to_owned())
+        } else if let Some(fallback) = &self.fallback {
+            fallback.resolve(domain)
+        } else {
+            Err(IOError::new(
+                IOErrorKind::NotFound,
+                format!("No IP address is specified for domain {}", domain),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering::Relaxed},
+        thread::sleep,
+    };
+
+    #[derive(Debug, Default)]
+    struct CountedResolver {
+        counter: AtomicUsize,
+    }
+
+    impl Resolver for CountedResolver {
+        fn resolve(&self, _domain: &str) -> IOResult<Vec<IpAddr>> {
+            let count = self.counter.fetch_add(1, Relaxed);
+            if count < 2 {
+                Ok(vec![IpAddr::from([10, 0, 0, count as u8 + 1])])
+            } else {
+                Err(IOError::other("resolver is down"))
+            }
+        }
+    }
+
+    #[test]
+    fn test_cached_resolver() -> IOResult<()> {
+        let counted_resolver = Arc::new(CountedResolver::default());
+        let resolver = CachedResolver::new(counted_resolver.to_owned(), Duration::from_millis(200));
+        assert_eq!(
+            resolver.resolve("up.qiniup.com")?,
+            vec![IpAddr::from([10, 0, 0, 1])]
+        );
+        assert_eq!(
+            resolver.resolve("up.qiniup.com")?,
+            vec![IpAddr::from([10, 0, 0, 1])]
+        );
+        assert_eq!(counted_resolver.counter.load(Relaxed), 1);
+
+        sleep(Duration::from_millis(200));
+        assert_eq!(
+            resolver.resolve("up.qiniup.com")?,
+            vec![IpAddr::from([10, 0, 0, 2])]
+        );
+        assert_eq!(counted_resolver.counter.load(Relaxed), 2);
+
+        sleep(Duration::from_millis(200));
+        assert_eq!(
+            resolver.resolve("up.qiniup.com")?,
+            vec![IpAddr::from([10, 0, 0, 2])]
+        );
+        assert_eq!(counted_resolver.counter.load(Relaxed), 3);
+
+        assert!(resolver.resolve("upload.qiniup.com").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_static_resolver() -> IOResult<()> {
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "up.qiniup.com".to_owned(),
+            vec![IpAddr::from([127, 0, 0, 1])],
+        );
+        let resolver = StaticResolver::new(overrides.to_owned());
+        assert_eq!(
+            resolver.resolve("up.qiniup.com")?,
+            vec![IpAddr::from([127, 0, 0, 1])]
+        );
+        assert_eq!(
+            resolver.resolve("upload.qiniup.com").unwrap_err().kind(),
+            IOErrorKind::NotFound
+        );
+
+        let resolver =
+            StaticResolver::new(overrides).fallback(Arc::new(CountedResolver::default()));
+        assert_eq!(
+            resolver.resolve("up.qiniup.com")?,
+            vec![IpAddr::from([127, 0, 0, 1])]
+        );
+        assert_eq!(
+            resolver.resolve("upload.qiniup.com")?,
+            vec![IpAddr::from([10, 0, 0, 1])]
+        );
+        Ok(())
+    }
+}
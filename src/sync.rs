@@ -0,0 +1,421 @@
+use super::{
+    batch::{BatchUploadHook, BatchUploadJob, BatchUploadOutcome},
+    error::HttpCallResult,
+    etag::{etag_of_reader, is_qetag},
+    uploader::Uploader,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{create_dir_all, remove_file, rename, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind as IOErrorKind, Result as IOResult, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+/// 同步清单
+///
+/// 记录已经上传的文件及其对象名称，以对象名称为键，用于在同步上传时跳过未发生变化的文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncManifest {
+    entries: BTreeMap<String, SyncManifestEntry>,
+}
+
+/// 同步清单项
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncManifestEntry {
+    path: PathBuf,
+    size: u64,
+    mtime: SystemTime,
+    qetag: String,
+    object_name: String,
+    uploaded_at: SystemTime,
+}
+
+impl SyncManifest {
+    /// 从文件中读取同步清单，文件不存在时返回空的同步清单
+    pub fn load(path: impl AsRef<Path>) -> IOResult<Self> {
+        match File::open(path.as_ref()) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(err) if err.kind() == IOErrorKind::NotFound => Ok(Default::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// 将同步清单写入文件
+    ///
+    /// 清单首先被写入同一目录下的临时文件，再替换原文件，因此写入过程中被中断不会损坏原有的清单。
+    /// 每次写入都使用独立的临时文件，多个同步任务同时写入同一个清单文件时不会相互覆盖临时文件
+    pub fn save(&self, path: impl AsRef<Path>) -> IOResult<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            create_dir_all(parent)?;
+        }
+        let tmp_path = {
+            let mut tmp_path = path.as_os_str().to_owned();
+            tmp_path.push(format!(
+                ".{}.{:016x}.tmp",
+                process::id(),
+                rand::random::<u64>()
+            ));
+            PathBuf::from(tmp_path)
+        };
+        let result = (|| {
+            let mut writer = BufWriter::new(
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&tmp_path)?,
+            );
+            serde_json::to_writer_pretty(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            rename(&tmp_path, path)
+        })();
+        if result.is_err() {
+            remove_file(&tmp_path).ok();
+        }
+        result
+    }
+
+    /// 根据对象名称获取同步清单项
+    #[inline]
+    pub fn get(&self, object_name: &str) -> Option<&SyncManifestEntry> {
+        self.entries.get(object_name)
+    }
+
+    /// 获取所有同步清单项，按对象名称排序
+    #[inline]
+    pub fn entries(&self) -> impl Iterator<Item = &SyncManifestEntry> {
+        self.entries.values()
+    }
+
+    /// 获取同步清单项的数量
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 判断同步清单是否为空
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    fn insert(&mut self, entry: SyncManifestEntry) {
+        self.entries.insert(entry.object_name.to_owned(), entry);
+    }
+}
+
+impl SyncManifestEntry {
+    /// 获取文件路径
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 获取上传时的文件大小
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 获取上传时的文件修改时间
+    #[inline]
+    pub fn mtime(&self) -> SystemTime {
+        self.mtime
+    }
+
+    /// 获取文件的七牛 Etag
+    #[inline]
+    pub fn qetag(&self) -> &str {
+        &self.qetag
+    }
+
+    /// 获取对象名称
+    #[inline]
+    pub fn object_name(&self) -> &str {
+        &self.object_name
+    }
+
+    /// 获取上传时间
+    #[inline]
+    pub fn uploaded_at(&self) -> SystemTime {
+        self.uploaded_at
+    }
+}
+
+/// 同步上传钩子
+///
+/// 上传前对比同步清单跳过未发生变化的文件，上传成功后更新同步清单。
+/// 同步清单每更新一定数量的文件保存一次，所有任务结束后再保存一次，因此进程意外退出时最多只需重新上传少量文件
+pub(super) struct SyncHook {
+    manifest_path: PathBuf,
+    manifest: Mutex<SyncManifest>,
+    pending: Mutex<HashMap<usize, SyncManifestEntry>>,
+    unsaved: AtomicUsize,
+    remote_checker: Option<Uploader>,
+}
+
+impl SyncHook {
+    #[inline]
+    pub(super) fn load(manifest_path: PathBuf, remote_checker: Option<Uploader>) -> IOResult<Self> {
+        Ok(Self {
+            manifest: Mutex::new(SyncManifest::load(&manifest_path)?),
+            manifest_path,
+            pending: Default::default(),
+            unsaved: Default::default(),
+            remote_checker,
+        })
+    }
+
+    fn record(&self, entry: SyncManifestEntry) {
+        self.manifest.lock().unwrap().insert(entry);
+        if self.unsaved.fetch_add(1, SeqCst) + 1 >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let unsaved = self.unsaved.swap(0, SeqCst);
+        if unsaved > 0 {
+            let manifest = self.manifest.lock().unwrap();
+            if let Err(err) = manifest.save(&self.manifest_path) {
+                warn!(
+                    "failed to save sync manifest to {:?}: {}",
+                    self.manifest_path, err
+                );
+                self.unsaved.fetch_add(unsaved, SeqCst);
+            }
+        }
+    }
+}
+
+/// 同步清单每更新多少个文件保存一次
+const SAVE_INTERVAL: usize = 100;
+
+impl BatchUploadHook for SyncHook {
+    fn before_upload(&self, index: usize, job: &BatchUploadJob) -> HttpCallResult<bool> {
+        let (path, object_name) = match (job.file_path(), job.key()) {
+            (Some(path), Some(object_name)) => (path, object_name),
+            _ => return Ok(true),
+        };
+        let metadata = path.metadata()?;
+        let (size, mtime) = (metadata.len(), metadata.modified()?);
+        let recorded = self.manifest.lock().unwrap().get(object_name).cloned();
+        if let Some(recorded) = &recorded {
+            if recorded.path == path && recorded.size == size && recorded.mtime == mtime {
+                return Ok(false);
+            }
+        }
+
+        let qetag = etag_of_reader(File::open(path)?)?;
+        let mut entry = SyncManifestEntry {
+            path: path.to_owned(),
+            size,
+            mtime,
+            qetag,
+            object_name: object_name.to_owned(),
+            uploaded_at: SystemTime::now(),
+        };
+        if let Some(recorded) = recorded {
+            if recorded.size == size && recorded.qetag == entry.qetag {
+                // 仅修改时间发生变化，内容未变，更新清单后跳过
+                entry.uploaded_at = recorded.uploaded_at;
+                self.record(entry);
+                return Ok(false);
+            }
+        }
+        if let Some(uploader) = &self.remote_checker {
+            if let Some(stat) = uploader.stat(object_name)? {
+                let unchanged = if is_qetag(stat.hash()) {
+                    stat.hash() == entry.qetag
+                } else {
+                    // 无法比较 Etag 的对象，大小相同且上传时间晚于文件修改时间即认为未发生变化
+                    stat.size() == size && stat.put_time() >= mtime
+                };
+                if unchanged {
+                    info!("object {} exists and is unchanged, skip", object_name);
+                    entry.uploaded_at = stat.put_time();
+                    self.record(entry);
+                    return Ok(false);
+                }
+            }
+        }
+        self.pending.lock().unwrap().insert(index, entry);
+        Ok(true)
+    }
+
+    fn after_upload(&self, index: usize, outcome: &BatchUploadOutcome) {
+        let entry = self.pending.lock().unwrap().remove(&index);
+        if let (Some(mut entry), BatchUploadOutcome::Succeeded(_)) = (entry, outcome) {
+            entry.uploaded_at = SystemTime::now();
+            self.record(entry);
+        }
+    }
+
+    #[inline]
+    fn finish(&self) {
+        self.save();
+    }
+}
+
+impl Drop for SyncHook {
+    #[inline]
+    fn drop(&mut self) {
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{fake_uploader, FakeHttpCaller};
+    use reqwest::Method;
+    use std::{
+        fs::write,
+        sync::Arc,
+        thread::spawn,
+        time::{Duration, UNIX_EPOCH},
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sync_upload() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let dir = tempdir()?;
+        let manifest_dir = tempdir()?;
+        let manifest_path = manifest_dir.path().join("manifest.json");
+        write(dir.path().join("a.txt"), b"a")?;
+        write(dir.path().join("b.txt"), b"b")?;
+
+        let http_caller = Arc::new(FakeHttpCaller::with_remote_objects(
+            vec![
+                ("c.txt".to_owned(), etag_of_reader(&b"c"[..])?),
+                ("e.txt".to_owned(), "multipart-v2-etag".to_owned()),
+                ("f.txt".to_owned(), "multipart-v2-etag".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        let uploader = fake_uploader(http_caller.to_owned());
+        let sync = || uploader.upload_dir(dir.path()).manifest(&manifest_path);
+
+        let summary = sync().start()?.wait();
+        assert_eq!((summary.succeeded(), summary.skipped()), (2, 0));
+        assert_eq!(http_caller.count(&Method::POST), 2);
+        let manifest = SyncManifest::load(&manifest_path)?;
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(
+            manifest.get("a.txt").unwrap().qetag(),
+            etag_of_reader(&b"a"[..])?
+        );
+
+        let summary = sync().start()?.wait();
+        assert_eq!((summary.succeeded(), summary.skipped()), (0, 2));
+        assert_eq!(http_caller.count(&Method::POST), 2);
+
+        // a.txt 仅修改时间变化，b.txt 内容变化
+        let mtime = SystemTime::now() + Duration::from_secs(10);
+        OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("a.txt"))?
+            .set_modified(mtime)?;
+        write(dir.path().join("b.txt"), b"bb")?;
+        let summary = sync().start()?.wait();
+        assert_eq!((summary.succeeded(), summary.skipped()), (1, 1));
+        assert_eq!(http_caller.count(&Method::POST), 3);
+        let manifest = SyncManifest::load(&manifest_path)?;
+        assert_eq!(manifest.get("a.txt").unwrap().mtime(), mtime);
+        assert_eq!(manifest.get("b.txt").unwrap().size(), 2);
+
+        write(dir.path().join("c.txt"), b"c")?;
+        write(dir.path().join("d.txt"), b"d")?;
+        // e.txt 和 f.txt 的远端 Etag 无法比较，e.txt 的修改时间早于远端对象的上传时间，f.txt 则晚于
+        write(dir.path().join("e.txt"), b"e")?;
+        OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("e.txt"))?
+            .set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000_000))?;
+        write(dir.path().join("f.txt"), b"f")?;
+        let summary = sync().check_remote(true).start()?.wait();
+        assert_eq!((summary.succeeded(), summary.skipped()), (2, 4));
+        assert_eq!(http_caller.count(&Method::POST), 5);
+        assert_eq!(http_caller.count(&Method::GET), 4);
+        let manifest = SyncManifest::load(&manifest_path)?;
+        assert_eq!(
+            manifest
+                .entries()
+                .map(|e| e.object_name())
+                .collect::<Vec<_>>(),
+            vec!["a.txt", "b.txt", "c.txt", "d.txt", "e.txt", "f.txt"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_hook_saves_periodically() -> anyhow::Result<()> {
+        let manifest_dir = tempdir()?;
+        let manifest_path = manifest_dir.path().join("manifest.json");
+        let hook = SyncHook::load(manifest_path.to_owned(), None)?;
+        let entry = |i: usize| SyncManifestEntry {
+            path: PathBuf::from(format!("{}.txt", i)),
+            size: 1,
+            mtime: UNIX_EPOCH,
+            qetag: Default::default(),
+            object_name: format!("{}.txt", i),
+            uploaded_at: UNIX_EPOCH,
+        };
+        for i in 0..SAVE_INTERVAL - 1 {
+            hook.record(entry(i));
+        }
+        assert!(!manifest_path.exists());
+        hook.record(entry(SAVE_INTERVAL));
+        assert_eq!(SyncManifest::load(&manifest_path)?.len(), SAVE_INTERVAL);
+        hook.record(entry(SAVE_INTERVAL + 1));
+        drop(hook);
+        assert_eq!(SyncManifest::load(&manifest_path)?.len(), SAVE_INTERVAL + 1);
+        Ok(())
+    }
+    #[test]
+    fn test_save_manifest_concurrently() -> anyhow::Result<()> {
+        let manifest_dir = tempdir()?;
+        let manifest_path = manifest_dir.path().join("manifest.json");
+        let mut manifest = SyncManifest::default();
+        for i in 0..200 {
+            manifest.insert(SyncManifestEntry {
+                path: PathBuf::from(format!("{}.txt", i)),
+                size: 1,
+                mtime: UNIX_EPOCH,
+                qetag: Default::default(),
+                object_name: format!("{}.txt", i),
+                uploaded_at: UNIX_EPOCH,
+            });
+        }
+        let manifest = Arc::new(manifest);
+        let threads = (0..8)
+            .map(|_| {
+                let manifest = manifest.to_owned();
+                let manifest_path = manifest_path.to_owned();
+                spawn(move || (0..20).try_for_each(|_| manifest.save(&manifest_path)))
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+        assert_eq!(SyncManifest::load(&manifest_path)?.len(), 200);
+        assert_eq!(manifest_dir.path().read_dir()?.count(), 1);
+        Ok(())
+    }
+}
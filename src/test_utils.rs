@@ -0,0 +1,167 @@
+use super::{
+    base64::urlsafe_decode,
+    error::HttpCallResult,
+    http::{HttpCaller, HttpRequest, HttpResponse},
+    uploader::{Uploader, UploaderBuilder},
+};
+use digest::Digest;
+use md5::Md5;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read},
+    mem::take,
+    sync::{
+        atomic::{AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+};
+
+pub(super) const FAKE_UP_URL: &str = "http://up.fake.com";
+pub(super) const FAKE_RS_URL: &str = "http://rs.fake.com";
+
+/// 模拟七牛服务器的 HTTP 调用器
+///
+/// 记录所有请求，列举分片时返回 `listed_parts` 中设置的分片，UP 服务器的请求路径去掉了 [`FAKE_UP_URL`] 前缀，其他服务器的请求保留完整 URL
+#[derive(Debug, Default)]
+pub(super) struct FakeHttpCaller {
+    pub(super) requests: Mutex<Vec<(Method, String, Vec<u8>)>>,
+    pub(super) listed_parts: Mutex<Vec<serde_json::Value>>,
+    remaining_failures: AtomicUsize,
+    remote_objects: HashMap<String, String>,
+}
+
+impl FakeHttpCaller {
+    /// 前若干次请求返回 500 错误，此后的请求均成功
+    #[inline]
+    pub(super) fn failing_first(failures: usize) -> Self {
+        Self {
+            remaining_failures: AtomicUsize::new(failures),
+            ..Default::default()
+        }
+    }
+
+    /// 设置存储空间中已经存在的对象及其 Etag，用于响应 stat 请求
+    #[inline]
+    pub(super) fn with_remote_objects(remote_objects: HashMap<String, String>) -> Self {
+        Self {
+            remote_objects,
+            ..Default::default()
+        }
+    }
+
+    /// 获取指定方法的请求次数
+    #[inline]
+    pub(super) fn count(&self, method: &Method) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _, _)| m == method)
+            .count()
+    }
+
+    /// 获取所有请求的次数
+    #[inline]
+    pub(super) fn called(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    fn stat(&self, url: &str) -> HttpResponse {
+        let entry = url.rsplit('/').next().unwrap();
+        let entry = String::from_utf8(urlsafe_decode(entry.as_bytes()).unwrap()).unwrap();
+        let object_name = entry.split_once(':').unwrap().1;
+        match self.remote_objects.get(object_name) {
+            Some(hash) => json_response(
+                StatusCode::OK,
+                json!({"fsize": 1, "hash": hash, "mimeType": "text/plain", "putTime": 16_000_000_000_000_000u64}),
+            ),
+            None => json_response(
+                StatusCode::from_u16(612).unwrap(),
+                json!({"error": "no such file or directory"}),
+            ),
+        }
+    }
+}
+
+impl HttpCaller for FakeHttpCaller {
+    fn call(&self, mut request: HttpRequest) -> HttpCallResult<HttpResponse> {
+        let mut body = Vec::new();
+        take(request.body_mut())
+            .into_reader()
+            .read_to_end(&mut body)?;
+        let url = request.url().to_owned();
+        let method = request.method().to_owned();
+        let path = url
+            .strip_prefix(FAKE_UP_URL)
+            .map_or(url.as_str(), |path| path.trim_start_matches('/'));
+        let failed = self
+            .remaining_failures
+            .fetch_update(SeqCst, SeqCst, |failures| failures.checked_sub(1))
+            .is_ok();
+        let response = if failed {
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({"error": "fake error"}),
+            )
+        } else if url.starts_with(FAKE_RS_URL) {
+            self.stat(&url)
+        } else {
+            json_response(
+                StatusCode::OK,
+                match (&method, path) {
+                    (&Method::POST, "") => json!({"key": "form-object", "hash": "fake-hash"}),
+                    (&Method::POST, path) if path.ends_with("/uploads") => {
+                        json!({"uploadId": "fake-upload-id"})
+                    }
+                    (&Method::GET, path) if path.contains("/uploads/resumed-upload-id?") => {
+                        json!({
+                            "uploadId": "resumed-upload-id",
+                            "partNumberMarker": 0,
+                            "parts": *self.listed_parts.lock().unwrap(),
+                        })
+                    }
+                    (&Method::PUT, _) => json!({
+                        "etag": format!("etag-{}", body.len()),
+                        "md5": hex::encode(Md5::digest(&body)),
+                    }),
+                    _ => json!({"key": "resumable-object", "hash": "fake-hash"}),
+                },
+            )
+        };
+        let path = path.to_owned();
+        self.requests.lock().unwrap().push((method, path, body));
+        Ok(response)
+    }
+}
+
+fn json_response(status_code: StatusCode, body: serde_json::Value) -> HttpResponse {
+    HttpResponse::new(
+        status_code,
+        Default::default(),
+        Cursor::new(body.to_string().into_bytes()),
+    )
+}
+
+/// 创建使用模拟服务器的上传器构建器
+pub(super) fn fake_uploader_builder(http_caller: Arc<FakeHttpCaller>) -> UploaderBuilder {
+    UploaderBuilder::new("test-ak", "test-sk", "test-bucket")
+        .up_urls(vec![FAKE_UP_URL.to_owned()])
+        .rs_urls(vec![FAKE_RS_URL.to_owned()])
+        .http_caller(http_caller)
+}
+
+/// 创建使用模拟服务器的上传器
+#[inline]
+pub(super) fn fake_uploader(http_caller: Arc<FakeHttpCaller>) -> Uploader {
+    fake_uploader_builder(http_caller).build()
+}
+
+/// 获取请求的方法列表
+pub(super) fn methods_of(requests: &[(Method, String, Vec<u8>)]) -> Vec<Method> {
+    requests
+        .iter()
+        .map(|(method, _, _)| method.to_owned())
+        .collect()
+}
@@ -1,35 +1,110 @@
 use crate::{
     base64::urlsafe_encode,
-    config::HTTP_CLIENT,
+    cancellation::{CancellationToken, PauseHandle},
+    credential::CredentialProvider,
     error::{json_decode_response, HttpCallError, HttpCallResult},
-    host_selector::{HostInfo, HostSelector},
+    host_selector::HostSelector,
+    http::{HttpCaller, HttpRequest, HttpResponse, MultipartForm},
+    progress::{ProgressReader, ProgressReporter, UploadPhase},
     reader::{FormUploadSource, PartReader},
     upload_token::UploadTokenProvider,
 };
 use log::{debug, error, info, warn};
+use mime::Mime;
 use reqwest::{
-    blocking::{
-        multipart::{Form, Part},
-        RequestBuilder as HTTPRequestBuilder,
-    },
-    header::{HeaderName, HeaderValue, AUTHORIZATION},
+    header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
     Method, StatusCode,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JSONValue;
-use std::{borrow::Cow, collections::HashMap, thread::sleep, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{Error as IOError, ErrorKind as IOErrorKind},
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 use tap::prelude::*;
 
 #[derive(Debug)]
 pub(super) struct UploadApiCaller {
     up_selector: HostSelector,
     tries: usize,
+    http_caller: Arc<dyn HttpCaller>,
 }
 
 impl UploadApiCaller {
     #[inline]
-    pub(super) fn new(up_selector: HostSelector, tries: usize) -> Self {
-        Self { up_selector, tries }
+    pub(super) fn new(
+        up_selector: HostSelector,
+        tries: usize,
+        http_caller: Arc<dyn HttpCaller>,
+    ) -> Self {
+        Self {
+            up_selector,
+            tries,
+            http_caller,
+        }
+    }
+}
+
+/// 单次上传的 API 调用选项，未设置的选项使用上传器的默认值
+#[derive(Debug, Clone, Default)]
+pub(super) struct UploadApiOptions {
+    pub(super) up_selector: Option<HostSelector>,
+    pub(super) tries: Option<usize>,
+    pub(super) base_timeout: Option<Duration>,
+    pub(super) progress: Option<ProgressReporter>,
+    pub(super) cancellation_token: Option<CancellationToken>,
+    pub(super) pause_handle: Option<PauseHandle>,
+    pub(super) deadline: Option<Instant>,
+}
+
+impl UploadApiOptions {
+    #[inline]
+    fn report_retry(&self, tries: usize, phase: UploadPhase) -> HttpCallResult<()> {
+        if let (Some(progress), true) = (&self.progress, tries > 0) {
+            progress.retried(phase)?;
+        }
+        Ok(())
+    }
+
+    /// 发送请求前，等待暂停的上传恢复，并检查上传是否已被取消
+    #[inline]
+    fn wait_until_ready(&self) -> HttpCallResult<()> {
+        if let Some(pause_handle) = &self.pause_handle {
+            pause_handle.wait_until_resumed(self.cancellation_token.as_ref())?;
+        }
+        if let Some(cancellation_token) = &self.cancellation_token {
+            cancellation_token.check()?;
+        }
+        Ok(())
+    }
+
+    /// 获取距离总时限的剩余时间，已经超出总时限则返回错误
+    #[inline]
+    fn remaining_time(&self) -> HttpCallResult<Option<Duration>> {
+        match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_secs(0) => Ok(Some(remaining)),
+                _ => Err(HttpCallError::DeadlineExceeded),
+            },
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn is_deadline_exceeded(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+    }
+
+    #[inline]
+    fn is_cancelled(&self) -> bool {
+        self.cancellation_token
+            .as_ref()
+            .is_some_and(|cancellation_token| cancellation_token.is_cancelled())
     }
 }
 
@@ -321,60 +396,174 @@ impl CompletePartsResponse {
     }
 }
 
+#[derive(Debug, Clone)]
+pub(super) struct AbortPartsRequest<'a> {
+    upload_token_provider: &'a dyn UploadTokenProvider,
+    bucket_name: &'a str,
+    object_name: Option<&'a str>,
+    upload_id: &'a str,
+}
+
+impl<'a> AbortPartsRequest<'a> {
+    #[inline]
+    pub(super) fn new(
+        upload_token_provider: &'a dyn UploadTokenProvider,
+        bucket_name: &'a str,
+        object_name: Option<&'a str>,
+        upload_id: &'a str,
+    ) -> Self {
+        Self {
+            upload_token_provider,
+            bucket_name,
+            object_name,
+            upload_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct ListPartsRequest<'a> {
+    upload_token_provider: &'a dyn UploadTokenProvider,
+    bucket_name: &'a str,
+    object_name: Option<&'a str>,
+    upload_id: &'a str,
+}
+
+impl<'a> ListPartsRequest<'a> {
+    #[inline]
+    pub(super) fn new(
+        upload_token_provider: &'a dyn UploadTokenProvider,
+        bucket_name: &'a str,
+        object_name: Option<&'a str>,
+        upload_id: &'a str,
+    ) -> Self {
+        Self {
+            upload_token_provider,
+            bucket_name,
+            object_name,
+            upload_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ListPartsResponseBody {
+    #[serde(rename = "partNumberMarker", default)]
+    part_number_marker: u32,
+    #[serde(default)]
+    parts: Vec<ListedPartInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct ListedPartInfo {
+    pub(super) size: u64,
+    pub(super) etag: String,
+    #[serde(default)]
+    pub(super) md5: Option<String>,
+    #[serde(rename = "partNumber")]
+    pub(super) part_number: u32,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct StatRequest<'a> {
+    credential: &'a dyn CredentialProvider,
+    bucket_name: &'a str,
+    object_name: &'a str,
+}
+
+impl<'a> StatRequest<'a> {
+    #[inline]
+    pub(super) fn new(
+        credential: &'a dyn CredentialProvider,
+        bucket_name: &'a str,
+        object_name: &'a str,
+    ) -> Self {
+        Self {
+            credential,
+            bucket_name,
+            object_name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct StatResponseBody {
+    #[serde(rename = "fsize")]
+    pub(super) size: u64,
+    pub(super) hash: String,
+    #[serde(rename = "mimeType", default)]
+    pub(super) mime_type: String,
+    #[serde(rename = "putTime", default)]
+    pub(super) put_time: u64,
+}
+
 const CONTENT_MD5: &str = "content-md5";
+const APPLICATION_JSON: &str = "application/json";
+/// 单次列举分片的最大数量
+const MAX_LISTED_PARTS: u32 = 1000;
+/// 对象不存在时服务器返回的状态码
+const NO_SUCH_ENTRY: u16 = 612;
 
 impl UploadApiCaller {
     pub(super) fn form_upload(
         &self,
         request: &FormUploadRequest,
+        options: &UploadApiOptions,
     ) -> HttpCallResult<FormUploadResponse> {
         self.with_retries(
+            options,
             &Method::POST,
             "",
             None,
-            |tries, request_builder, url, chosen_info| {
+            |tries, http_request, url| {
                 debug!("[{}] form_upload url: {}", tries, url);
+                options.report_retry(tries, UploadPhase::FormUploading)?;
                 let form_data = {
-                    let mut form_data = Form::new().text(
+                    let mut form_data = MultipartForm::new().text(
                         "token",
                         request.upload_token_provider.to_string()?.into_owned(),
                     );
                     if let Some(object_name) = request.object_name {
-                        form_data = form_data.text("key", object_name.to_owned());
+                        form_data = form_data.text("key", object_name);
                     }
 
+                    if let Some(progress) = &options.progress {
+                        progress.report(UploadPhase::Hashing)?;
+                    }
                     let (file_size, crc32) = request.upload_source.crc32()?;
                     form_data = form_data.text("crc32", crc32.to_string());
-                    let mut file_part =
-                        Part::reader_with_length(request.upload_source.reader(), file_size);
-                    if let Some(file_name) = request.file_name {
-                        file_part = file_part.file_name(file_name.to_owned());
-                    }
-                    if let Some(mime_str) = request.mime_type {
-                        file_part = file_part.mime_str(mime_str)?;
+                    if let Some(progress) = &options.progress {
+                        progress.report(UploadPhase::FormUploading)?;
                     }
-                    form_data = form_data.part("file", file_part);
+                    let mime_type = request.mime_type.map(str::parse::<Mime>).transpose()?;
+                    form_data = form_data.part(
+                        "file",
+                        request.file_name,
+                        mime_type.as_ref().map(|mime_type| mime_type.as_ref()),
+                        ProgressReader::new(
+                            request.upload_source.body_reader(),
+                            options.progress.to_owned(),
+                        ),
+                        file_size,
+                    );
                     if let Some(metadata) = &request.metadata {
                         for (meta_key, meta_value) in metadata.iter() {
                             form_data = form_data
-                                .text(format!("x-qn-meta-{}", meta_key), meta_value.to_owned());
+                                .text(&format!("x-qn-meta-{}", meta_key), meta_value.to_owned());
                         }
                     }
                     if let Some(custom_vars) = &request.custom_vars {
                         for (var_name, var_value) in custom_vars.iter() {
                             form_data =
-                                form_data.text(format!("x:{}", var_name), var_value.to_owned());
+                                form_data.text(&format!("x:{}", var_name), var_value.to_owned());
                         }
                     }
                     form_data
                 };
-                let response_body = request_builder
-                    .multipart(form_data)
-                    .send()
-                    .map_err(HttpCallError::ReqwestError)
-                    .tap_err(|err| self.increase_timeout_power_if_needed(chosen_info, err))
+                let response_body = self
+                    .send(form_data.into_request(http_request))
                     .and_then(|resp| {
-                        if resp.status() == StatusCode::OK {
+                        if resp.status_code() == StatusCode::OK {
                             json_decode_response(resp)
                         } else {
                             Err(resp.into())
@@ -401,8 +590,10 @@ impl UploadApiCaller {
     pub(super) fn init_parts(
         &self,
         request: &InitPartsRequest,
+        options: &UploadApiOptions,
     ) -> HttpCallResult<InitPartsResponse> {
         self.with_retries(
+            options,
             &Method::POST,
             &format!(
                 "buckets/{}/objects/{}/uploads",
@@ -410,14 +601,13 @@ impl UploadApiCaller {
                 encode_object_name(request.object_name)
             ),
             Some(request.upload_token_provider),
-            |tries, request_builder, url, chosen_info| {
+            |tries, http_request, url| {
                 debug!("[{}] init_parts url: {}", tries, url);
-                let response_body = request_builder
-                    .send()
-                    .map_err(HttpCallError::ReqwestError)
-                    .tap_err(|err| self.increase_timeout_power_if_needed(chosen_info, err))
+                options.report_retry(tries, UploadPhase::InitParts)?;
+                let response_body = self
+                    .send(http_request)
                     .and_then(|resp| {
-                        if resp.status() == StatusCode::OK {
+                        if resp.status_code() == StatusCode::OK {
                             json_decode_response(resp)
                         } else {
                             Err(resp.into())
@@ -446,8 +636,10 @@ impl UploadApiCaller {
     pub(super) fn upload_part(
         &self,
         request: &UploadPartRequest,
+        options: &UploadApiOptions,
     ) -> HttpCallResult<UploadPartResponse> {
         self.with_retries(
+            options,
             &Method::PUT,
             &format!(
                 "buckets/{}/objects/{}/uploads/{}/{}",
@@ -457,17 +649,19 @@ impl UploadApiCaller {
                 request.part_number,
             ),
             Some(request.upload_token_provider),
-            |tries, request_builder, url, chosen_info| {
+            |tries, mut http_request, url| {
                 debug!("[{}] upload_part url: {}", tries, url);
+                options.report_retry(tries, UploadPhase::PartRetrying(request.part_number))?;
                 let (part_size, md5) = request.part_reader.md5()?;
-                let response_body = request_builder
-                    .header(HeaderName::from_static(CONTENT_MD5), hex::encode(md5))
-                    .body(request.part_reader.body(part_size))
-                    .send()
-                    .map_err(HttpCallError::ReqwestError)
-                    .tap_err(|err| self.increase_timeout_power_if_needed(chosen_info, err))
+                http_request.headers_mut().insert(
+                    HeaderName::from_static(CONTENT_MD5),
+                    HeaderValue::from_str(&hex::encode(md5)).expect("Invalid Content-MD5"),
+                );
+                *http_request.body_mut() = request.part_reader.body(part_size);
+                let response_body = self
+                    .send(http_request)
                     .and_then(|resp| {
-                        if resp.status() == StatusCode::OK {
+                        if resp.status_code() == StatusCode::OK {
                             json_decode_response(resp)
                         } else {
                             Err(resp.into())
@@ -499,8 +693,10 @@ impl UploadApiCaller {
     pub(super) fn complete_parts(
         &self,
         request: &CompletePartsRequest,
+        options: &UploadApiOptions,
     ) -> HttpCallResult<CompletePartsResponse> {
         self.with_retries(
+            options,
             &Method::POST,
             &format!(
                 "buckets/{}/objects/{}/uploads/{}",
@@ -509,15 +705,19 @@ impl UploadApiCaller {
                 request.upload_id,
             ),
             Some(request.upload_token_provider),
-            |tries, request_builder, url, chosen_info| {
+            |tries, mut http_request, url| {
                 debug!("[{}] complete_parts url: {}", tries, url);
-                let response_body = request_builder
-                    .json(&request.request_body)
-                    .send()
-                    .map_err(HttpCallError::ReqwestError)
-                    .tap_err(|err| self.increase_timeout_power_if_needed(chosen_info, err))
+                options.report_retry(tries, UploadPhase::CompletingParts)?;
+                http_request
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static(APPLICATION_JSON));
+                *http_request.body_mut() = serde_json::to_vec(&request.request_body)
+                    .map_err(IOError::from)?
+                    .into();
+                let response_body = self
+                    .send(http_request)
                     .and_then(|resp| {
-                        if resp.status() == StatusCode::OK {
+                        if resp.status_code() == StatusCode::OK {
                             json_decode_response(resp)
                         } else {
                             Err(resp.into())
@@ -551,38 +751,232 @@ impl UploadApiCaller {
         )
     }
 
+    /// 终止分片上传，已经上传的分片将被服务器清理
+    pub(super) fn abort_parts(
+        &self,
+        request: &AbortPartsRequest,
+        options: &UploadApiOptions,
+    ) -> HttpCallResult<()> {
+        self.with_retries(
+            options,
+            &Method::DELETE,
+            &format!(
+                "buckets/{}/objects/{}/uploads/{}",
+                request.bucket_name,
+                encode_object_name(request.object_name),
+                request.upload_id,
+            ),
+            Some(request.upload_token_provider),
+            |tries, http_request, url| {
+                debug!("[{}] abort_parts url: {}", tries, url);
+                self.send(http_request)
+                    .and_then(|resp| {
+                        if resp.status_code() == StatusCode::OK {
+                            Ok(())
+                        } else {
+                            Err(resp.into())
+                        }
+                    })
+                    .tap_ok(|_| {
+                        info!("[{}] abort_parts ok url: {}", tries, url);
+                    })
+                    .tap_err(|err| {
+                        warn!("[{}] abort_parts error url: {}, error: {}", tries, url, err);
+                    })
+            },
+            |err, url| {
+                error!("final failed abort_parts url = {}, error: {:?}", url, err,);
+            },
+        )
+    }
+
+    /// 列出分片上传中已经上传的所有分片
+    pub(super) fn list_parts(
+        &self,
+        request: &ListPartsRequest,
+        options: &UploadApiOptions,
+    ) -> HttpCallResult<Vec<ListedPartInfo>> {
+        let mut parts = Vec::new();
+        let mut part_number_marker = 0u32;
+        loop {
+            let response_body: ListPartsResponseBody = self.with_retries(
+                options,
+                &Method::GET,
+                &format!(
+                    "buckets/{}/objects/{}/uploads/{}?max-parts={}&part-number-marker={}",
+                    request.bucket_name,
+                    encode_object_name(request.object_name),
+                    request.upload_id,
+                    MAX_LISTED_PARTS,
+                    part_number_marker,
+                ),
+                Some(request.upload_token_provider),
+                |tries, http_request, url| {
+                    debug!("[{}] list_parts url: {}", tries, url);
+                    self.send(http_request)
+                        .and_then(|resp| {
+                            if resp.status_code() == StatusCode::OK {
+                                json_decode_response(resp)
+                            } else {
+                                Err(resp.into())
+                            }
+                        })
+                        .tap_ok(
+                            |(resp, request_id): &(ListPartsResponseBody, Option<HeaderValue>)| {
+                                info!(
+                                    "[{}] list_parts ok url: {}, parts: {}, request_id: {:?}",
+                                    tries,
+                                    url,
+                                    resp.parts.len(),
+                                    request_id,
+                                );
+                            },
+                        )
+                        .map(|(resp, _)| resp)
+                        .tap_err(|err| {
+                            warn!("[{}] list_parts error url: {}, error: {}", tries, url, err);
+                        })
+                },
+                |err, url| {
+                    error!("final failed list_parts url = {}, error: {:?}", url, err,);
+                },
+            )?;
+            parts.extend(response_body.parts);
+            // 服务器返回的下一次列举的起始位置为 0 表示列举结束
+            if response_body.part_number_marker == 0 {
+                return Ok(parts);
+            }
+            part_number_marker = response_body.part_number_marker;
+        }
+    }
+
+    /// 查询对象元信息，对象不存在时返回 None
+    pub(super) fn stat(
+        &self,
+        request: &StatRequest,
+        options: &UploadApiOptions,
+    ) -> HttpCallResult<Option<StatResponseBody>> {
+        let path = format!(
+            "stat/{}",
+            urlsafe_encode(format!("{}:{}", request.bucket_name, request.object_name).as_bytes())
+        );
+        self.with_retries(
+            options,
+            &Method::GET,
+            &path,
+            None,
+            |tries, mut http_request, url| {
+                debug!("[{}] stat url: {}", tries, url);
+                let authorization = format!(
+                    "QBox {}",
+                    request
+                        .credential
+                        .get()?
+                        .sign(format!("/{}\n", path).as_bytes())
+                );
+                http_request.headers_mut().insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&authorization)
+                        .map_err(|err| IOError::new(IOErrorKind::InvalidInput, err))?,
+                );
+                self.send(http_request)
+                    .and_then(|resp| match resp.status_code().as_u16() {
+                        200 => json_decode_response(resp).map(|(resp, _)| Some(resp)),
+                        NO_SUCH_ENTRY => Ok(None),
+                        _ => Err(resp.into()),
+                    })
+                    .tap_ok(|resp: &Option<StatResponseBody>| {
+                        info!(
+                            "[{}] stat ok url: {}, hash: {:?}",
+                            tries,
+                            url,
+                            resp.as_ref().map(|resp| resp.hash.as_str()),
+                        );
+                    })
+                    .tap_err(|err| {
+                        warn!("[{}] stat error url: {}, error: {}", tries, url, err);
+                    })
+            },
+            |err, url| {
+                error!("final failed stat url = {}, error: {:?}", url, err,);
+            },
+        )
+    }
+
     fn with_retries<T>(
         &self,
+        options: &UploadApiOptions,
         method: &Method,
         path: &str,
         upload_token_provider: Option<&dyn UploadTokenProvider>,
-        mut for_each_url: impl FnMut(usize, HTTPRequestBuilder, &str, &HostInfo) -> HttpCallResult<T>,
+        mut for_each_url: impl FnMut(usize, HttpRequest, &str) -> HttpCallResult<T>,
         final_error: impl FnOnce(&HttpCallError, &str),
     ) -> HttpCallResult<T> {
-        assert!(self.tries > 0);
-
-        for tries in 0..self.tries {
-            sleep_before_retry(tries);
-            let last_try = self.tries - tries <= 1;
-            let chosen_up_info = self.up_selector.select_host();
+        let max_tries = options.tries.unwrap_or(self.tries).max(1);
+
+        for tries in 0..max_tries {
+            sleep_before_retry(tries, options.remaining_time()?);
+            options.wait_until_ready()?;
+            // 单次请求的超时时长不超过距离总时限的剩余时间
+            let remaining_time = options.remaining_time()?;
+            let last_try = max_tries - tries <= 1;
+            let up_selector = options.up_selector.as_ref().unwrap_or(&self.up_selector);
+            let mut chosen_up_info = up_selector
+                .select_host_with(options.base_timeout)
+                .ok_or(HttpCallError::NoAvailableHosts)?;
+            if !self.http_caller.supports_resolved_ip() {
+                // HTTP 调用器不会连接选中的 IP 地址，因此只能以域名为单位惩罚服务器
+                chosen_up_info.ip = None;
+            }
             let url = format!("{}/{}", chosen_up_info.host, path);
-            let mut request_builder = HTTP_CLIENT
-                .read()
-                .unwrap()
-                .request(method.to_owned(), url.to_owned())
-                .timeout(chosen_up_info.timeout);
+            let mut http_request = HttpRequest::new(method.to_owned(), url.to_owned());
+            *http_request.timeout_mut() = Some(
+                remaining_time.map_or(chosen_up_info.timeout, |remaining_time| {
+                    chosen_up_info.timeout.min(remaining_time)
+                }),
+            );
+            *http_request.resolved_ip_mut() = chosen_up_info.ip;
             if let Some(upload_token_provider) = upload_token_provider {
                 let upload_token = upload_token_provider.to_string()?;
-                request_builder =
-                    request_builder.header(AUTHORIZATION, &format!("UpToken {}", upload_token));
+                http_request.headers_mut().insert(
+                    AUTHORIZATION,
+                    HeaderValue::from_str(&format!("UpToken {}", upload_token))
+                        .map_err(|err| IOError::new(IOErrorKind::InvalidInput, err))?,
+                );
             }
-            match for_each_url(tries, request_builder, &url, &chosen_up_info) {
+            let result = for_each_url(tries, http_request, &url).map_err(|err| {
+                options
+                    .progress
+                    .as_ref()
+                    .and_then(|progress| progress.take_aborted())
+                    .unwrap_or(err)
+            });
+            match result {
                 Ok(result) => {
-                    self.up_selector.reward(&chosen_up_info.host);
+                    up_selector.reward_chosen(&chosen_up_info);
                     return Ok(result);
                 }
+                Err(err) if err.is_aborted() => {
+                    // 上传进度回调函数中止上传与域名无关，不重试也不惩罚域名
+                    return Err(err);
+                }
+                Err(_) if options.is_cancelled() => {
+                    // 取消导致的请求失败与域名无关，不惩罚域名
+                    return Err(HttpCallError::Cancelled);
+                }
+                Err(err) if options.is_deadline_exceeded() => {
+                    // 因总时限而导致的请求超时与域名无关，不惩罚域名
+                    final_error(&err, url.as_str());
+                    return Err(HttpCallError::DeadlineExceeded);
+                }
                 Err(err) => {
-                    let punished = self.up_selector.punish(&chosen_up_info.host, &err);
+                    if err.is_timeout() {
+                        up_selector.increase_timeout_power_by(
+                            &chosen_up_info.host,
+                            chosen_up_info.timeout_power,
+                        );
+                    }
+                    let punished = up_selector.punish_chosen(&chosen_up_info, &err);
                     if !punished || last_try {
                         final_error(&err, url.as_str());
                         return Err(err);
@@ -593,21 +987,19 @@ impl UploadApiCaller {
         unreachable!();
 
         #[inline]
-        fn sleep_before_retry(tries: usize) {
+        fn sleep_before_retry(tries: usize, remaining_time: Option<Duration>) {
             if tries >= 3 {
-                sleep(Duration::from_secs(tries as u64));
+                let duration = Duration::from_secs(tries as u64);
+                sleep(
+                    remaining_time.map_or(duration, |remaining_time| duration.min(remaining_time)),
+                );
             }
         }
     }
 
     #[inline]
-    fn increase_timeout_power_if_needed(&self, chosen_info: &HostInfo, err: &HttpCallError) {
-        match err {
-            HttpCallError::ReqwestError(err) if err.is_timeout() => self
-                .up_selector
-                .increase_timeout_power_by(&chosen_info.host, chosen_info.timeout_power),
-            _ => {}
-        }
+    fn send(&self, http_request: HttpRequest) -> HttpCallResult<HttpResponse> {
+        self.http_caller.call(http_request)
     }
 }
 
@@ -625,6 +1017,7 @@ mod tests {
     use crate::{
         credential::StaticCredentialProvider,
         host_selector::HostSelectorBuilder,
+        http::ReqwestHttpCaller,
         upload_token::{BucketUploadTokenProvider, ObjectUploadTokenProvider},
     };
     use crc32fast::Hasher as Crc32;
@@ -723,13 +1116,14 @@ mod tests {
                     .read_to_end(&mut file_data_buf)
                     .unwrap();
                 assert_eq!(FILE_CONTENT.to_vec(), file_data_buf);
-                assert!(form_data.get("token").is_some());
+                assert!(form_data.contains_key("token"));
                 Ok::<_, Rejection>(reply_json(&json!({ "key": "testfile" })))
             });
         starts_with_server!(addr, routes, {
             let caller = UploadApiCaller {
                 up_selector: HostSelectorBuilder::new(vec![format!("http://{}", addr)]).build(),
                 tries: 1,
+                http_caller: Arc::new(ReqwestHttpCaller::default()),
             };
             spawn_blocking::<_, HttpCallResult<_>>(move || {
                 let upload_token_provider = ObjectUploadTokenProvider::new(
@@ -738,15 +1132,18 @@ mod tests {
                     Duration::from_secs(60),
                     Arc::new(get_credential()),
                 );
-                let response = caller.form_upload(&FormUploadRequest::new(
-                    &upload_token_provider,
-                    Some("testfile"),
-                    Some("testfilename"),
-                    Some("text/plain"),
-                    Arc::new(FILE_CONTENT.to_vec()).into(),
-                    None,
-                    None,
-                ))?;
+                let response = caller.form_upload(
+                    &FormUploadRequest::new(
+                        &upload_token_provider,
+                        Some("testfile"),
+                        Some("testfilename"),
+                        Some("text/plain"),
+                        Arc::new(FILE_CONTENT.to_vec()).into(),
+                        None,
+                        None,
+                    ),
+                    &Default::default(),
+                )?;
                 assert_eq!(
                     response.response_body.get("key").and_then(|s| s.as_str()),
                     Some("testfile"),
@@ -779,6 +1176,7 @@ mod tests {
             let caller = UploadApiCaller {
                 up_selector: HostSelectorBuilder::new(vec![format!("http://{}", addr)]).build(),
                 tries: 1,
+                http_caller: Arc::new(ReqwestHttpCaller::default()),
             };
             spawn_blocking::<_, HttpCallResult<_>>(move || {
                 let upload_token_provider = ObjectUploadTokenProvider::new(
@@ -787,11 +1185,10 @@ mod tests {
                     Duration::from_secs(60),
                     Arc::new(get_credential()),
                 );
-                let response = caller.init_parts(&InitPartsRequest::new(
-                    &upload_token_provider,
-                    "test-bucket",
-                    Some("test-key"),
-                ))?;
+                let response = caller.init_parts(
+                    &InitPartsRequest::new(&upload_token_provider, "test-bucket", Some("test-key")),
+                    &Default::default(),
+                )?;
                 assert_eq!(response.response_body.upload_id, "fakeuploadid");
                 Ok(())
             })
@@ -829,6 +1226,7 @@ mod tests {
             let caller = UploadApiCaller {
                 up_selector: HostSelectorBuilder::new(vec![format!("http://{}", addr)]).build(),
                 tries: 3,
+                http_caller: Arc::new(ReqwestHttpCaller::default()),
             };
             {
                 let called_times = called_times.to_owned();
@@ -839,11 +1237,10 @@ mod tests {
                         Arc::new(get_credential()),
                     );
                     let err = caller
-                        .init_parts(&InitPartsRequest::new(
-                            &upload_token_provider,
-                            "test-bucket",
-                            None,
-                        ))
+                        .init_parts(
+                            &InitPartsRequest::new(&upload_token_provider, "test-bucket", None),
+                            &Default::default(),
+                        )
                         .unwrap_err();
                     match err {
                         HttpCallError::StatusCodeError(err) => {
@@ -868,6 +1265,7 @@ mod tests {
                     }))
                     .build(),
                 tries: 3,
+                http_caller: Arc::new(ReqwestHttpCaller::default()),
             };
             spawn_blocking::<_, HttpCallResult<_>>(move || {
                 let upload_token_provider = BucketUploadTokenProvider::new(
@@ -876,11 +1274,10 @@ mod tests {
                     Arc::new(get_credential()),
                 );
                 let err = caller
-                    .init_parts(&InitPartsRequest::new(
-                        &upload_token_provider,
-                        "test-bucket",
-                        None,
-                    ))
+                    .init_parts(
+                        &InitPartsRequest::new(&upload_token_provider, "test-bucket", None),
+                        &Default::default(),
+                    )
                     .unwrap_err();
                 match err {
                     HttpCallError::StatusCodeError(err) => {
@@ -940,6 +1337,7 @@ mod tests {
             let caller = UploadApiCaller {
                 up_selector: HostSelectorBuilder::new(vec![format!("http://{}", addr)]).build(),
                 tries: 1,
+                http_caller: Arc::new(ReqwestHttpCaller::default()),
             };
             spawn_blocking::<_, HttpCallResult<_>>(move || {
                 let upload_token_provider = ObjectUploadTokenProvider::new(
@@ -948,18 +1346,21 @@ mod tests {
                     Duration::from_secs(60),
                     Arc::new(get_credential()),
                 );
-                let response = caller.upload_part(&UploadPartRequest {
-                    upload_token_provider: &upload_token_provider,
-                    bucket_name: "test-bucket",
-                    object_name: Some("test-key"),
-                    upload_id: "fakeuploadid",
-                    part_number: 1,
-                    part_reader: PartReader::partible_data(
-                        Arc::new(PART_CONTENT.to_vec()),
-                        0,
-                        1 << 20,
-                    ),
-                })?;
+                let response = caller.upload_part(
+                    &UploadPartRequest {
+                        upload_token_provider: &upload_token_provider,
+                        bucket_name: "test-bucket",
+                        object_name: Some("test-key"),
+                        upload_id: "fakeuploadid",
+                        part_number: 1,
+                        part_reader: PartReader::partible_data(
+                            Arc::new(PART_CONTENT.to_vec()),
+                            0,
+                            1 << 20,
+                        ),
+                    },
+                    &Default::default(),
+                )?;
                 assert_eq!(response.response_body.etag, "fakeetag_1");
                 assert_eq!(response.response_body.md5, hex::encode(md5));
                 Ok(())
@@ -997,6 +1398,7 @@ mod tests {
             let caller = UploadApiCaller {
                 up_selector: HostSelectorBuilder::new(vec![format!("http://{}", addr)]).build(),
                 tries: 1,
+                http_caller: Arc::new(ReqwestHttpCaller::default()),
             };
             spawn_blocking::<_, HttpCallResult<_>>(move || {
                 let upload_token_provider = ObjectUploadTokenProvider::new(
@@ -1005,32 +1407,35 @@ mod tests {
                     Duration::from_secs(60),
                     Arc::new(get_credential()),
                 );
-                let response = caller.complete_parts(&CompletePartsRequest {
-                    upload_token_provider: &upload_token_provider,
-                    bucket_name: "test-bucket",
-                    object_name: Some("~"),
-                    upload_id: "fakeuploadid",
-                    request_body: CompletePartsRequestBody {
-                        parts: vec![
-                            CompletePartInfo {
-                                etag: "fakeetag_1".to_string(),
-                                part_number: 1,
-                            },
-                            CompletePartInfo {
-                                etag: "fakeetag_2".to_string(),
-                                part_number: 2,
-                            },
-                            CompletePartInfo {
-                                etag: "fakeetag_3".to_string(),
-                                part_number: 3,
-                            },
-                        ],
-                        custom_vars: None,
-                        fname: None,
-                        mime_type: None,
-                        metadata: None,
+                let response = caller.complete_parts(
+                    &CompletePartsRequest {
+                        upload_token_provider: &upload_token_provider,
+                        bucket_name: "test-bucket",
+                        object_name: Some("~"),
+                        upload_id: "fakeuploadid",
+                        request_body: CompletePartsRequestBody {
+                            parts: vec![
+                                CompletePartInfo {
+                                    etag: "fakeetag_1".to_string(),
+                                    part_number: 1,
+                                },
+                                CompletePartInfo {
+                                    etag: "fakeetag_2".to_string(),
+                                    part_number: 2,
+                                },
+                                CompletePartInfo {
+                                    etag: "fakeetag_3".to_string(),
+                                    part_number: 3,
+                                },
+                            ],
+                            custom_vars: None,
+                            fname: None,
+                            mime_type: None,
+                            metadata: None,
+                        },
                     },
-                })?;
+                    &Default::default(),
+                )?;
                 assert_eq!(
                     response.response_body.get("hash").and_then(|s| s.as_str()),
                     Some("fakeetag")
@@ -38,7 +38,7 @@ impl UploadPolicy {
         self.get(SCOPE_KEY)
             .as_ref()
             .and_then(|s| s.as_str())
-            .and_then(|s| s.splitn(2, ':').next())
+            .and_then(|s| s.split(':').next())
     }
 
     #[inline]
@@ -46,7 +46,7 @@ impl UploadPolicy {
         self.get(SCOPE_KEY)
             .as_ref()
             .and_then(|v| v.as_str())
-            .and_then(|s| s.splitn(2, ':').nth(1))
+            .and_then(|s| s.split_once(':').map(|(_, key)| key))
     }
 
     #[inline]
@@ -74,7 +74,7 @@ impl UploadPolicy {
     }
 
     #[inline]
-    pub(super) fn keys(&self) -> JSONMapKeys {
+    pub(super) fn keys(&self) -> JSONMapKeys<'_> {
         self.inner.as_object().unwrap().keys()
     }
 }
@@ -130,7 +130,7 @@ impl UploadPolicyBuilder {
                     .checked_add(lifetime)
                     .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
                     .map(|t| t.as_secs())
-                    .unwrap_or(u64::max_value())
+                    .unwrap_or(u64::MAX)
                     .into(),
             ),
         )
@@ -32,9 +32,9 @@ pub(super) enum ParseError {
 pub(super) type ParseResult<T> = Result<T, ParseError>;
 
 pub(super) trait UploadTokenProvider: Any + fmt::Debug + Sync + Send {
-    fn access_key(&self) -> ParseResult<Cow<str>>;
-    fn policy(&self) -> ParseResult<Cow<UploadPolicy>>;
-    fn to_string(&self) -> IOResult<Cow<str>>;
+    fn access_key(&self) -> ParseResult<Cow<'_, str>>;
+    fn policy(&self) -> ParseResult<Cow<'_, UploadPolicy>>;
+    fn to_string(&self) -> IOResult<Cow<'_, str>>;
     fn as_upload_token_provider(&self) -> &dyn UploadTokenProvider;
     fn as_any(&self) -> &dyn Any;
 }
@@ -66,7 +66,7 @@ impl fmt::Debug for StaticUploadTokenProvider {
 }
 
 impl UploadTokenProvider for StaticUploadTokenProvider {
-    fn access_key(&self) -> ParseResult<Cow<str>> {
+    fn access_key(&self) -> ParseResult<Cow<'_, str>> {
         self.access_key
             .get_or_try_init(|| {
                 self.upload_token
@@ -77,7 +77,7 @@ impl UploadTokenProvider for StaticUploadTokenProvider {
             .map(|access_key| access_key.as_ref().into())
     }
 
-    fn policy(&self) -> ParseResult<Cow<UploadPolicy>> {
+    fn policy(&self) -> ParseResult<Cow<'_, UploadPolicy>> {
         self.policy
             .get_or_try_init(|| {
                 let encoded_policy = self
@@ -93,7 +93,7 @@ impl UploadTokenProvider for StaticUploadTokenProvider {
     }
 
     #[inline]
-    fn to_string(&self) -> IOResult<Cow<str>> {
+    fn to_string(&self) -> IOResult<Cow<'_, str>> {
         Ok(Cow::Borrowed(&self.upload_token))
     }
 
@@ -139,16 +139,16 @@ impl fmt::Debug for FromUploadPolicy {
 
 impl UploadTokenProvider for FromUploadPolicy {
     #[inline]
-    fn access_key(&self) -> ParseResult<Cow<str>> {
+    fn access_key(&self) -> ParseResult<Cow<'_, str>> {
         Ok(self.credential.get()?.into_pair().0)
     }
 
     #[inline]
-    fn policy(&self) -> ParseResult<Cow<UploadPolicy>> {
+    fn policy(&self) -> ParseResult<Cow<'_, UploadPolicy>> {
         Ok(Cow::Borrowed(&self.upload_policy))
     }
 
-    fn to_string(&self) -> IOResult<Cow<str>> {
+    fn to_string(&self) -> IOResult<Cow<'_, str>> {
         let upload_token = self.upload_token.get_or_try_init::<_, IOError>(|| {
             Ok(self
                 .credential
@@ -210,11 +210,11 @@ impl fmt::Debug for BucketUploadTokenProvider {
 
 impl UploadTokenProvider for BucketUploadTokenProvider {
     #[inline]
-    fn access_key(&self) -> ParseResult<Cow<str>> {
+    fn access_key(&self) -> ParseResult<Cow<'_, str>> {
         Ok(self.credential.get()?.into_pair().0)
     }
 
-    fn policy(&self) -> ParseResult<Cow<UploadPolicy>> {
+    fn policy(&self) -> ParseResult<Cow<'_, UploadPolicy>> {
         Ok(UploadPolicyBuilder::new_policy_for_bucket(
             self.bucket.as_ref(),
             self.upload_token_lifetime,
@@ -223,7 +223,7 @@ impl UploadTokenProvider for BucketUploadTokenProvider {
         .into())
     }
 
-    fn to_string(&self) -> IOResult<Cow<str>> {
+    fn to_string(&self) -> IOResult<Cow<'_, str>> {
         let upload_token = self.credential.get()?.sign_with_data(
             UploadPolicyBuilder::new_policy_for_bucket(
                 self.bucket.as_ref(),
@@ -277,11 +277,11 @@ impl fmt::Debug for ObjectUploadTokenProvider {
 
 impl UploadTokenProvider for ObjectUploadTokenProvider {
     #[inline]
-    fn access_key(&self) -> ParseResult<Cow<str>> {
+    fn access_key(&self) -> ParseResult<Cow<'_, str>> {
         Ok(self.credential.get()?.into_pair().0)
     }
 
-    fn policy(&self) -> ParseResult<Cow<UploadPolicy>> {
+    fn policy(&self) -> ParseResult<Cow<'_, UploadPolicy>> {
         Ok(UploadPolicyBuilder::new_policy_for_object(
             self.bucket.as_ref(),
             self.key.as_ref(),
@@ -291,7 +291,7 @@ impl UploadTokenProvider for ObjectUploadTokenProvider {
         .into())
     }
 
-    fn to_string(&self) -> IOResult<Cow<str>> {
+    fn to_string(&self) -> IOResult<Cow<'_, str>> {
         let upload_token = self.credential.get()?.sign_with_data(
             UploadPolicyBuilder::new_policy_for_object(
                 self.bucket.as_ref(),
@@ -0,0 +1,799 @@
+use super::{
+    batch::{BatchUploadJob, BatchUploadOutcome, BatchUploadResults, BatchUploader},
+    cancellation::CancellationToken,
+    directory::{DirWalker, ObjectNameFilter, ObjectNameMapper},
+    error::HttpCallError,
+    uploader::{UploadResult, Uploader},
+};
+use log::{info, warn};
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{create_dir_all, remove_file, rename},
+    io::{Error as IOError, Result as IOResult},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{Builder as ThreadBuilder, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+use walkdir::WalkDir;
+
+/// 监控事件回调函数
+///
+/// 回调函数可能被多个工作线程同时调用
+pub type WatchEventCallback = Box<dyn Fn(&WatchEvent) + Send + Sync + 'static>;
+
+/// 监控事件
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WatchEvent<'a> {
+    /// 文件上传成功
+    Uploaded {
+        /// 文件路径
+        path: &'a Path,
+        /// 对象名称
+        object_name: &'a str,
+        /// 上传结果
+        result: &'a UploadResult,
+    },
+    /// 文件上传失败
+    Failed {
+        /// 文件路径
+        path: &'a Path,
+        /// 对象名称
+        object_name: &'a str,
+        /// 上传错误
+        error: &'a HttpCallError,
+        /// 是否将在稍后重试
+        retrying: bool,
+    },
+}
+
+/// 文件上传成功后的处理方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AfterUploadAction {
+    /// 保留文件
+    Keep,
+    /// 删除文件
+    Delete,
+    /// 将文件移动到指定目录，保留文件相对于监控目录的路径
+    ///
+    /// 该目录不应位于监控目录之内，否则移动后的文件将被忽略
+    MoveTo(PathBuf),
+}
+
+impl Default for AfterUploadAction {
+    #[inline]
+    fn default() -> Self {
+        Self::Keep
+    }
+}
+
+/// 监控目录上传构建器
+///
+/// 监控目录下新建或写入的文件，在文件大小和修改时间保持不变一段时间后将其上传，上传失败的文件将被放入重试队列
+pub struct WatchUploadBuilder {
+    batch: BatchUploader,
+    walker: DirWalker,
+    recursive: bool,
+    stable_duration: Duration,
+    max_retries: usize,
+    retry_interval: Duration,
+    after_upload: AfterUploadAction,
+    upload_existing_files: bool,
+    event_callback: Option<WatchEventCallback>,
+}
+
+impl WatchUploadBuilder {
+    #[inline]
+    pub(super) fn new(uploader: Uploader, root: PathBuf) -> Self {
+        Self {
+            batch: BatchUploader::new(uploader),
+            walker: DirWalker::new(root),
+            recursive: true,
+            stable_duration: Duration::from_secs(5),
+            max_retries: 3,
+            retry_interval: Duration::from_secs(30),
+            after_upload: Default::default(),
+            upload_existing_files: false,
+            event_callback: None,
+        }
+    }
+
+    /// 设置对象名称前缀，将被添加在每个对象名称之前
+    #[inline]
+    pub fn key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.walker.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// 设置对象名称映射函数，默认使用文件的相对路径作为对象名称
+    #[inline]
+    pub fn key_mapper(mut self, key_mapper: ObjectNameMapper) -> Self {
+        self.walker.key_mapper = Some(key_mapper);
+        self
+    }
+
+    /// 追加需要上传的文件的 Glob 模式，匹配文件的相对路径，未设置时上传所有文件
+    #[inline]
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.walker.includes.push(pattern.into());
+        self
+    }
+
+    /// 追加需要排除的文件的 Glob 模式，匹配文件的相对路径，优先于需要上传的文件的模式
+    #[inline]
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.walker.excludes.push(pattern.into());
+        self
+    }
+
+    /// 是否上传隐藏文件，默认为否
+    #[inline]
+    pub fn include_hidden_files(mut self, include_hidden_files: bool) -> Self {
+        self.walker.include_hidden_files = include_hidden_files;
+        self
+    }
+
+    /// 是否监控子目录，默认为是
+    #[inline]
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// 设置文件稳定时长，默认为 5 秒
+    ///
+    /// 文件的大小和修改时间在该时长内保持不变才被视为写入完成并开始上传
+    #[inline]
+    pub fn stable_duration(mut self, stable_duration: Duration) -> Self {
+        self.stable_duration = stable_duration;
+        self
+    }
+
+    /// 设置上传失败后的最大重试次数，默认为 3
+    #[inline]
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// 设置上传失败后的重试间隔，第 N 次重试将等待 N 倍的间隔，默认为 30 秒
+    #[inline]
+    pub fn retry_interval(mut self, retry_interval: Duration) -> Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// 设置文件上传成功后的处理方式，默认保留文件
+    #[inline]
+    pub fn after_upload(mut self, after_upload: AfterUploadAction) -> Self {
+        self.after_upload = after_upload;
+        self
+    }
+
+    /// 是否在开始监控时上传目录下已经存在的文件，默认为否
+    #[inline]
+    pub fn upload_existing_files(mut self, upload_existing_files: bool) -> Self {
+        self.upload_existing_files = upload_existing_files;
+        self
+    }
+
+    /// 设置工作线程数量，默认为 4
+    #[inline]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.batch = self.batch.concurrency(concurrency);
+        self
+    }
+
+    /// 设置取消令牌，令牌被取消后正在上传的文件将上传失败且不再重试
+    #[inline]
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.batch = self.batch.cancellation_token(cancellation_token);
+        self
+    }
+
+    /// 设置监控事件回调函数
+    #[inline]
+    pub fn event_callback(mut self, event_callback: WatchEventCallback) -> Self {
+        self.event_callback = Some(event_callback);
+        self
+    }
+
+    /// 开始监控目录，返回监控句柄
+    ///
+    /// 如果 Glob 模式非法或目录无法被监控则返回错误，监控将持续到句柄被停止或释放
+    pub fn start(mut self) -> IOResult<DirWatcher> {
+        // 监控器汇报的路径均为绝对路径，因此监控目录和移动的目标目录也需要转换为绝对路径
+        self.walker.root = self.walker.root.canonicalize()?;
+        if let AfterUploadAction::MoveTo(target_dir) = &mut self.after_upload {
+            create_dir_all(&target_dir)?;
+            *target_dir = target_dir.canonicalize()?;
+        }
+        let (events_tx, events_rx) = channel();
+        let mut notify_watcher =
+            watcher(events_tx, self.stable_duration.min(Duration::from_secs(1)))
+                .map_err(IOError::other)?;
+        notify_watcher
+            .watch(
+                &self.walker.root,
+                if self.recursive {
+                    RecursiveMode::Recursive
+                } else {
+                    RecursiveMode::NonRecursive
+                },
+            )
+            .map_err(IOError::other)?;
+
+        let existing_files = if self.upload_existing_files {
+            WalkDir::new(&self.walker.root)
+                .max_depth(if self.recursive { usize::MAX } else { 1 })
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.into_path())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if !self.recursive {
+            self.walker.max_depth = Some(1);
+        }
+        let filter = self.walker.into_filter()?;
+
+        let context = Arc::new(WatchContext {
+            filter,
+            stable_duration: self.stable_duration,
+            max_retries: self.max_retries,
+            retry_interval: self.retry_interval,
+            after_upload: self.after_upload,
+            event_callback: self.event_callback,
+            in_flight: Default::default(),
+            retries: Default::default(),
+            stopped: Default::default(),
+        });
+        let (jobs_tx, jobs_rx) = channel();
+        let results = self.batch.start(jobs_rx);
+
+        let scheduler = {
+            let context = context.to_owned();
+            ThreadBuilder::new()
+                .name("qiniu-watch-scheduler".into())
+                .spawn(move || {
+                    // 监控器在调度线程结束时被释放
+                    let _notify_watcher = notify_watcher;
+                    Scheduler::new(context, jobs_tx, existing_files).run(events_rx)
+                })?
+        };
+        let collector = {
+            let context = context.to_owned();
+            ThreadBuilder::new()
+                .name("qiniu-watch-collector".into())
+                .spawn(move || context.collect(results))?
+        };
+        info!("Start watching directory {:?}", context.filter.root());
+        Ok(DirWatcher {
+            context,
+            threads: vec![scheduler, collector],
+        })
+    }
+}
+
+impl fmt::Debug for WatchUploadBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchUploadBuilder")
+            .field("batch", &self.batch)
+            .field("root", &self.walker.root)
+            .field("key_prefix", &self.walker.key_prefix)
+            .field("includes", &self.walker.includes)
+            .field("excludes", &self.walker.excludes)
+            .field("include_hidden_files", &self.walker.include_hidden_files)
+            .field("recursive", &self.recursive)
+            .field("stable_duration", &self.stable_duration)
+            .field("max_retries", &self.max_retries)
+            .field("retry_interval", &self.retry_interval)
+            .field("after_upload", &self.after_upload)
+            .field("upload_existing_files", &self.upload_existing_files)
+            .finish()
+    }
+}
+
+/// 目录监控句柄
+///
+/// 句柄被停止或释放后不再处理新的文件，正在上传的文件将继续上传直到结束，重试队列中的文件将被放弃
+pub struct DirWatcher {
+    context: Arc<WatchContext>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DirWatcher {
+    /// 停止监控，并等待正在上传的文件上传结束
+    #[inline]
+    pub fn stop(mut self) {
+        self.stop_and_join();
+    }
+
+    /// 判断监控是否已经停止
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        self.context.stopped.load(SeqCst)
+    }
+
+    fn stop_and_join(&mut self) {
+        self.context.stopped.store(true, SeqCst);
+        for thread in self.threads.drain(..) {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for DirWatcher {
+    #[inline]
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
+
+impl fmt::Debug for DirWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirWatcher")
+            .field("root", &self.context.filter.root())
+            .field("stopped", &self.is_stopped())
+            .finish()
+    }
+}
+
+struct WatchContext {
+    filter: ObjectNameFilter,
+    stable_duration: Duration,
+    max_retries: usize,
+    retry_interval: Duration,
+    after_upload: AfterUploadAction,
+    event_callback: Option<WatchEventCallback>,
+    in_flight: Mutex<HashMap<usize, InFlightFile>>,
+    retries: Mutex<Vec<InFlightFile>>,
+    stopped: AtomicBool,
+}
+
+#[derive(Debug)]
+struct InFlightFile {
+    path: PathBuf,
+    size: Option<u64>,
+    mtime: Option<SystemTime>,
+    retried: usize,
+}
+
+impl WatchContext {
+    /// 处理批量上传结果，上传成功的文件按照设置处理，上传失败的文件放入重试队列
+    fn collect(&self, results: BatchUploadResults) {
+        for result in results {
+            let file = match self.in_flight.lock().unwrap().remove(&result.index()) {
+                Some(file) => file,
+                None => continue,
+            };
+            let object_name = result.object_name().unwrap_or_default();
+            match result.outcome() {
+                BatchUploadOutcome::Succeeded(upload_result) => {
+                    info!("{:?} is uploaded as {}", file.path, object_name);
+                    self.emit(&WatchEvent::Uploaded {
+                        path: &file.path,
+                        object_name,
+                        result: upload_result,
+                    });
+                    if file_status(&file.path) != (file.size, file.mtime) {
+                        // 文件在上传期间发生了变化，调度器将在其稳定后再次上传，届时再处理文件
+                        info!("{:?} is changed during uploading", file.path);
+                    } else if let Err(err) = self.handle_uploaded(&file.path) {
+                        warn!("failed to handle uploaded file {:?}: {}", file.path, err);
+                    }
+                }
+                BatchUploadOutcome::Failed(err) => {
+                    let retrying = file.retried < self.max_retries
+                        && !matches!(err, HttpCallError::Cancelled)
+                        && !self.stopped.load(SeqCst);
+                    self.emit(&WatchEvent::Failed {
+                        path: &file.path,
+                        object_name,
+                        error: err,
+                        retrying,
+                    });
+                    if retrying {
+                        self.retries.lock().unwrap().push(InFlightFile {
+                            retried: file.retried + 1,
+                            ..file
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_uploaded(&self, path: &Path) -> IOResult<()> {
+        match &self.after_upload {
+            AfterUploadAction::Keep => Ok(()),
+            AfterUploadAction::Delete => remove_file(path),
+            AfterUploadAction::MoveTo(target_dir) => {
+                let relative_path = path.strip_prefix(self.filter.root()).unwrap_or(path);
+                let target_path = target_dir.join(relative_path);
+                if let Some(parent) = target_path.parent() {
+                    create_dir_all(parent)?;
+                }
+                rename(path, target_path)
+            }
+        }
+    }
+
+    #[inline]
+    fn emit(&self, event: &WatchEvent) {
+        if let Some(event_callback) = &self.event_callback {
+            event_callback(event);
+        }
+    }
+
+    #[inline]
+    fn is_ignored(&self, path: &Path) -> bool {
+        match &self.after_upload {
+            AfterUploadAction::MoveTo(target_dir) => path.starts_with(target_dir),
+            _ => false,
+        }
+    }
+}
+
+/// 调度器，记录发生变化的文件，在文件稳定后提交上传任务
+struct Scheduler {
+    context: Arc<WatchContext>,
+    jobs_tx: Sender<BatchUploadJob>,
+    candidates: HashMap<PathBuf, Candidate>,
+    next_index: usize,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    size: Option<u64>,
+    mtime: Option<SystemTime>,
+    changed_at: Instant,
+    not_before: Instant,
+    retried: usize,
+}
+
+impl Scheduler {
+    fn new(
+        context: Arc<WatchContext>,
+        jobs_tx: Sender<BatchUploadJob>,
+        existing_files: Vec<PathBuf>,
+    ) -> Self {
+        let mut scheduler = Self {
+            context,
+            jobs_tx,
+            candidates: Default::default(),
+            next_index: 0,
+        };
+        for path in existing_files {
+            scheduler.touch(path);
+        }
+        scheduler
+    }
+
+    fn run(mut self, events_rx: Receiver<DebouncedEvent>) {
+        let tick = (self.context.stable_duration / 4)
+            .clamp(Duration::from_millis(50), Duration::from_secs(1));
+        while !self.context.stopped.load(SeqCst) {
+            match events_rx.recv_timeout(tick) {
+                Ok(event) => self.handle_event(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    warn!("Directory watcher is disconnected");
+                    break;
+                }
+            }
+            self.schedule_retries();
+            if !self.submit_stable_files() {
+                break;
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: DebouncedEvent) {
+        match event {
+            DebouncedEvent::Create(path) if path.is_dir() => {
+                // 新目录被监控前写入的文件不会产生事件，因此需要遍历新目录
+                for entry in WalkDir::new(path)
+                    .into_iter()
+                    .filter_map(|entry| entry.ok())
+                {
+                    if entry.file_type().is_file() {
+                        self.touch(entry.into_path());
+                    }
+                }
+            }
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::NoticeWrite(path) => self.touch(path),
+            DebouncedEvent::Remove(path) | DebouncedEvent::NoticeRemove(path) => {
+                self.candidates.remove(&path);
+            }
+            DebouncedEvent::Rename(from, to) => {
+                self.candidates.remove(&from);
+                self.touch(to);
+            }
+            DebouncedEvent::Rescan => {
+                warn!("Directory watcher requires rescan, some events may be lost");
+            }
+            DebouncedEvent::Error(err, path) => {
+                warn!(
+                    "Received error from directory watcher: {:?} {:?}",
+                    err, path
+                );
+            }
+        }
+    }
+
+    fn touch(&mut self, path: PathBuf) {
+        if self.context.is_ignored(&path) || self.context.filter.object_name(&path).is_none() {
+            return;
+        }
+        let now = Instant::now();
+        let (size, mtime) = file_status(&path);
+        let candidate = self.candidates.entry(path).or_insert(Candidate {
+            size,
+            mtime,
+            changed_at: now,
+            not_before: now,
+            retried: 0,
+        });
+        candidate.size = size;
+        candidate.mtime = mtime;
+        candidate.changed_at = now;
+    }
+
+    fn schedule_retries(&mut self) {
+        let retries = std::mem::take(&mut *self.context.retries.lock().unwrap());
+        let now = Instant::now();
+        for file in retries {
+            let (size, mtime) = file_status(&file.path);
+            self.candidates.insert(
+                file.path,
+                Candidate {
+                    size,
+                    mtime,
+                    changed_at: now,
+                    not_before: now + self.context.retry_interval * file.retried as u32,
+                    retried: file.retried,
+                },
+            );
+        }
+    }
+
+    /// 提交所有已经稳定的文件，如果批量上传器已经停止则返回 false
+    ///
+    /// 正在上传的文件即使已经稳定也不会被再次提交，而是等待其本次上传结束
+    fn submit_stable_files(&mut self) -> bool {
+        let now = Instant::now();
+        let mut stable_files = Vec::new();
+        let stable_duration = self.context.stable_duration;
+        let uploading_paths = self
+            .context
+            .in_flight
+            .lock()
+            .unwrap()
+            .values()
+            .map(|file| file.path.to_owned())
+            .collect::<HashSet<_>>();
+        self.candidates.retain(|path, candidate| {
+            if now < candidate.not_before
+                || now.duration_since(candidate.changed_at) < stable_duration
+                || uploading_paths.contains(path)
+            {
+                return true;
+            }
+            let (size, mtime) = file_status(path);
+            if size.is_none() {
+                // 文件已被删除，或者不是普通文件
+                return false;
+            }
+            if candidate.size != size || candidate.mtime != mtime {
+                // 文件仍在变化，重新等待稳定
+                candidate.size = size;
+                candidate.mtime = mtime;
+                candidate.changed_at = now;
+                return true;
+            }
+            stable_files.push((path.to_owned(), size, mtime, candidate.retried));
+            false
+        });
+        for (path, size, mtime, retried) in stable_files {
+            let object_name = match self.context.filter.object_name(&path) {
+                Some(object_name) => object_name,
+                None => continue,
+            };
+            let index = self.next_index;
+            self.next_index += 1;
+            self.context.in_flight.lock().unwrap().insert(
+                index,
+                InFlightFile {
+                    path: path.to_owned(),
+                    size,
+                    mtime,
+                    retried,
+                },
+            );
+            if self
+                .jobs_tx
+                .send(BatchUploadJob::path(path).object_name(object_name))
+                .is_err()
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 获取文件的大小和修改时间，如果文件不存在或不是普通文件则返回 None
+fn file_status(path: &Path) -> (Option<u64>, Option<SystemTime>) {
+    match path.metadata() {
+        Ok(metadata) if metadata.is_file() => (Some(metadata.len()), metadata.modified().ok()),
+        _ => (None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::HttpCallResult,
+        http::{HttpCaller, HttpRequest, HttpResponse},
+        test_utils::{fake_uploader_builder, FakeHttpCaller, FAKE_UP_URL},
+        UploaderBuilder,
+    };
+    use std::{
+        fs::{create_dir_all, read, write},
+        mem::take,
+        sync::atomic::AtomicUsize,
+        thread::sleep,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_watch_dir() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let dir = tempdir()?;
+        let done_dir = tempdir()?;
+        write(dir.path().join("existing.log"), b"existing")?;
+        write(dir.path().join("ignored.tmp"), b"ignored")?;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        // 第一次上传请求返回 500 错误，此后的请求均上传成功
+        let uploader = fake_uploader_builder(Arc::new(FakeHttpCaller::failing_first(1)))
+            .up_tries(1)
+            .build();
+        let watcher = uploader
+            .watch_dir(dir.path())
+            .key_prefix("camera/")
+            .exclude("*.tmp")
+            .stable_duration(Duration::from_millis(200))
+            .retry_interval(Duration::from_millis(100))
+            .upload_existing_files(true)
+            .concurrency(1)
+            .after_upload(AfterUploadAction::MoveTo(done_dir.path().to_owned()))
+            .event_callback({
+                let events = events.to_owned();
+                Box::new(move |event| {
+                    events.lock().unwrap().push(match event {
+                        WatchEvent::Uploaded { object_name, .. } => {
+                            format!("uploaded {}", object_name)
+                        }
+                        WatchEvent::Failed {
+                            object_name,
+                            retrying,
+                            ..
+                        } => format!("failed {} {}", object_name, retrying),
+                    });
+                })
+            })
+            .start()?;
+
+        create_dir_all(dir.path().join("a"))?;
+        write(dir.path().join("a/new.log"), b"new")?;
+
+        let begin_at = Instant::now();
+        while events.lock().unwrap().len() < 3 && begin_at.elapsed() < Duration::from_secs(10) {
+            sleep(Duration::from_millis(50));
+        }
+        watcher.stop();
+
+        let mut events = events.lock().unwrap().to_owned();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![
+                "failed camera/existing.log true",
+                "uploaded camera/a/new.log",
+                "uploaded camera/existing.log",
+            ]
+        );
+        assert!(done_dir.path().join("existing.log").exists());
+        assert!(done_dir.path().join("a/new.log").exists());
+        assert!(!dir.path().join("existing.log").exists());
+        assert!(dir.path().join("ignored.tmp").exists());
+        Ok(())
+    }
+
+    /// 每次请求都耗费一段时间的 HTTP 调用器，记录每次请求的起止时间
+    #[derive(Debug, Default)]
+    struct SlowHttpCaller {
+        inner: FakeHttpCaller,
+        started: AtomicUsize,
+        spans: Mutex<Vec<(Instant, Instant)>>,
+    }
+
+    impl HttpCaller for SlowHttpCaller {
+        fn call(&self, request: HttpRequest) -> HttpCallResult<HttpResponse> {
+            let begin_at = Instant::now();
+            self.started.fetch_add(1, SeqCst);
+            let response = self.inner.call(request);
+            sleep(Duration::from_millis(500));
+            self.spans.lock().unwrap().push((begin_at, Instant::now()));
+            response
+        }
+    }
+
+    #[test]
+    fn test_watch_file_changed_during_uploading() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let dir = tempdir()?;
+        let done_dir = tempdir()?;
+        let http_caller = Arc::new(SlowHttpCaller::default());
+        let uploader = UploaderBuilder::new("test-ak", "test-sk", "test-bucket")
+            .up_urls(vec![FAKE_UP_URL.to_owned()])
+            .http_caller(http_caller.to_owned())
+            .build();
+        let watcher = uploader
+            .watch_dir(dir.path())
+            .stable_duration(Duration::from_millis(100))
+            .concurrency(2)
+            .after_upload(AfterUploadAction::MoveTo(done_dir.path().join("done")))
+            .start()?;
+
+        write(dir.path().join("file.log"), b"v1")?;
+        let begin_at = Instant::now();
+        while http_caller.started.load(SeqCst) == 0 && begin_at.elapsed() < Duration::from_secs(10)
+        {
+            sleep(Duration::from_millis(10));
+        }
+        write(dir.path().join("file.log"), b"version 2")?;
+        while http_caller.spans.lock().unwrap().len() < 2
+            && begin_at.elapsed() < Duration::from_secs(10)
+        {
+            sleep(Duration::from_millis(50));
+        }
+        sleep(Duration::from_millis(300));
+        watcher.stop();
+
+        let requests = take(&mut *http_caller.inner.requests.lock().unwrap());
+        let spans = take(&mut *http_caller.spans.lock().unwrap());
+        assert_eq!(requests.len(), 2);
+        assert_eq!(spans.len(), 2);
+        // 文件在上传期间发生变化，第二次上传在第一次上传结束后才开始
+        assert!(spans[1].0 >= spans[0].1);
+        assert!(contains(&requests[0].2, b"v1"));
+        assert!(contains(&requests[1].2, b"version 2"));
+        assert_eq!(read(done_dir.path().join("done/file.log"))?, b"version 2");
+        assert!(!dir.path().join("file.log").exists());
+        return Ok(());
+
+        fn contains(body: &[u8], content: &[u8]) -> bool {
+            body.windows(content.len()).any(|window| window == content)
+        }
+    }
+}
@@ -0,0 +1,571 @@
+use super::{
+    cancellation::CancellationToken,
+    error::{HttpCallError, HttpCallResult},
+    reader::PartReader,
+    upload_apis::{
+        AbortPartsRequest, CompletePartInfo, CompletePartsRequest, CompletePartsRequestBody,
+        InitPartsRequest, UploadApiOptions, UploadPartRequest,
+    },
+    upload_token::ObjectUploadTokenProvider,
+    uploader::{clamp_part_size, UploadResult, Uploader},
+};
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Error as IOError, Result as IOResult, Write},
+    mem::{replace, take},
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Arc,
+    },
+    thread::{Builder as ThreadBuilder, JoinHandle},
+    time::Duration,
+};
+
+/// 流式上传写入器
+///
+/// 写入的数据按分片大小缓存，缓存满后作为一个分片上传，调用 [`UploadWriter::finish`] 上传剩余数据并合并分片。
+/// 写入的数据不足一个分片时将改用表单上传。
+/// 未调用 [`UploadWriter::finish`] 即被丢弃时，已经开始的分片上传将被终止
+pub struct UploadWriter {
+    uploader: Uploader,
+    object_name: String,
+    file_name: Option<String>,
+    mime_type: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+    custom_vars: Option<HashMap<String, String>>,
+    part_size: u64,
+    background: bool,
+    cancellation_token: Option<CancellationToken>,
+    buffer: Vec<u8>,
+    parts_uploader: Option<PartsUploader>,
+    broken: bool,
+    finished: bool,
+}
+
+impl UploadWriter {
+    #[inline]
+    pub(super) fn new(uploader: Uploader, object_name: String) -> Self {
+        Self {
+            part_size: uploader.part_size(),
+            uploader,
+            object_name,
+            file_name: None,
+            mime_type: None,
+            metadata: None,
+            custom_vars: None,
+            background: false,
+            cancellation_token: None,
+            buffer: Default::default(),
+            parts_uploader: None,
+            broken: false,
+            finished: false,
+        }
+    }
+
+    /// 设置原始文件名
+    #[inline]
+    pub fn file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// 设置 MIME 类型
+    #[inline]
+    pub fn mime_type(mut self, mime_type: impl Into<String>) -> Self {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// 设置对象元信息
+    #[inline]
+    pub fn metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// 设置自定义变量
+    #[inline]
+    pub fn custom_vars(mut self, custom_vars: HashMap<String, String>) -> Self {
+        self.custom_vars = Some(custom_vars);
+        self
+    }
+
+    /// 设置分片大小，默认使用上传器的分片大小，应在写入数据前设置
+    ///
+    /// 不在服务器允许的 1 MB 到 1 GB 范围内的分片大小将被调整到最接近的边界
+    #[inline]
+    pub fn part_size(mut self, part_size: u64) -> Self {
+        self.part_size = clamp_part_size(part_size);
+        self
+    }
+
+    /// 是否在后台线程中上传分片，默认为否
+    ///
+    /// 后台上传时，写入操作不会等待分片上传完成，但至多只有一个分片在排队等待上传，
+    /// 因此内存中至多缓存三个分片。分片上传失败的错误将在之后的写入或完成上传时返回
+    #[inline]
+    pub fn background(mut self, background: bool) -> Self {
+        self.background = background;
+        self
+    }
+
+    /// 设置上传取消令牌
+    #[inline]
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// 完成上传
+    ///
+    /// 如果已经开始分片上传，则上传剩余数据并合并分片，否则以表单上传的方式上传所有数据
+    pub fn finish(mut self) -> HttpCallResult<UploadResult> {
+        self.check_broken()?;
+        let buffer = take(&mut self.buffer);
+        let result = match self.parts_uploader.as_mut() {
+            Some(parts_uploader) => {
+                if !buffer.is_empty() {
+                    parts_uploader.upload_part(buffer)?;
+                }
+                parts_uploader.complete(
+                    self.file_name.take(),
+                    self.mime_type.take(),
+                    self.metadata.take(),
+                    self.custom_vars.take(),
+                )?
+            }
+            None => self.form_upload(buffer)?,
+        };
+        self.finished = true;
+        Ok(result)
+    }
+
+    fn form_upload(&mut self, buffer: Vec<u8>) -> HttpCallResult<UploadResult> {
+        let mut builder = self
+            .uploader
+            .upload_bytes(buffer)
+            .object_name(&self.object_name)
+            .force_form();
+        if let Some(file_name) = self.file_name.take() {
+            builder = builder.file_name(file_name);
+        }
+        if let Some(mime_type) = self.mime_type.take() {
+            builder = builder.mime_type(mime_type);
+        }
+        if let Some(metadata) = self.metadata.take() {
+            builder = builder.metadata(metadata);
+        }
+        if let Some(custom_vars) = self.custom_vars.take() {
+            builder = builder.custom_vars(custom_vars);
+        }
+        if let Some(cancellation_token) = self.cancellation_token.to_owned() {
+            builder = builder.cancellation_token(cancellation_token);
+        }
+        builder.start()
+    }
+
+    fn upload_buffer(&mut self) -> HttpCallResult<()> {
+        let buffer = replace(
+            &mut self.buffer,
+            Vec::with_capacity(self.part_size as usize),
+        );
+        let parts_uploader = match &mut self.parts_uploader {
+            Some(parts_uploader) => parts_uploader,
+            None => self.parts_uploader.insert(PartsUploader::init(
+                PartsContext::new(
+                    &self.uploader,
+                    &self.object_name,
+                    self.cancellation_token.to_owned(),
+                ),
+                self.background,
+            )?),
+        };
+        parts_uploader.upload_part(buffer)
+    }
+
+    #[inline]
+    fn check_broken(&self) -> HttpCallResult<()> {
+        if self.broken {
+            Err(IOError::other("upload writer is broken by previous error").into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Write for UploadWriter {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.check_broken().map_err(IOError::other)?;
+        let part_size = self.part_size as usize;
+        let size = buf.len().min(part_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..size]);
+        if self.buffer.len() == part_size {
+            self.upload_buffer().map_err(|err| {
+                self.broken = true;
+                match err {
+                    HttpCallError::LocalIoError(err) => err,
+                    err => IOError::other(err),
+                }
+            })?;
+        }
+        Ok(size)
+    }
+
+    /// 除最后一个分片外，每个分片都必须达到分片大小，因此缓存的数据只能在缓存满或完成上传时上传
+    #[inline]
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+impl Drop for UploadWriter {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some(parts_uploader) = self.parts_uploader.take() {
+            parts_uploader.abort();
+        }
+    }
+}
+
+impl fmt::Debug for UploadWriter {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UploadWriter")
+            .field("object_name", &self.object_name)
+            .field("part_size", &self.part_size)
+            .field("background", &self.background)
+            .field("buffered", &self.buffer.len())
+            .field("parts_uploader", &self.parts_uploader)
+            .field("broken", &self.broken)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct PartsContext {
+    uploader: Uploader,
+    object_name: String,
+    upload_token_provider: ObjectUploadTokenProvider,
+    api_options: UploadApiOptions,
+}
+
+impl PartsContext {
+    fn new(
+        uploader: &Uploader,
+        object_name: &str,
+        cancellation_token: Option<CancellationToken>,
+    ) -> Self {
+        Self {
+            upload_token_provider: ObjectUploadTokenProvider::new(
+                uploader.bucket_name().to_owned(),
+                object_name.to_owned(),
+                Duration::from_secs(600),
+                uploader.credential().to_owned(),
+            ),
+            uploader: uploader.to_owned(),
+            object_name: object_name.to_owned(),
+            // 使用子令牌，写入器被丢弃时可以单独取消后台上传，而不影响调用者的取消令牌
+            api_options: UploadApiOptions {
+                cancellation_token: Some(
+                    cancellation_token.map_or_else(CancellationToken::new, |token| token.child()),
+                ),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn upload_part(
+        &self,
+        upload_id: &str,
+        part_number: u32,
+        data: Vec<u8>,
+    ) -> HttpCallResult<CompletePartInfo> {
+        let part_reader = PartReader::data(Arc::new(data))
+            .rate_limited(self.uploader.rate_limiter().into_iter().cloned().collect())
+            .cancellable(self.api_options.cancellation_token.to_owned());
+        let mut response = self.uploader.api_caller().upload_part(
+            &UploadPartRequest::new(
+                &self.upload_token_provider,
+                self.uploader.bucket_name(),
+                Some(&self.object_name),
+                upload_id,
+                part_number,
+                part_reader,
+            ),
+            &self.api_options,
+        )?;
+        Ok(CompletePartInfo::new(
+            take(response.response_body_mut().etag_mut()),
+            part_number,
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct PartsUploader {
+    context: Arc<PartsContext>,
+    upload_id: String,
+    next_part_number: u32,
+    completed_parts: Vec<CompletePartInfo>,
+    background: Option<BackgroundPartsUploader>,
+}
+
+#[derive(Debug)]
+struct BackgroundPartsUploader {
+    sender: SyncSender<(u32, Vec<u8>)>,
+    handle: JoinHandle<HttpCallResult<Vec<CompletePartInfo>>>,
+}
+
+impl PartsUploader {
+    fn init(context: PartsContext, background: bool) -> HttpCallResult<Self> {
+        let mut init_parts_response = context.uploader.api_caller().init_parts(
+            &InitPartsRequest::new(
+                &context.upload_token_provider,
+                context.uploader.bucket_name(),
+                Some(&context.object_name),
+            ),
+            &context.api_options,
+        )?;
+        let upload_id = take(init_parts_response.response_body_mut().upload_id_mut());
+        let context = Arc::new(context);
+        let background = if background {
+            Some(BackgroundPartsUploader::spawn(
+                context.to_owned(),
+                upload_id.to_owned(),
+            )?)
+        } else {
+            None
+        };
+        Ok(Self {
+            context,
+            upload_id,
+            next_part_number: 1,
+            completed_parts: Default::default(),
+            background,
+        })
+    }
+
+    fn upload_part(&mut self, data: Vec<u8>) -> HttpCallResult<()> {
+        let part_number = self.next_part_number;
+        self.next_part_number += 1;
+        match &self.background {
+            Some(background) => {
+                if background.sender.send((part_number, data)).is_err() {
+                    // 后台线程仅在分片上传失败时提前退出
+                    self.join_background()?;
+                    return Err(IOError::other("background uploader exited").into());
+                }
+            }
+            None => {
+                let completed_part =
+                    self.context
+                        .upload_part(&self.upload_id, part_number, data)?;
+                self.completed_parts.push(completed_part);
+            }
+        }
+        Ok(())
+    }
+
+    fn join_background(&mut self) -> HttpCallResult<()> {
+        if let Some(background) = self.background.take() {
+            drop(background.sender);
+            let completed_parts = background
+                .handle
+                .join()
+                .map_err(|_| IOError::other("background uploader panicked"))??;
+            self.completed_parts.extend(completed_parts);
+        }
+        Ok(())
+    }
+
+    fn complete(
+        &mut self,
+        file_name: Option<String>,
+        mime_type: Option<String>,
+        metadata: Option<HashMap<String, String>>,
+        custom_vars: Option<HashMap<String, String>>,
+    ) -> HttpCallResult<UploadResult> {
+        self.join_background()?;
+        let mut response = self.context.uploader.api_caller().complete_parts(
+            &CompletePartsRequest::new(
+                &self.context.upload_token_provider,
+                self.context.uploader.bucket_name(),
+                Some(&self.context.object_name),
+                &self.upload_id,
+                CompletePartsRequestBody::new(
+                    take(&mut self.completed_parts),
+                    file_name,
+                    mime_type,
+                    metadata,
+                    custom_vars,
+                ),
+            ),
+            &self.context.api_options,
+        )?;
+        Ok(UploadResult::new(take(response.response_body_mut())))
+    }
+
+    fn abort(mut self) {
+        // 先取消正在上传的分片，排队中的分片也将因取消而直接失败，无需等待它们上传完毕
+        if let Some(cancellation_token) = &self.context.api_options.cancellation_token {
+            cancellation_token.cancel();
+        }
+        self.join_background().ok();
+        info!(
+            "abort upload_id {} of object {}",
+            self.upload_id, self.context.object_name
+        );
+        // 即使上传已被取消，也依然需要终止分片上传
+        let api_options = UploadApiOptions {
+            cancellation_token: None,
+            ..self.context.api_options.to_owned()
+        };
+        if let Err(err) = self.context.uploader.api_caller().abort_parts(
+            &AbortPartsRequest::new(
+                &self.context.upload_token_provider,
+                self.context.uploader.bucket_name(),
+                Some(&self.context.object_name),
+                &self.upload_id,
+            ),
+            &api_options,
+        ) {
+            warn!("failed to abort upload_id {}: {}", self.upload_id, err);
+        }
+    }
+}
+
+impl BackgroundPartsUploader {
+    fn spawn(context: Arc<PartsContext>, upload_id: String) -> IOResult<Self> {
+        let (sender, receiver) = sync_channel::<(u32, Vec<u8>)>(1);
+        let handle = ThreadBuilder::new()
+            .name("qiniu-upload-writer".into())
+            .spawn(move || {
+                receiver
+                    .into_iter()
+                    .map(|(part_number, data)| context.upload_part(&upload_id, part_number, data))
+                    .collect()
+            })?;
+        Ok(Self { sender, handle })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{HttpCaller, HttpRequest, HttpResponse},
+        test_utils::{fake_uploader_builder, methods_of, FakeHttpCaller, FAKE_UP_URL},
+        uploader::{UploaderBuilder, MIN_PART_SIZE},
+    };
+    use reqwest::Method;
+    use serde_json::Value as JSONValue;
+    use std::thread::sleep;
+
+    #[derive(Debug, Default)]
+    struct SlowHttpCaller(FakeHttpCaller);
+
+    impl HttpCaller for SlowHttpCaller {
+        fn call(&self, request: HttpRequest) -> HttpCallResult<HttpResponse> {
+            if request.method() == Method::PUT {
+                sleep(Duration::from_millis(300));
+            }
+            self.0.call(request)
+        }
+    }
+
+    #[test]
+    fn test_upload_writer() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let http_caller = Arc::new(FakeHttpCaller::default());
+        let uploader = fake_uploader_builder(http_caller.to_owned())
+            .part_size(MIN_PART_SIZE)
+            .build();
+
+        let mut writer = uploader.writer("form-object");
+        writer.write_all(b"hello")?;
+        let result = writer.finish()?;
+        assert_eq!(result.response_body()["key"], "form-object");
+        {
+            let requests = take(&mut *http_caller.requests.lock().unwrap());
+            assert_eq!(methods_of(&requests), vec![Method::POST]);
+            assert!(String::from_utf8(requests[0].2.to_owned())?.contains("\r\n\r\nhello\r\n"));
+        }
+
+        let content = (0..(MIN_PART_SIZE * 5 / 2))
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        for background in [false, true] {
+            let mut writer = uploader.writer("resumable-object").background(background);
+            for chunk in content.chunks(1000) {
+                writer.write_all(chunk)?;
+            }
+            let result = writer.finish()?;
+            assert_eq!(result.response_body()["key"], "resumable-object");
+
+            let requests = take(&mut *http_caller.requests.lock().unwrap());
+            assert_eq!(
+                methods_of(&requests),
+                vec![
+                    Method::POST,
+                    Method::PUT,
+                    Method::PUT,
+                    Method::PUT,
+                    Method::POST
+                ]
+            );
+            let part_size = MIN_PART_SIZE as usize;
+            assert_eq!(requests[1].2, content[..part_size].to_vec());
+            assert_eq!(requests[2].2, content[part_size..part_size * 2].to_vec());
+            assert_eq!(requests[3].2, content[part_size * 2..].to_vec());
+            assert!(requests[3].1.ends_with("/uploads/fake-upload-id/3"));
+            let complete_body: JSONValue = serde_json::from_slice(&requests[4].2)?;
+            assert_eq!(
+                complete_body["parts"][2]["etag"],
+                format!("etag-{}", part_size / 2)
+            );
+        }
+
+        {
+            let mut writer = uploader.writer("aborted-object");
+            writer.write_all(&content[..(MIN_PART_SIZE * 3 / 2) as usize])?;
+        }
+        let requests = take(&mut *http_caller.requests.lock().unwrap());
+        assert_eq!(
+            methods_of(&requests),
+            vec![Method::POST, Method::PUT, Method::DELETE]
+        );
+        assert!(requests[2].1.ends_with("/uploads/fake-upload-id"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_background_upload_writer() -> anyhow::Result<()> {
+        env_logger::try_init().ok();
+
+        let http_caller = Arc::new(SlowHttpCaller::default());
+        let uploader = UploaderBuilder::new("test-ak", "test-sk", "test-bucket")
+            .up_urls(vec![FAKE_UP_URL.to_owned()])
+            .part_size(MIN_PART_SIZE)
+            .http_caller(http_caller.to_owned())
+            .build();
+        let cancellation_token = CancellationToken::new();
+        {
+            let mut writer = uploader
+                .writer("aborted-object")
+                .background(true)
+                .cancellation_token(cancellation_token.to_owned());
+            writer.write_all(&vec![0u8; (MIN_PART_SIZE * 3) as usize + 1])?;
+        }
+        // 第三个分片仍在排队，写入器丢弃后不应再被上传
+        assert!(http_caller.0.count(&Method::PUT) < 3);
+        assert_eq!(http_caller.0.count(&Method::DELETE), 1);
+        assert!(!cancellation_token.is_cancelled());
+        Ok(())
+    }
+}